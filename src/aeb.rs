@@ -0,0 +1,235 @@
+use avian3d::prelude::*;
+use bevy::prelude::*;
+
+use crate::car_dynamics::{ControlSystems, Ego, EgoControl, EgoState};
+use crate::radar::Radar;
+use crate::run_log::RunLog;
+use crate::scenario::Passable;
+
+pub struct AebPlugin;
+
+impl Plugin for AebPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<AebConfig>()
+            .init_resource::<AebStatus>()
+            .add_message::<AebActivation>()
+            .add_systems(
+                Update,
+                (aeb_system, log_aeb_activation)
+                    .chain()
+                    .in_set(ControlSystems::Assist),
+            );
+    }
+}
+
+// Cast volume matching the ego collider footprint, kept above the ground plane
+const CAST_LENGTH: f32 = 5.0;
+const CAST_WIDTH: f32 = 2.0;
+const CAST_HEIGHT: f32 = 0.6;
+const MAX_RANGE: f32 = 80.0;
+// below this speed the car is considered stopped and AEB releases
+const STANDSTILL_SPEED: f32 = 0.1;
+const MIN_ACTIVATION_SPEED: f32 = 1.0;
+// Radar detections weaker than this are taken for clutter
+const MIN_TARGET_RCS: f32 = -5.0; // dBsm
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum AebStage {
+    #[default]
+    Inactive,
+    Warning,
+    PartialBrake,
+    FullBrake,
+}
+
+#[derive(Resource)]
+pub struct AebConfig {
+    pub enabled: bool,
+    pub warning_ttc: f32,       // s
+    pub partial_brake_ttc: f32, // s
+    pub full_brake_ttc: f32,    // s
+    pub partial_brake: f32,     // brake pedal [0, 1]
+    /// Brake on the ego's radar detections instead of a ground truth shape cast, when
+    /// the radar is enabled.
+    pub use_radar: bool,
+}
+
+impl Default for AebConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            warning_ttc: 2.6,
+            partial_brake_ttc: 1.6,
+            full_brake_ttc: 0.9,
+            partial_brake: 0.5,
+            use_radar: false,
+        }
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct AebStatus {
+    pub stage: AebStage,
+    pub ttc: Option<f32>,      // s
+    pub distance: Option<f32>, // m
+}
+
+#[derive(Message, Copy, Clone, Debug)]
+pub struct AebActivation {
+    pub stage: AebStage,
+    pub ttc: f32,
+    pub distance: f32,
+    pub speed: f32,
+}
+
+impl AebConfig {
+    fn stage_for(&self, ttc: f32) -> AebStage {
+        if ttc < self.full_brake_ttc {
+            AebStage::FullBrake
+        } else if ttc < self.partial_brake_ttc {
+            AebStage::PartialBrake
+        } else if ttc < self.warning_ttc {
+            AebStage::Warning
+        } else {
+            AebStage::Inactive
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn aeb_system(
+    spatial_query: SpatialQuery,
+    car: Single<(Entity, &Transform, &EgoState, &mut EgoControl), With<Ego>>,
+    radar: Query<&Radar, With<Ego>>,
+    velocities: Query<&LinearVelocity>,
+    vehicles: Query<&EgoState, Without<Ego>>,
+    passable: Query<(), Passable>,
+    config: Res<AebConfig>,
+    mut status: ResMut<AebStatus>,
+    mut activations: MessageWriter<AebActivation>,
+) {
+    let (entity, transform, state, mut control) = car.into_inner();
+
+    if !config.enabled {
+        *status = AebStatus::default();
+        return;
+    }
+
+    let (ttc, distance) = match radar
+        .single()
+        .ok()
+        .filter(|radar| config.use_radar && radar.enabled)
+    {
+        Some(radar) => radar_ttc(radar),
+        None => shape_cast_ttc(
+            &spatial_query,
+            entity,
+            transform,
+            state,
+            &velocities,
+            &vehicles,
+            &passable,
+        ),
+    };
+    status.ttc = ttc;
+    status.distance = distance;
+
+    let mut stage = ttc.map_or(AebStage::Inactive, |ttc| config.stage_for(ttc));
+    if state.v < MIN_ACTIVATION_SPEED && stage > AebStage::Warning {
+        stage = AebStage::Warning;
+    }
+    // Braking stays latched until standstill, like production AEB
+    if status.stage >= AebStage::PartialBrake && state.v > STANDSTILL_SPEED {
+        stage = stage.max(status.stage);
+    }
+
+    if stage > status.stage {
+        activations.write(AebActivation {
+            stage,
+            ttc: ttc.unwrap_or(0.0),
+            distance: status.distance.unwrap_or(0.0),
+            speed: state.v,
+        });
+    }
+    status.stage = stage;
+
+    let brake = match stage {
+        AebStage::Inactive | AebStage::Warning => return,
+        AebStage::PartialBrake => config.partial_brake,
+        AebStage::FullBrake => 1.0,
+    };
+    control.throttle = 0.0;
+    control.brake = control.brake.max(brake);
+}
+
+// Nearest obstacle ahead from a cast of the ego footprint, with the time to reach it.
+// Trigger volumes and drivable surfaces are passed through.
+fn shape_cast_ttc(
+    spatial_query: &SpatialQuery,
+    entity: Entity,
+    transform: &Transform,
+    state: &EgoState,
+    velocities: &Query<&LinearVelocity>,
+    vehicles: &Query<&EgoState, Without<Ego>>,
+    passable: &Query<(), Passable>,
+) -> (Option<f32>, Option<f32>) {
+    let heading = Vec3::new(state.yaw.cos(), state.yaw.sin(), 0.0);
+    let shape = Collider::cuboid(CAST_LENGTH, CAST_WIDTH, CAST_HEIGHT);
+    let filter = SpatialQueryFilter::from_excluded_entities([entity]);
+    let hit = spatial_query.cast_shape_predicate(
+        &shape,
+        transform.translation,
+        Quat::from_rotation_z(state.yaw),
+        Dir3::new_unchecked(heading),
+        &ShapeCastConfig::from_max_distance(MAX_RANGE),
+        &filter,
+        &|entity| !passable.contains(entity),
+    );
+
+    // Time to collision assuming both keep their current speed. Vehicles are
+    // moved kinematically, so their speed comes from their state.
+    let ttc = hit.and_then(|hit| {
+        let target_v = match vehicles.get(hit.entity) {
+            Ok(target) => target.v * (target.yaw - state.yaw).cos(),
+            Err(_) => velocities
+                .get(hit.entity)
+                .map(|v| v.dot(heading))
+                .unwrap_or(0.0),
+        };
+        let closing_speed = state.v - target_v;
+        (closing_speed > 0.0).then(|| hit.distance / closing_speed)
+    });
+    (ttc, hit.map(|hit| hit.distance))
+}
+
+// Nearest radar detection in the ego's path, with the time to reach it at the measured
+// range rate. The radar is taken to face forward from the front of the car.
+fn radar_ttc(radar: &Radar) -> (Option<f32>, Option<f32>) {
+    let target = radar
+        .detections
+        .iter()
+        .filter(|detection| detection.rcs >= MIN_TARGET_RCS && detection.range <= MAX_RANGE)
+        .filter(|detection| detection.position().y.abs() < CAST_WIDTH / 2.0)
+        .min_by(|a, b| a.range.total_cmp(&b.range));
+    let ttc = target.and_then(|target| {
+        (target.range_rate < 0.0).then(|| target.range.max(0.0) / -target.range_rate)
+    });
+    (ttc, target.map(|target| target.range))
+}
+
+fn log_aeb_activation(
+    mut activations: MessageReader<AebActivation>,
+    mut run_log: ResMut<RunLog>,
+    time: Res<Time>,
+) {
+    for activation in activations.read() {
+        run_log.record(
+            time.elapsed_secs(),
+            "AEB",
+            format!(
+                "{:?} at TTC {:.2} s, distance {:.1} m, speed {:.1} m/s",
+                activation.stage, activation.ttc, activation.distance, activation.speed
+            ),
+        );
+    }
+}
@@ -3,7 +3,7 @@ use bevy_panorbit_camera::PanOrbitCamera;
 use bevy_panorbit_camera::PanOrbitCameraPlugin;
 use std::f32::consts::{PI, TAU};
 
-use crate::car_dynamics::{Car, EgoState};
+use crate::car_dynamics::{Ego, EgoState};
 use crate::utils::wrap;
 
 #[derive(Resource, PartialEq, Eq, Debug, Clone, Copy)]
@@ -54,8 +54,8 @@ fn setup_camera(mut commands: Commands) {
 fn camera_follow(
     mut camera: Single<&mut PanOrbitCamera>,
     camera_mode: Res<CameraMode>,
-    car_transform: Single<&Transform, With<Car>>,
-    ego_state: Single<&EgoState, With<Car>>
+    car_transform: Single<&Transform, With<Ego>>,
+    ego_state: Single<&EgoState, With<Ego>>
 ) {
     camera.target_focus = car_transform.translation;
     if *camera_mode != CameraMode::ThirdPersonView {
@@ -0,0 +1,550 @@
+use avian3d::prelude::*;
+use bevy::asset::RenderAssetUsages;
+use bevy::camera::RenderTarget;
+use bevy::prelude::*;
+use bevy::render::gpu_readback::{Readback, ReadbackComplete};
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat, TextureUsages};
+use bevy::tasks::{ComputeTaskPool, ParallelSlice};
+use bevy_egui::{EguiContexts, EguiPrimaryContextPass, EguiTextureHandle, egui};
+use serde::Deserialize;
+
+use crate::car_dynamics::{Car, EgoState};
+use crate::faults::{FaultTarget, Faults};
+use crate::semantic::SemanticClass;
+use crate::sensor::Mount;
+
+pub struct CameraSensorPlugin;
+
+impl Plugin for CameraSensorPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_message::<CameraFrame>()
+            .add_observer(read_back_frame)
+            .add_systems(
+                Update,
+                (spawn_sensor_cameras, update_sensor_cameras).chain(),
+            )
+            .add_systems(EguiPrimaryContextPass, draw_thumbnails);
+        #[cfg(not(target_arch = "wasm32"))]
+        app.init_resource::<export::FrameExport>()
+            .add_systems(Startup, export::setup_recorder)
+            .add_systems(Update, export::save_frames);
+    }
+}
+
+const THUMBNAIL_WIDTH: f32 = 320.0; // egui points
+const ROWS_PER_TASK: usize = 16; // of depth and label ray casts
+
+/// Camera rendering the scene from the vehicle into an offscreen image.
+///
+/// Frames are read back from the GPU at `rate` and published as [`CameraFrame`]s. With
+/// `depth` or `segmentation`, every frame also carries a metric depth image or per-pixel
+/// [`SemanticClass`] labels from the same viewpoint. Those are ray cast against the
+/// colliders when the frame is requested, so they show collision shapes and leave out
+/// anything without a collider.
+#[derive(Component, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct CameraSensor {
+    pub enabled: bool,
+    pub mount: Mount,
+    pub width: u32,  // px
+    pub height: u32, // px
+    pub fov: f32,    // deg, horizontal
+    pub rate: f32,   // Hz, frames per second
+    pub depth: bool,
+    pub segmentation: bool,
+    pub max_depth: f32, // m, farther pixels have no depth and no label
+    #[serde(skip)]
+    next_frame: f32, // s since startup
+    #[serde(skip)]
+    camera: Option<Entity>,
+    /// Render target, the latest rendered view.
+    #[serde(skip)]
+    pub image: Handle<Image>,
+    /// Colour coded depth and labels of the latest frame.
+    #[serde(skip)]
+    pub depth_preview: Handle<Image>,
+    #[serde(skip)]
+    pub label_preview: Handle<Image>,
+}
+
+impl Default for CameraSensor {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            mount: Mount::new(1.5, 0.0, 1.4),
+            width: 640,
+            height: 480,
+            fov: 60.0,
+            rate: 10.0,
+            depth: false,
+            segmentation: false,
+            max_depth: 200.0,
+            next_frame: 0.0,
+            camera: None,
+            image: Handle::default(),
+            depth_preview: Handle::default(),
+            label_preview: Handle::default(),
+        }
+    }
+}
+
+impl CameraSensor {
+    /// Camera fitted to the car but switched off.
+    pub fn disabled() -> Self {
+        Self {
+            enabled: false,
+            ..default()
+        }
+    }
+
+    fn vertical_fov(&self) -> f32 {
+        let aspect = self.height as f32 / self.width.max(1) as f32;
+        2.0 * ((self.fov.to_radians() / 2.0).tan() * aspect).atan()
+    }
+
+    // Sensor frame direction through the centre of pixel (column, row), with a forward
+    // component of one
+    fn pixel_direction(&self, column: u32, row: u32) -> Vec3 {
+        let half_width = (self.fov.to_radians() / 2.0).tan();
+        let half_height = half_width * self.height as f32 / self.width.max(1) as f32;
+        let right = (2.0 * (column as f32 + 0.5) / self.width as f32 - 1.0) * half_width;
+        let up = (1.0 - 2.0 * (row as f32 + 0.5) / self.height as f32) * half_height;
+        Vec3::new(1.0, -right, up)
+    }
+
+    /// Pixel coordinates, x right and y down from the top left corner, of a point in the
+    /// sensor frame in front of the camera.
+    pub fn project(&self, point: Vec3) -> Vec2 {
+        let half_width = (self.fov.to_radians() / 2.0).tan();
+        let half_height = half_width * self.height as f32 / self.width.max(1) as f32;
+        let right = -point.y / point.x / half_width;
+        let up = point.z / point.x / half_height;
+        Vec2::new(
+            (right + 1.0) / 2.0 * self.width as f32,
+            (1.0 - up) / 2.0 * self.height as f32,
+        )
+    }
+}
+
+/// Render camera of a [`CameraSensor`], spawned on its own so it can be posed freely.
+#[derive(Component)]
+struct SensorCamera {
+    car: Entity,
+    requested: Option<PendingFrame>,
+}
+
+// Frame being read back, with the outputs cast when it was requested
+struct PendingFrame {
+    time: f32, // s since startup
+    depth: Option<Vec<f32>>,
+    labels: Option<Vec<u8>>,
+}
+
+/// One frame read back from a camera sensor, as tightly packed RGBA8 rows from the top.
+#[derive(Message, Clone, Debug)]
+pub struct CameraFrame {
+    #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+    pub time: f32, // s since startup
+    #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+    pub width: u32,
+    #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+    pub height: u32,
+    pub pixels: Vec<u8>,
+    /// Distance along the optical axis per pixel in m, infinite where nothing was hit.
+    #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+    pub depth: Option<Vec<f32>>,
+    /// [`SemanticClass`] id per pixel.
+    #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+    pub labels: Option<Vec<u8>>,
+}
+
+fn spawn_sensor_cameras(
+    mut commands: Commands,
+    mut sensors: Query<(Entity, &mut CameraSensor), With<Car>>,
+    cameras: Query<(Entity, &SensorCamera)>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    // Cameras of removed or replaced sensors
+    for (entity, camera) in &cameras {
+        let owned = sensors
+            .get(camera.car)
+            .is_ok_and(|(_, sensor)| sensor.camera == Some(entity));
+        if !owned {
+            commands.entity(entity).despawn();
+        }
+    }
+
+    for (car, mut sensor) in &mut sensors {
+        if sensor.camera.is_some() {
+            continue;
+        }
+        let mut image = Image::new_target_texture(
+            sensor.width.max(1),
+            sensor.height.max(1),
+            TextureFormat::Rgba8UnormSrgb,
+        );
+        image.texture_descriptor.usage |= TextureUsages::COPY_SRC;
+        sensor.image = images.add(image);
+        sensor.depth_preview = images.add(preview_image(&sensor));
+        sensor.label_preview = images.add(preview_image(&sensor));
+        let camera = commands
+            .spawn((
+                Camera3d::default(),
+                Camera {
+                    target: RenderTarget::Image(sensor.image.clone().into()),
+                    // Before the main camera, which draws the UI showing this one
+                    order: -1,
+                    is_active: sensor.enabled,
+                    ..default()
+                },
+                Projection::from(PerspectiveProjection {
+                    fov: sensor.vertical_fov(),
+                    ..default()
+                }),
+                SensorCamera {
+                    car,
+                    requested: None,
+                },
+            ))
+            .id();
+        sensor.camera = Some(camera);
+    }
+}
+
+fn preview_image(sensor: &CameraSensor) -> Image {
+    Image::new_fill(
+        Extent3d {
+            width: sensor.width.max(1),
+            height: sensor.height.max(1),
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        &[0, 0, 0, 255],
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::default(),
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+fn update_sensor_cameras(
+    mut commands: Commands,
+    mut sensors: Query<(&mut CameraSensor, &EgoState), With<Car>>,
+    mut cameras: Query<(&mut Camera, &mut Transform, &mut SensorCamera)>,
+    spatial_query: SpatialQuery,
+    triggers: Query<(), With<Sensor>>,
+    classes: Query<&SemanticClass>,
+    parents: Query<&ChildOf>,
+    time: Res<Time>,
+) {
+    let now = time.elapsed_secs();
+    for (mut sensor, state) in &mut sensors {
+        let Some(entity) = sensor.camera else {
+            continue;
+        };
+        let Ok((mut camera, mut transform, mut sensor_camera)) = cameras.get_mut(entity) else {
+            continue;
+        };
+        camera.is_active = sensor.enabled;
+        if !sensor.enabled {
+            continue;
+        }
+        // Bevy cameras look down -Z with Y up, the sensor frame looks down X with Z up
+        let pose = sensor.mount.world_pose(state);
+        *transform = Transform::from_translation(pose.translation)
+            .looking_to(pose.rotation * Vec3::X, pose.rotation * Vec3::Z);
+
+        if now >= sensor.next_frame && sensor_camera.requested.is_none() {
+            sensor.next_frame = now + 1.0 / sensor.rate.max(0.1);
+            let (depth, labels) = if sensor.depth || sensor.segmentation {
+                let hits = cast_pixels(&sensor, pose, &spatial_query, &triggers);
+                let depth = hits.iter().map(|hit| hit.map_or(f32::INFINITY, |(d, _)| d));
+                let labels = hits.iter().map(|hit| {
+                    hit.map_or(SemanticClass::Unlabeled, |(_, entity)| {
+                        SemanticClass::of(entity, &classes, &parents)
+                    })
+                    .id()
+                });
+                (
+                    sensor.depth.then(|| depth.collect()),
+                    sensor.segmentation.then(|| labels.collect()),
+                )
+            } else {
+                (None, None)
+            };
+            sensor_camera.requested = Some(PendingFrame {
+                time: now,
+                depth,
+                labels,
+            });
+            commands
+                .entity(entity)
+                .insert(Readback::texture(sensor.image.clone()));
+        }
+    }
+}
+
+// Depth along the optical axis and the entity hit for every pixel, row by row from the top
+fn cast_pixels(
+    sensor: &CameraSensor,
+    pose: Transform,
+    spatial_query: &SpatialQuery,
+    triggers: &Query<(), With<Sensor>>,
+) -> Vec<Option<(f32, Entity)>> {
+    // The car itself is in view like in the rendered image, trigger volumes are not
+    let filter = SpatialQueryFilter::default();
+    let rows: Vec<u32> = (0..sensor.height).collect();
+    rows.par_chunk_map(ComputeTaskPool::get(), ROWS_PER_TASK, |_, rows| {
+        let solid = |entity| !triggers.contains(entity);
+        let mut hits = Vec::with_capacity(rows.len() * sensor.width as usize);
+        for &row in rows {
+            for column in 0..sensor.width {
+                let direction = sensor.pixel_direction(column, row);
+                let length = direction.length();
+                let hit = spatial_query.cast_ray_predicate(
+                    pose.translation,
+                    Dir3::new_unchecked(pose.rotation * direction / length),
+                    sensor.max_depth * length,
+                    true,
+                    &filter,
+                    &solid,
+                );
+                hits.push(hit.map(|hit| (hit.distance / length, hit.entity)));
+            }
+        }
+        hits
+    })
+    .concat()
+}
+
+fn read_back_frame(
+    readback: On<ReadbackComplete>,
+    mut commands: Commands,
+    mut cameras: Query<&mut SensorCamera>,
+    sensors: Query<&CameraSensor>,
+    mut images: ResMut<Assets<Image>>,
+    mut frames: MessageWriter<CameraFrame>,
+    mut faults: ResMut<Faults>,
+) {
+    let Ok(mut camera) = cameras.get_mut(readback.entity) else {
+        return;
+    };
+    // One frame per request, the readback would otherwise repeat every frame
+    commands.entity(readback.entity).remove::<Readback>();
+    let (Some(pending), Ok(sensor)) = (camera.requested.take(), sensors.get(camera.car)) else {
+        return;
+    };
+
+    // Rows come padded to the GPU's copy alignment
+    let (width, height) = (sensor.width.max(1), sensor.height.max(1));
+    let row = width as usize * 4;
+    let stride = readback.data.len() / height as usize;
+    if stride < row {
+        return;
+    }
+    let pixels = readback
+        .data
+        .chunks_exact(stride)
+        .flat_map(|padded| &padded[..row])
+        .copied()
+        .collect();
+
+    if let (Some(depth), Some(image)) = (&pending.depth, images.get_mut(&sensor.depth_preview)) {
+        // Near is white, fading to black at the maximum depth
+        let pixels = depth.iter().flat_map(|&depth| {
+            let value = (255.0 * (1.0 - depth / sensor.max_depth).clamp(0.0, 1.0)) as u8;
+            [value, value, value, 255]
+        });
+        image.data = Some(pixels.collect());
+    }
+    if let (Some(labels), Some(image)) = (&pending.labels, images.get_mut(&sensor.label_preview)) {
+        let pixels = labels.iter().flat_map(|&id| {
+            let [r, g, b] = SemanticClass::from_id(id).color();
+            [r, g, b, 255]
+        });
+        image.data = Some(pixels.collect());
+    }
+
+    let frame = CameraFrame {
+        time: pending.time,
+        width,
+        height,
+        pixels,
+        depth: pending.depth,
+        labels: pending.labels,
+    };
+    let corrupt = |frame: &mut CameraFrame, error: &mut dyn FnMut() -> f32| {
+        for pixel in frame.pixels.chunks_exact_mut(4) {
+            for channel in &mut pixel[..3] {
+                *channel = (*channel as f32 + 255.0 * error()).clamp(0.0, 255.0) as u8;
+            }
+        }
+    };
+    if let Some(frame) = faults.apply(FaultTarget::Camera, frame, pending.time, corrupt) {
+        frames.write(frame);
+    }
+}
+
+fn draw_thumbnails(
+    mut contexts: EguiContexts,
+    sensors: Query<(Entity, &CameraSensor)>,
+    #[cfg(not(target_arch = "wasm32"))] mut export: ResMut<export::FrameExport>,
+) -> Result {
+    for (car, sensor) in &sensors {
+        if !sensor.enabled || sensor.camera.is_none() {
+            continue;
+        }
+        let texture = contexts.add_image(EguiTextureHandle::Weak(sensor.image.id()));
+        let previews: Vec<_> = [
+            (&sensor.depth_preview, sensor.depth),
+            (&sensor.label_preview, sensor.segmentation),
+        ]
+        .into_iter()
+        .filter(|(_, shown)| *shown)
+        .map(|(preview, _)| contexts.add_image(EguiTextureHandle::Weak(preview.id())))
+        .collect();
+        let size = egui::vec2(
+            THUMBNAIL_WIDTH,
+            THUMBNAIL_WIDTH * sensor.height as f32 / sensor.width.max(1) as f32,
+        );
+        egui::Window::new("Camera")
+            .id(egui::Id::new(("camera_sensor", car)))
+            .default_pos([15.0, 15.0])
+            .resizable(false)
+            .show(contexts.ctx_mut()?, |ui| {
+                ui.image((texture, size));
+                // Depth and labels side by side under the image
+                ui.horizontal(|ui| {
+                    for &preview in &previews {
+                        ui.image((preview, size / 2.0));
+                    }
+                });
+                ui.label(format!(
+                    "{}x{} @ {:.0} Hz, {:.0}° FOV",
+                    sensor.width, sensor.height, sensor.rate, sensor.fov
+                ));
+                #[cfg(not(target_arch = "wasm32"))]
+                if ui.button("Save PNG").clicked() {
+                    export.snapshot = true;
+                }
+            });
+    }
+    Ok(())
+}
+
+/// Frames saved to files: every frame to `--record-camera <dir>`, or the next one to the
+/// working directory with the thumbnail's save button. Images are PNG files, depth is a
+/// PFM file of metres with infinity where nothing was hit, and labels are 8-bit PNG files
+/// of class ids.
+#[cfg(not(target_arch = "wasm32"))]
+mod export {
+    use bevy::asset::RenderAssetUsages;
+    use bevy::prelude::*;
+    use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+    use bevy::tasks::IoTaskPool;
+    use std::path::{Path, PathBuf};
+
+    use super::CameraFrame;
+    use crate::utils::launch_argument;
+
+    #[derive(Resource, Default)]
+    pub struct FrameExport {
+        pub snapshot: bool,
+        dir: Option<PathBuf>,
+        count: usize,
+    }
+
+    pub fn setup_recorder(mut export: ResMut<FrameExport>) {
+        let Some(dir) = launch_argument("record-camera") else {
+            return;
+        };
+        let dir = PathBuf::from(dir);
+        if let Err(e) = std::fs::create_dir_all(&dir) {
+            error!("Cannot record camera frames to {}: {}", dir.display(), e);
+            return;
+        }
+        info!("Recording camera frames to {}", dir.display());
+        export.dir = Some(dir);
+    }
+
+    pub fn save_frames(mut frames: MessageReader<CameraFrame>, mut export: ResMut<FrameExport>) {
+        for frame in frames.read() {
+            if let Some(dir) = &export.dir {
+                let count = export.count;
+                save_frame(frame, |kind, extension| {
+                    dir.join(format!("{}_{:06}.{}", kind, count, extension))
+                });
+                export.count += 1;
+            }
+            if export.snapshot {
+                export.snapshot = false;
+                info!("Saving camera frame at {:.2} s", frame.time);
+                save_frame(frame, |kind, extension| {
+                    PathBuf::from(format!("camera_{:.2}_{}.{}", frame.time, kind, extension))
+                });
+            }
+        }
+    }
+
+    // Encoded off the main thread, like screenshots
+    fn save_frame(frame: &CameraFrame, path: impl Fn(&str, &str) -> PathBuf) {
+        let image = |data, format| {
+            Image::new(
+                Extent3d {
+                    width: frame.width,
+                    height: frame.height,
+                    depth_or_array_layers: 1,
+                },
+                TextureDimension::D2,
+                data,
+                format,
+                RenderAssetUsages::MAIN_WORLD,
+            )
+        };
+        let mut images = vec![(
+            image(frame.pixels.clone(), TextureFormat::Rgba8UnormSrgb),
+            path("frame", "png"),
+        )];
+        if let Some(labels) = &frame.labels {
+            images.push((
+                image(labels.clone(), TextureFormat::R8Unorm),
+                path("label", "png"),
+            ));
+        }
+        let depth = frame
+            .depth
+            .as_ref()
+            .map(|depth| (pfm(frame.width, frame.height, depth), path("depth", "pfm")));
+
+        IoTaskPool::get()
+            .spawn(async move {
+                for (image, path) in images {
+                    write_png(image, &path);
+                }
+                if let Some((data, path)) = depth
+                    && let Err(e) = std::fs::write(&path, data)
+                {
+                    error!("Failed to save {}: {}", path.display(), e);
+                }
+            })
+            .detach();
+    }
+
+    fn write_png(image: Image, path: &Path) {
+        let result = image
+            .try_into_dynamic()
+            .map_err(|e| e.to_string())
+            .and_then(|image| image.save(path).map_err(|e| e.to_string()));
+        if let Err(e) = result {
+            error!("Failed to save {}: {}", path.display(), e);
+        }
+    }
+
+    // Portable float map, little endian with rows from the bottom
+    fn pfm(width: u32, height: u32, values: &[f32]) -> Vec<u8> {
+        let mut data = format!("Pf\n{} {}\n-1.0\n", width, height).into_bytes();
+        for row in values.chunks_exact(width as usize).rev() {
+            data.extend(row.iter().flat_map(|value| value.to_le_bytes()));
+        }
+        data
+    }
+}
@@ -1,3 +1,4 @@
+use crate::semantic::SemanticClass;
 use crate::utils::normalize_angle;
 use bevy::prelude::*;
 use std::{
@@ -9,25 +10,73 @@ pub struct CarDynamicsPlugin;
 
 impl Plugin for CarDynamicsPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Update, (bicycle_kinematic_model, wheel_movement));
+        app.configure_sets(
+            Update,
+            (
+                ControlSystems::Driver,
+                ControlSystems::Controller,
+                ControlSystems::Autopilot,
+                ControlSystems::Assist,
+                ControlSystems::Faults,
+            )
+                .chain()
+                .before(bicycle_kinematic_model),
+        )
+        .add_systems(Update, (bicycle_kinematic_model, wheel_movement));
     }
 }
 
-const WHEELBASE: f32 = 3.0;
-const MAX_ACCELERATION: f32 = 5.0;
+/// Systems writing [`EgoControl`], ordered before the vehicle model consumes it.
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ControlSystems {
+    /// Driver demand from keyboard and gamepad.
+    Driver,
+    /// The car's [`VehicleController`](crate::controller::VehicleController).
+    Controller,
+    /// Autonomous controllers taking over from the driver.
+    Autopilot,
+    /// Driver assistance that may override the driver, e.g. AEB.
+    Assist,
+    /// Injected actuator faults, corrupting whatever was commanded.
+    Faults,
+}
+
+pub const WHEELBASE: f32 = 3.0;
+pub const TRACK_WIDTH: f32 = 1.6;
+pub const WHEEL_RADIUS: f32 = 0.35;
+// Collider length, centred on the state position so the bumpers are half of it away
+pub const CAR_LENGTH: f32 = 5.0;
+// Height of the state position over the ground with the wheels on it
+pub const RIDE_HEIGHT: f32 = 0.64;
+pub const MAX_ACCELERATION: f32 = 5.0;
 pub const MAX_STEERING_ANGLE: f32 = 35.0f32.to_radians();
 const MAX_SPEED: f32 = 33.3;
+const MAX_REVERSE_SPEED: f32 = 5.0;
 pub const STEER_RATIO: f32 = 15.0;
 
+/// A vehicle moved by the bicycle model from its [`EgoControl`].
 #[derive(Component)]
+#[require(SemanticClass::Vehicle)]
 pub struct Car;
 
-#[derive(Component, Default)]
+/// The car driven by the player and the driver assistance systems.
+#[derive(Component)]
+pub struct Ego;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Gear {
+    #[default]
+    Drive,
+    Reverse,
+}
+
+#[derive(Component, Copy, Clone, Default)]
 pub struct EgoControl {
     pub throttle: f32,
     pub brake: f32,
     pub front_wheel_angle: f32,
     pub steer_wheel_angle: f32,
+    pub gear: Gear,
 }
 
 #[derive(Component)]
@@ -93,16 +142,23 @@ impl Mul<f32> for EgoStateDerivative {
 
 pub fn wheel_movement(
     mut wheels: Query<
-        (&mut Transform, &InitWheelRotation, Option<&SteeringWheel>),
+        (
+            &mut Transform,
+            &InitWheelRotation,
+            Option<&SteeringWheel>,
+            &ChildOf,
+        ),
         With<RollingWheel>,
     >,
-    control: Single<&EgoControl, With<Car>>,
-    q: Single<&EgoState, With<Car>>,
+    cars: Query<(&EgoControl, &EgoState), With<Car>>,
 ) {
-    let d_theta = (q.s / 0.35).rem_euclid(2.0 * PI);
-    let x_rot = Quat::from_rotation_x(d_theta); // wheel roll
-    let delta = control.front_wheel_angle;
-    for (mut transform, init_rot, is_steer) in wheels.iter_mut() {
+    for (mut transform, init_rot, is_steer, parent) in wheels.iter_mut() {
+        let Ok((control, q)) = cars.get(parent.parent()) else {
+            continue;
+        };
+        let d_theta = (q.s / WHEEL_RADIUS).rem_euclid(2.0 * PI);
+        let x_rot = Quat::from_rotation_x(d_theta); // wheel roll
+        let delta = control.front_wheel_angle;
         transform.rotation = x_rot * init_rot.0;
         if is_steer.is_some() {
             transform.rotate_y(delta); // wheel steer
@@ -110,47 +166,67 @@ pub fn wheel_movement(
     }
 }
 
-fn calculate_acceleration(throttle: f32, v: f32) -> f32 {
-    MAX_ACCELERATION * throttle * (1.0 - v / MAX_SPEED)
+fn calculate_acceleration(throttle: f32, v: f32, gear: Gear) -> f32 {
+    match gear {
+        Gear::Drive => MAX_ACCELERATION * throttle * (1.0 - v / MAX_SPEED),
+        Gear::Reverse => -MAX_ACCELERATION * throttle * (1.0 + v / MAX_REVERSE_SPEED),
+    }
 }
 
+const BRAKE_CURVE_MIDPOINT: f32 = 0.4;
+const BRAKE_RESPONSE_GAIN: f32 = 7.0;
+
 fn calculate_deceleration(brake: f32, v: f32) -> f32 {
-    let brake_curve_midpoint = 0.4;
-    let brake_response_gain = 7.0;
     let v_threshold = 0.2;
     let a_target = if brake == 0.0 {
         0.0
     } else {
         -1.0 * MAX_ACCELERATION
-            / (1.0 + (-brake_response_gain * (brake - brake_curve_midpoint)).exp())
+            / (1.0 + (-BRAKE_RESPONSE_GAIN * (brake - BRAKE_CURVE_MIDPOINT)).exp())
     };
-    let a_brake = if v > v_threshold {
+    let a_brake = if v.abs() > v_threshold {
         a_target
     } else {
-        a_target * (v / v_threshold)
+        a_target * (v.abs() / v_threshold)
     };
-    a_brake
+    // brake opposes the direction of travel
+    a_brake * v.signum()
 }
 
-fn bicycle_kinematic_model(
-    state: Single<(&mut Transform, &mut EgoState), With<Car>>,
-    control: Single<&EgoControl, With<Car>>,
+/// Inverse of the pedal maps: (throttle, brake) that yield acceleration `a` at speed `v`.
+pub fn pedals_for_acceleration(a: f32, v: f32) -> (f32, f32) {
+    if a >= 0.0 {
+        let a_max = MAX_ACCELERATION * (1.0 - v / MAX_SPEED);
+        let throttle = if a_max > 0.0 { a / a_max } else { 1.0 };
+        (throttle.clamp(0.0, 1.0), 0.0)
+    } else if -a >= MAX_ACCELERATION {
+        (0.0, 1.0)
+    } else {
+        let brake =
+            BRAKE_CURVE_MIDPOINT - (MAX_ACCELERATION / -a - 1.0).ln() / BRAKE_RESPONSE_GAIN;
+        (0.0, brake.clamp(0.0, 1.0))
+    }
+}
+
+pub fn bicycle_kinematic_model(
+    mut cars: Query<(&mut Transform, &mut EgoState, &EgoControl), With<Car>>,
     time: Res<Time>,
 ) {
-    let delta = control.front_wheel_angle;
-    let (mut trans, mut q) = state.into_inner();
-    let dq = EgoStateDerivative {
-        dx: q.v * q.yaw.cos(),
-        dy: q.v * q.yaw.sin(),
-        dtheta: q.v * delta.tan() / WHEELBASE,
-        dv: calculate_acceleration(control.throttle, q.v)
-            + calculate_deceleration(control.brake, q.v),
-        ds: q.v,
-    };
     let dt = time.delta_secs();
-    q.apply_derivative(dq, dt);
-
-    trans.translation.x = q.x;
-    trans.translation.y = q.y;
-    trans.rotate_z(dq.dtheta * dt);
+    for (mut trans, mut q, control) in &mut cars {
+        let delta = control.front_wheel_angle;
+        let dq = EgoStateDerivative {
+            dx: q.v * q.yaw.cos(),
+            dy: q.v * q.yaw.sin(),
+            dtheta: q.v * delta.tan() / WHEELBASE,
+            dv: calculate_acceleration(control.throttle, q.v, control.gear)
+                + calculate_deceleration(control.brake, q.v),
+            ds: q.v,
+        };
+        q.apply_derivative(dq, dt);
+
+        trans.translation.x = q.x;
+        trans.translation.y = q.y;
+        trans.rotate_z(dq.dtheta * dt);
+    }
 }
@@ -0,0 +1,143 @@
+use bevy::prelude::*;
+
+use crate::car_dynamics::{
+    ControlSystems, Ego, EgoControl, EgoState, MAX_ACCELERATION, pedals_for_acceleration,
+};
+use crate::input::HumanDriver;
+
+pub struct ControllerPlugin;
+
+impl Plugin for ControllerPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, toggle_cruise_control.before(ControlSystems::Driver))
+            .add_systems(Update, run_controller.in_set(ControlSystems::Controller));
+    }
+}
+
+/// Targets handed to the car's [`Controller`] each tick.
+#[derive(Component, Copy, Clone, Default)]
+pub struct ControlReference {
+    pub speed: f32,         // m/s
+    pub driver: EgoControl, // pedal and steering demand from keyboard or gamepad
+}
+
+/// Turns the vehicle state and reference into actuator commands.
+pub trait Controller: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn update(&mut self, state: &EgoState, reference: &ControlReference, dt: f32) -> EgoControl;
+}
+
+/// The controller currently driving a car; swap it to change who is in charge.
+#[derive(Component)]
+pub struct VehicleController(pub Box<dyn Controller>);
+
+impl Default for VehicleController {
+    fn default() -> Self {
+        Self(Box::new(HumanDriver))
+    }
+}
+
+impl VehicleController {
+    pub fn is_human(&self) -> bool {
+        self.0.name() == HumanDriver.name()
+    }
+}
+
+/// Speed tracking PID on acceleration with feed-forward of the reference rate and
+/// conditional integration as anti-windup. Steering stays with the driver.
+pub struct PidSpeedController {
+    pub kp: f32,
+    pub ki: f32,
+    pub kd: f32,
+    pub feedforward_gain: f32,
+    integral: f32,
+    prev_error: Option<f32>,
+    prev_reference: Option<f32>,
+}
+
+impl Default for PidSpeedController {
+    fn default() -> Self {
+        Self {
+            kp: 0.8,
+            ki: 0.2,
+            kd: 0.05,
+            feedforward_gain: 1.0,
+            integral: 0.0,
+            prev_error: None,
+            prev_reference: None,
+        }
+    }
+}
+
+impl Controller for PidSpeedController {
+    fn name(&self) -> &'static str {
+        "PID Speed"
+    }
+
+    fn update(&mut self, state: &EgoState, reference: &ControlReference, dt: f32) -> EgoControl {
+        let error = reference.speed - state.v;
+        let derivative = match self.prev_error {
+            Some(prev) if dt > 0.0 => (error - prev) / dt,
+            _ => 0.0,
+        };
+        let reference_rate = match self.prev_reference {
+            Some(prev) if dt > 0.0 => (reference.speed - prev) / dt,
+            _ => 0.0,
+        };
+        self.prev_error = Some(error);
+        self.prev_reference = Some(reference.speed);
+
+        let unsaturated = self.feedforward_gain * reference_rate
+            + self.kp * error
+            + self.ki * (self.integral + error * dt)
+            + self.kd * derivative;
+        let acceleration = unsaturated.clamp(-MAX_ACCELERATION, MAX_ACCELERATION);
+        // Only integrate while the output is not pushed further into saturation
+        if acceleration == unsaturated || unsaturated.signum() != error.signum() {
+            self.integral += error * dt;
+        }
+
+        let (throttle, brake) = pedals_for_acceleration(acceleration, state.v);
+        EgoControl {
+            throttle,
+            brake,
+            ..reference.driver
+        }
+    }
+}
+
+fn run_controller(
+    car: Single<
+        (
+            &EgoState,
+            &ControlReference,
+            &mut VehicleController,
+            &mut EgoControl,
+        ),
+        With<Ego>,
+    >,
+    time: Res<Time>,
+) {
+    let (state, reference, mut controller, mut control) = car.into_inner();
+    *control = controller.0.update(state, reference, time.delta_secs());
+}
+
+// Cruise engages at the current speed and drops out when the driver brakes
+fn toggle_cruise_control(
+    key: Res<ButtonInput<KeyCode>>,
+    car: Single<(&EgoState, &mut ControlReference, &mut VehicleController), With<Ego>>,
+) {
+    let (state, mut reference, mut controller) = car.into_inner();
+    let cruising = !controller.is_human();
+    if key.just_pressed(KeyCode::KeyC) {
+        if cruising {
+            *controller = VehicleController::default();
+        } else {
+            reference.speed = state.v;
+            controller.0 = Box::new(PidSpeedController::default());
+        }
+    } else if cruising && reference.driver.brake > 0.0 {
+        *controller = VehicleController::default();
+    }
+}
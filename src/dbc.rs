@@ -0,0 +1,313 @@
+use bevy::asset::io::Reader;
+use bevy::asset::{AssetLoader, AsyncReadExt, LoadContext};
+use bevy::prelude::*;
+
+// DBC reader for the message and signal definitions (BO_ and SG_ lines), enough to
+// encode signal values into CAN frames. Multiplexed signals, value tables and
+// attributes are ignored.
+
+pub struct DbcPlugin;
+
+impl Plugin for DbcPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<Dbc>().register_asset_loader(DbcLoader);
+    }
+}
+
+const EXTENDED_ID_FLAG: u32 = 0x8000_0000;
+
+/// CAN database loaded from a `.dbc` file.
+#[derive(Asset, TypePath)]
+pub struct Dbc {
+    pub messages: Vec<DbcMessage>,
+}
+
+pub struct DbcMessage {
+    pub id: u32,
+    pub extended: bool,
+    pub size: usize, // bytes
+    pub signals: Vec<DbcSignal>,
+}
+
+pub struct DbcSignal {
+    pub name: String,
+    start: u32, // bit, the least significant for Intel and the most significant for Motorola
+    length: u32,
+    little_endian: bool,
+    signed: bool,
+    factor: f64,
+    offset: f64,
+    min: f64,
+    max: f64,
+}
+
+impl DbcMessage {
+    /// Payload with each signal set from `value`, and to zero where it has none.
+    pub fn encode(&self, value: impl Fn(&str) -> Option<f64>) -> Vec<u8> {
+        let mut data = vec![0; self.size];
+        for signal in &self.signals {
+            if let Some(value) = value(&signal.name) {
+                signal.pack(signal.raw(value), &mut data);
+            }
+        }
+        data
+    }
+}
+
+impl DbcSignal {
+    /// Raw value of a physical value, limited to the signal's range and width.
+    fn raw(&self, value: f64) -> u64 {
+        let value = if self.min < self.max {
+            value.clamp(self.min, self.max)
+        } else {
+            value
+        };
+        let raw = ((value - self.offset) / self.factor).round() as i128;
+        let raw = if self.signed {
+            let limit = 1i128 << (self.length - 1);
+            raw.clamp(-limit, limit - 1)
+        } else {
+            raw.clamp(0, (1i128 << self.length) - 1)
+        };
+        raw as u64 & (u64::MAX >> (64 - self.length))
+    }
+
+    /// Bit positions in the payload from the most to the least significant bit.
+    fn bit_positions(&self) -> Vec<u32> {
+        if self.little_endian {
+            (0..self.length).rev().map(|i| self.start + i).collect()
+        } else {
+            // Motorola bits run down within a byte, then on to the top of the next byte
+            let mut position = self.start;
+            (0..self.length)
+                .map(|_| {
+                    let current = position;
+                    position = if position.is_multiple_of(8) {
+                        position + 15
+                    } else {
+                        position - 1
+                    };
+                    current
+                })
+                .collect()
+        }
+    }
+
+    fn pack(&self, raw: u64, data: &mut [u8]) {
+        for (i, position) in self.bit_positions().into_iter().rev().enumerate() {
+            if raw >> i & 1 == 1 {
+                data[position as usize / 8] |= 1 << (position % 8);
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum DbcError {
+    Io(std::io::Error),
+    Invalid(String),
+}
+
+impl std::fmt::Display for DbcError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "could not read file: {e}"),
+            Self::Invalid(e) => write!(f, "invalid DBC: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for DbcError {}
+
+struct DbcLoader;
+
+impl AssetLoader for DbcLoader {
+    type Asset = Dbc;
+    type Settings = ();
+    type Error = DbcError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        _load_context: &mut LoadContext<'_>,
+    ) -> Result<Dbc, DbcError> {
+        let mut text = String::new();
+        reader
+            .read_to_string(&mut text)
+            .await
+            .map_err(DbcError::Io)?;
+        parse(&text)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["dbc"]
+    }
+}
+
+fn parse(text: &str) -> Result<Dbc, DbcError> {
+    let mut messages: Vec<DbcMessage> = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        let invalid = |what: &str| DbcError::Invalid(format!("{what} on line {}", number + 1));
+        if let Some(rest) = line.strip_prefix("BO_ ") {
+            messages.push(parse_message(rest).ok_or_else(|| invalid("bad message"))?);
+        } else if let Some(rest) = line.strip_prefix("SG_ ") {
+            let message = messages
+                .last_mut()
+                .ok_or_else(|| invalid("signal outside a message"))?;
+            let Some(signal) = parse_signal(rest).ok_or_else(|| invalid("bad signal"))? else {
+                continue;
+            };
+            if signal
+                .bit_positions()
+                .iter()
+                .any(|&p| p / 8 >= message.size as u32)
+            {
+                return Err(invalid(&format!(
+                    "signal {} outside the payload",
+                    signal.name
+                )));
+            }
+            message.signals.push(signal);
+        }
+    }
+    Ok(Dbc { messages })
+}
+
+/// `<id> <name>: <size> <transmitter>`
+fn parse_message(text: &str) -> Option<DbcMessage> {
+    let (head, tail) = text.split_once(':')?;
+    let id: u32 = head.split_whitespace().next()?.parse().ok()?;
+    let size = tail.split_whitespace().next()?.parse().ok()?;
+    Some(DbcMessage {
+        id: id & !EXTENDED_ID_FLAG,
+        extended: id & EXTENDED_ID_FLAG != 0,
+        size,
+        signals: Vec::new(),
+    })
+}
+
+/// `<name> [<multiplexing>] : <start>|<length>@<order><sign> (<factor>,<offset>)
+/// [<min>|<max>] "<unit>" <receivers>`, with `None` for multiplexed signals.
+fn parse_signal(text: &str) -> Option<Option<DbcSignal>> {
+    let (head, tail) = text.split_once(':')?;
+    let mut head = head.split_whitespace();
+    let name = head.next()?.to_string();
+    if head.next().is_some() {
+        warn!("Skipping multiplexed CAN signal {}", name);
+        return Some(None);
+    }
+    let mut fields = tail.split_whitespace();
+    let (start, layout) = fields.next()?.split_once('|')?;
+    let (length, layout) = layout.split_once('@')?;
+    let (factor, offset) = fields
+        .next()?
+        .strip_prefix('(')?
+        .strip_suffix(')')?
+        .split_once(',')?;
+    let (min, max) = fields
+        .next()?
+        .strip_prefix('[')?
+        .strip_suffix(']')?
+        .split_once('|')?;
+    let length: u32 = length.parse().ok()?;
+    let factor: f64 = factor.parse().ok()?;
+    if !(1..=64).contains(&length) || factor == 0.0 {
+        return None;
+    }
+    Some(Some(DbcSignal {
+        name,
+        start: start.parse().ok()?,
+        length,
+        little_endian: match layout.get(..1)? {
+            "1" => true,
+            "0" => false,
+            _ => return None,
+        },
+        signed: match layout.get(1..)? {
+            "+" => false,
+            "-" => true,
+            _ => return None,
+        },
+        factor,
+        offset: offset.parse().ok()?,
+        min: min.parse().ok()?,
+        max: max.parse().ok()?,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vehicle_message(id: u32) -> DbcMessage {
+        let dbc = parse(include_str!("../assets/can/vehicle.dbc")).unwrap();
+        dbc.messages.into_iter().find(|m| m.id == id).unwrap()
+    }
+
+    fn encode(message: &DbcMessage, values: &[(&str, f64)]) -> Vec<u8> {
+        message.encode(|name| values.iter().find(|(n, _)| *n == name).map(|(_, v)| *v))
+    }
+
+    #[test]
+    fn motorola_signed_signals() {
+        let steering = vehicle_message(192);
+        // -12.5 deg is raw -125, 0xff83, most significant byte first from bit 7
+        assert_eq!(
+            encode(&steering, &[("SteeringWheelAngle", -12.5)]),
+            [0xff, 0x83, 0x00, 0x00]
+        );
+        // 90 deg is raw 900, 0x0384, and -300 deg/s is 0xfed4 from bit 23
+        assert_eq!(
+            encode(
+                &steering,
+                &[("SteeringWheelAngle", 90.0), ("SteeringWheelSpeed", -300.0)]
+            ),
+            [0x03, 0x84, 0xfe, 0xd4]
+        );
+    }
+
+    #[test]
+    fn intel_unsigned_signals() {
+        let wheel_speeds = vehicle_message(416);
+        // 100 km/h is raw 3200, 0x0c80, least significant byte first
+        assert_eq!(
+            encode(
+                &wheel_speeds,
+                &[("WheelSpeedFL", 100.0), ("WheelSpeedFR", 1.0)]
+            ),
+            [0x80, 0x0c, 0x20, 0x00, 0x00, 0x00, 0x00, 0x00]
+        );
+        let pedals = vehicle_message(512);
+        assert_eq!(
+            encode(
+                &pedals,
+                &[
+                    ("ThrottlePosition", 50.0),
+                    ("BrakePosition", 0.4),
+                    ("Gear", 3.0)
+                ]
+            ),
+            [0x7d, 0x01, 0x03]
+        );
+    }
+
+    #[test]
+    fn values_are_limited_to_the_signal_range() {
+        assert_eq!(
+            encode(&vehicle_message(192), &[("SteeringWheelAngle", -5000.0)]),
+            [0x80, 0x00, 0x00, 0x00]
+        );
+        assert_eq!(
+            encode(&vehicle_message(512), &[("ThrottlePosition", 150.0)]),
+            [0xff, 0x00, 0x00]
+        );
+    }
+
+    #[test]
+    fn rejects_signals_outside_the_payload() {
+        let text = "BO_ 1 SHORT: 1 SIM\n SG_ Wide : 7|16@0+ (1,0) [0|0] \"\" ECU";
+        assert!(matches!(parse(text), Err(DbcError::Invalid(_))));
+    }
+}
@@ -0,0 +1,352 @@
+use bevy::prelude::*;
+use serde::Deserialize;
+use std::any::Any;
+use std::collections::{HashMap, VecDeque};
+
+use crate::car_dynamics::{ControlSystems, Ego, EgoControl, EgoState, MAX_STEERING_ANGLE};
+use crate::run_log::RunLog;
+use crate::storyboard::{ScriptedActor, Trigger, entity_states};
+use crate::utils::SplitMix64;
+
+pub struct FaultsPlugin;
+
+impl Plugin for FaultsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Faults>()
+            .add_systems(Update, schedule_faults.before(ControlSystems::Driver))
+            .add_systems(Update, inject_control_faults.in_set(ControlSystems::Faults));
+    }
+}
+
+/// Sensor stream or [`EgoControl`] channel a fault acts on. Bias and noise are in the
+/// unit given for each.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum FaultTarget {
+    /// Throttle pedal, 0 to 1.
+    Throttle,
+    /// Brake pedal, 0 to 1.
+    Brake,
+    /// Front wheel angle in rad, leaving the steering wheel as the driver holds it.
+    Steering,
+    /// Range of every lidar return, m.
+    Lidar,
+    /// Range of every radar detection, m.
+    Radar,
+    /// Brightness of every pixel of published camera frames, as a fraction of full
+    /// scale.
+    Camera,
+    /// Every axis of the IMU accelerometer, m/s².
+    Accelerometer,
+    /// Every axis of the IMU gyro, rad/s.
+    Gyroscope,
+    /// GNSS position east and north, m.
+    Gnss,
+    /// Every vehicle bus signal in its own unit, except the gear and pulse counters.
+    VehicleBus,
+    /// Every ultrasonic distance, m.
+    Ultrasonic,
+}
+
+/// What a fault does to the samples of its target. On a control channel every frame is
+/// a sample, and a frame without one keeps the last command delivered.
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub enum FaultKind {
+    /// Repeats the sample taken when the fault starts, e.g. a stuck throttle.
+    Freeze,
+    /// Adds a constant offset, e.g. a steering offset.
+    Bias { value: f32 },
+    /// Adds white noise of the given standard deviation.
+    Noise { sigma: f32 },
+    /// Loses each sample with the given probability, all of them at 1.
+    Drop { probability: f32 },
+    /// Holds samples back, each coming out with the first sample after it is due.
+    /// Samples still held when the fault ends are lost.
+    Delay { seconds: f32 },
+}
+
+/// Fault as configured in a scenario's `faults`.
+///
+/// Faults on one target apply in the order they are listed.
+#[derive(Deserialize, Clone)]
+pub struct FaultSpec {
+    pub name: String,
+    pub target: FaultTarget,
+    pub kind: FaultKind,
+    /// s after the scenario starts, or after `trigger` fires.
+    #[serde(default)]
+    pub start: f32,
+    /// s, or until the end of the run.
+    #[serde(default)]
+    pub duration: Option<f32>,
+    /// Storyboard conditions arming the fault, as for story events.
+    #[serde(default)]
+    pub trigger: Option<Trigger>,
+}
+
+impl FaultSpec {
+    pub fn new(name: &str, target: FaultTarget, kind: FaultKind) -> Self {
+        Self {
+            name: name.to_string(),
+            target,
+            kind,
+            start: 0.0,
+            duration: None,
+            trigger: None,
+        }
+    }
+}
+
+/// Ready-made faults the panel injects on the spot.
+pub fn presets() -> Vec<FaultSpec> {
+    vec![
+        FaultSpec::new("Stuck throttle", FaultTarget::Throttle, FaultKind::Freeze),
+        FaultSpec::new(
+            "Steering offset",
+            FaultTarget::Steering,
+            FaultKind::Bias { value: 0.05 },
+        ),
+        FaultSpec::new(
+            "Brake failure",
+            FaultTarget::Brake,
+            FaultKind::Bias { value: -1.0 },
+        ),
+        FaultSpec::new(
+            "Lidar dropout",
+            FaultTarget::Lidar,
+            FaultKind::Drop { probability: 0.5 },
+        ),
+        FaultSpec::new(
+            "Radar range bias",
+            FaultTarget::Radar,
+            FaultKind::Bias { value: 2.0 },
+        ),
+        FaultSpec::new("Camera freeze", FaultTarget::Camera, FaultKind::Freeze),
+        FaultSpec::new(
+            "Gyro drift",
+            FaultTarget::Gyroscope,
+            FaultKind::Bias { value: 0.02 },
+        ),
+        FaultSpec::new(
+            "GNSS delay",
+            FaultTarget::Gnss,
+            FaultKind::Delay { seconds: 1.0 },
+        ),
+        FaultSpec::new(
+            "Ultrasonic noise",
+            FaultTarget::Ultrasonic,
+            FaultKind::Noise { sigma: 0.2 },
+        ),
+    ]
+}
+
+pub struct Fault {
+    pub spec: FaultSpec,
+    /// Switched off faults never become active.
+    pub enabled: bool,
+    pub active: bool,
+    origin: Option<f32>, // s since startup that `start` counts from
+    trigger: Option<Trigger>,
+}
+
+/// Faults of the running scenario and those injected from the panel.
+///
+/// Sensors pass each sample through [`Faults::apply`] before publishing it, and the
+/// control channels are corrupted after every other system writing [`EgoControl`].
+#[derive(Resource)]
+pub struct Faults {
+    pub faults: Vec<Fault>,
+    start_time: f32, // s since startup, of the scenario
+    rng: SplitMix64,
+    channels: HashMap<(FaultTarget, u32), Box<dyn Any + Send + Sync>>,
+}
+
+impl Default for Faults {
+    fn default() -> Self {
+        Self::new(Vec::new(), 0.0)
+    }
+}
+
+// Samples kept by the faults of one stream
+struct Channel<T> {
+    frozen: Option<T>,
+    delayed: VecDeque<(f32, T)>,
+}
+
+impl<T> Default for Channel<T> {
+    fn default() -> Self {
+        Self {
+            frozen: None,
+            delayed: VecDeque::new(),
+        }
+    }
+}
+
+impl Faults {
+    pub fn new(specs: Vec<FaultSpec>, start_time: f32) -> Self {
+        Self {
+            faults: specs
+                .into_iter()
+                .map(|spec| Fault {
+                    trigger: spec.trigger.clone(),
+                    spec,
+                    enabled: true,
+                    active: false,
+                    origin: None,
+                })
+                .collect(),
+            start_time,
+            rng: SplitMix64::new(0),
+            channels: HashMap::new(),
+        }
+    }
+
+    /// Adds a fault starting `spec.start` from now.
+    pub fn inject(&mut self, spec: FaultSpec) {
+        self.faults.push(Fault {
+            spec,
+            enabled: true,
+            active: false,
+            origin: None,
+            trigger: None,
+        });
+    }
+
+    /// The sample of `target` to publish instead of `sample`, if any. `corrupt` adds the
+    /// error it is given to every value that bias and noise act on.
+    pub fn apply<T: Clone + Send + Sync + 'static>(
+        &mut self,
+        target: FaultTarget,
+        sample: T,
+        now: f32,
+        corrupt: impl Fn(&mut T, &mut dyn FnMut() -> f32),
+    ) -> Option<T> {
+        self.apply_stream(target, 0, sample, now, corrupt)
+    }
+
+    /// As [`Faults::apply`], for one of several streams of `target` that are frozen and
+    /// delayed separately.
+    pub fn apply_stream<T: Clone + Send + Sync + 'static>(
+        &mut self,
+        target: FaultTarget,
+        stream: u32,
+        sample: T,
+        now: f32,
+        corrupt: impl Fn(&mut T, &mut dyn FnMut() -> f32),
+    ) -> Option<T> {
+        let kinds: Vec<FaultKind> = self
+            .faults
+            .iter()
+            .filter(|fault| fault.active && fault.spec.target == target)
+            .map(|fault| fault.spec.kind)
+            .collect();
+        if kinds.is_empty() {
+            self.channels.remove(&(target, stream));
+            return Some(sample);
+        }
+        let channel = self
+            .channels
+            .entry((target, stream))
+            .or_insert_with(|| Box::new(Channel::<T>::default()));
+        if !channel.is::<Channel<T>>() {
+            *channel = Box::new(Channel::<T>::default());
+        }
+        let channel = channel.downcast_mut::<Channel<T>>().unwrap();
+        if !kinds.iter().any(|kind| matches!(kind, FaultKind::Freeze)) {
+            channel.frozen = None;
+        }
+        if !kinds
+            .iter()
+            .any(|kind| matches!(kind, FaultKind::Delay { .. }))
+        {
+            channel.delayed.clear();
+        }
+
+        let rng = &mut self.rng;
+        let mut sample = Some(sample);
+        for kind in kinds {
+            sample = match kind {
+                FaultKind::Freeze => {
+                    sample.map(|sample| channel.frozen.get_or_insert(sample).clone())
+                }
+                FaultKind::Bias { value } => sample.map(|mut sample| {
+                    corrupt(&mut sample, &mut || value);
+                    sample
+                }),
+                FaultKind::Noise { sigma } => sample.map(|mut sample| {
+                    corrupt(&mut sample, &mut || rng.gaussian() * sigma);
+                    sample
+                }),
+                FaultKind::Drop { probability } => sample.filter(|_| rng.next_f32() >= probability),
+                FaultKind::Delay { seconds } => {
+                    if let Some(sample) = sample {
+                        channel.delayed.push_back((now + seconds, sample));
+                    }
+                    if channel.delayed.front().is_some_and(|(due, _)| *due <= now) {
+                        channel.delayed.pop_front().map(|(_, sample)| sample)
+                    } else {
+                        None
+                    }
+                }
+            };
+        }
+        sample
+    }
+}
+
+fn schedule_faults(
+    mut faults: ResMut<Faults>,
+    ego: Single<&EgoState, With<Ego>>,
+    actors: Query<(&ScriptedActor, &EgoState), Without<Ego>>,
+    mut run_log: ResMut<RunLog>,
+    time: Res<Time>,
+) {
+    if faults.faults.is_empty() {
+        return;
+    }
+    let now = time.elapsed_secs();
+    let story_time = now - faults.start_time;
+    let world = entity_states(&ego, actors.iter());
+    for fault in &mut faults.faults {
+        if fault.origin.is_none() {
+            fault.origin = match &mut fault.trigger {
+                Some(trigger) => trigger.update(story_time, &world).then_some(now),
+                None => Some(now),
+            };
+        }
+        let active = fault.enabled
+            && fault.origin.is_some_and(|origin| {
+                let since = now - origin - fault.spec.start;
+                since >= 0.0 && fault.spec.duration.is_none_or(|duration| since < duration)
+            });
+        if active != fault.active {
+            fault.active = active;
+            let change = if active { "active" } else { "cleared" };
+            run_log.record(now, "Fault", format!("{} {}", fault.spec.name, change));
+        }
+    }
+}
+
+// Last frame's commands stand in for those a fault holds back
+fn inject_control_faults(
+    mut faults: ResMut<Faults>,
+    mut control: Single<&mut EgoControl, With<Ego>>,
+    mut delivered: Local<Option<EgoControl>>,
+    time: Res<Time>,
+) {
+    let now = time.elapsed_secs();
+    let previous = delivered.unwrap_or(**control);
+    let add = |value: &mut f32, error: &mut dyn FnMut() -> f32| *value += error();
+    control.throttle = faults
+        .apply(FaultTarget::Throttle, control.throttle, now, add)
+        .unwrap_or(previous.throttle)
+        .clamp(0.0, 1.0);
+    control.brake = faults
+        .apply(FaultTarget::Brake, control.brake, now, add)
+        .unwrap_or(previous.brake)
+        .clamp(0.0, 1.0);
+    control.front_wheel_angle = faults
+        .apply(FaultTarget::Steering, control.front_wheel_angle, now, add)
+        .unwrap_or(previous.front_wheel_angle)
+        .clamp(-MAX_STEERING_ANGLE, MAX_STEERING_ANGLE);
+    *delivered = Some(**control);
+}
@@ -0,0 +1,210 @@
+use bevy::platform::collections::HashMap;
+use bevy::prelude::*;
+
+use crate::car_dynamics::Ego;
+use crate::init::spawn_car_model;
+use crate::lap_timer::{LapCompleted, LapTimer};
+
+pub struct GhostPlugin;
+
+impl Plugin for GhostPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<GhostReplay>().add_systems(
+            PostUpdate,
+            (
+                record_ghost,
+                replay_ghost.before(TransformSystems::Propagate),
+                make_ghost_translucent,
+            )
+                .chain(),
+        );
+    }
+}
+
+const GHOST_ALPHA: f32 = 0.35;
+// Ego positions are matched to ghost samples recorded this close in lap time
+const DELTA_WINDOW: f32 = 15.0; // s
+
+#[derive(Clone, Copy)]
+struct PoseSample {
+    time: f32, // s into the lap
+    translation: Vec3,
+    rotation: Quat,
+}
+
+struct RecordedLap {
+    course: String,
+    samples: Vec<PoseSample>,
+}
+
+impl RecordedLap {
+    // Interpolated pose at `time` into the lap, holding the last pose once finished
+    fn pose_at(&self, time: f32) -> Transform {
+        let i = self.samples.partition_point(|sample| sample.time <= time);
+        let (a, b) = match i {
+            0 => (self.samples[0], self.samples[0]),
+            i if i == self.samples.len() => (self.samples[i - 1], self.samples[i - 1]),
+            i => (self.samples[i - 1], self.samples[i]),
+        };
+        let t = if b.time > a.time {
+            (time - a.time) / (b.time - a.time)
+        } else {
+            0.0
+        };
+        Transform::from_translation(a.translation.lerp(b.translation, t))
+            .with_rotation(a.rotation.slerp(b.rotation, t))
+    }
+
+    // Lap time at which the ghost passed closest to `position`
+    fn time_at(&self, position: Vec2, around: f32) -> Option<f32> {
+        self.samples
+            .windows(2)
+            .filter(|pair| (pair[0].time - around).abs() < DELTA_WINDOW)
+            .map(|pair| {
+                let a = pair[0].translation.truncate();
+                let b = pair[1].translation.truncate();
+                let segment = b - a;
+                let t = if segment.length_squared() > 0.0 {
+                    ((position - a).dot(segment) / segment.length_squared()).clamp(0.0, 1.0)
+                } else {
+                    0.0
+                };
+                let distance = position.distance(a + segment * t);
+                (distance, pair[0].time + (pair[1].time - pair[0].time) * t)
+            })
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+            .map(|(_, time)| time)
+    }
+}
+
+/// Records the ego's laps and replays the best one as a translucent ghost car.
+#[derive(Resource)]
+pub struct GhostReplay {
+    pub enabled: bool,
+    /// Current lap time minus the best lap's time at the same place, negative when ahead.
+    pub delta: Option<f32>,
+    recording: Vec<PoseSample>,
+    best: Option<RecordedLap>,
+}
+
+impl Default for GhostReplay {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            delta: None,
+            recording: Vec::new(),
+            best: None,
+        }
+    }
+}
+
+#[derive(Component)]
+struct Ghost;
+
+fn record_ghost(
+    mut completed: MessageReader<LapCompleted>,
+    mut replay: ResMut<GhostReplay>,
+    timer: Res<LapTimer>,
+    ego: Single<&Transform, With<Ego>>,
+    time: Res<Time>,
+) {
+    for lap in completed.read() {
+        let samples = std::mem::take(&mut replay.recording);
+        if lap.best && !samples.is_empty() {
+            replay.best = Some(RecordedLap {
+                course: lap.course.clone(),
+                samples,
+            });
+        }
+    }
+
+    let Some(lap_time) = timer.current(time.elapsed_secs()) else {
+        replay.recording.clear();
+        return;
+    };
+    // A restarted lap, e.g. after a missed checkpoint, starts a new recording
+    if replay
+        .recording
+        .last()
+        .is_some_and(|sample| sample.time > lap_time)
+    {
+        replay.recording.clear();
+    }
+    replay.recording.push(PoseSample {
+        time: lap_time,
+        translation: ego.translation,
+        rotation: ego.rotation,
+    });
+}
+
+fn replay_ghost(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut replay: ResMut<GhostReplay>,
+    timer: Res<LapTimer>,
+    ego: Single<&Transform, (With<Ego>, Without<Ghost>)>,
+    mut ghosts: Query<(Entity, &mut Transform), With<Ghost>>,
+    time: Res<Time>,
+) {
+    let lap_time = timer.current(time.elapsed_secs());
+    let best = replay
+        .best
+        .as_ref()
+        .filter(|best| best.course == timer.course);
+    let (Some(lap_time), Some(best), true) = (lap_time, best, replay.enabled) else {
+        for (entity, _) in &ghosts {
+            commands.entity(entity).despawn();
+        }
+        replay.delta = None;
+        return;
+    };
+
+    let pose = best.pose_at(lap_time);
+    let delta = best
+        .time_at(ego.translation.truncate(), lap_time)
+        .map(|ghost_time| lap_time - ghost_time);
+    match ghosts.single_mut() {
+        Ok((_, mut transform)) => *transform = pose,
+        Err(_) => {
+            let ghost = spawn_car_model(&mut commands, &asset_server, pose);
+            commands.entity(ghost).insert(Ghost);
+        }
+    }
+    replay.delta = delta;
+}
+
+// Scene meshes of the ghost get see-through copies of their materials
+fn make_ghost_translucent(
+    mut commands: Commands,
+    added: Query<
+        (Entity, &MeshMaterial3d<StandardMaterial>),
+        Added<MeshMaterial3d<StandardMaterial>>,
+    >,
+    parents: Query<&ChildOf>,
+    ghosts: Query<(), With<Ghost>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut translucent: Local<HashMap<AssetId<StandardMaterial>, Handle<StandardMaterial>>>,
+) {
+    for (entity, material) in &added {
+        // Skip the copies themselves, inserted on an earlier run
+        if translucent.values().any(|copy| copy.id() == material.id()) {
+            continue;
+        }
+        if !parents
+            .iter_ancestors(entity)
+            .any(|parent| ghosts.contains(parent))
+        {
+            continue;
+        }
+        let handle = translucent
+            .entry(material.id())
+            .or_insert_with(|| {
+                let mut ghost_material = materials.get(&material.0).cloned().unwrap_or_default();
+                ghost_material.base_color.set_alpha(GHOST_ALPHA);
+                ghost_material.alpha_mode = AlphaMode::Blend;
+                materials.add(ghost_material)
+            })
+            .clone();
+        commands.entity(entity).insert(MeshMaterial3d(handle));
+    }
+}
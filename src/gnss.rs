@@ -0,0 +1,256 @@
+use bevy::prelude::*;
+use serde::Deserialize;
+use std::collections::VecDeque;
+
+use crate::car_dynamics::{Car, EgoState};
+use crate::faults::{FaultTarget, Faults};
+use crate::sensor::Mount;
+use crate::utils::SplitMix64;
+
+pub struct GnssPlugin;
+
+impl Plugin for GnssPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_message::<GnssFix>()
+            .add_systems(Update, sample_gnss);
+        #[cfg(not(target_arch = "wasm32"))]
+        app.add_systems(Startup, recording::setup_recorder)
+            .add_systems(Update, recording::record_fixes.after(sample_gnss));
+    }
+}
+
+const EARTH_RADIUS: f64 = 6_378_137.0; // m, WGS 84 equatorial
+
+/// GNSS receiver with its antenna at `mount`, reporting noisy fixes of the antenna
+/// position and the vehicle velocity.
+///
+/// Fixes are delivered `latency` after they are measured. No fixes are measured during
+/// outages, either scheduled in `outages` or drawn at random from `outage_rate`.
+#[derive(Component, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct Gnss {
+    pub enabled: bool,
+    pub mount: Mount,
+    pub rate: f32,             // Hz, fixes per second
+    pub latency: f32,          // s, from measurement to delivery
+    pub horizontal_noise: f32, // m, standard deviation per axis
+    pub vertical_noise: f32,   // m, standard deviation
+    pub velocity_noise: f32,   // m/s, standard deviation per axis
+    /// Latitude and longitude in degrees and altitude in m of the world origin, with x
+    /// pointing east and y north.
+    pub origin: (f64, f64, f64),
+    /// Scheduled outages as start and end in s after the receiver is fitted.
+    pub outages: Vec<(f32, f32)>,
+    pub outage_rate: f32,     // random outages per minute
+    pub outage_duration: f32, // s, mean length of a random outage
+    pub seed: u64,
+    #[serde(skip)]
+    fitted: Option<f32>, // s since startup
+    #[serde(skip)]
+    next_fix: f32, // s since startup
+    #[serde(skip)]
+    random_outage_end: f32, // s since startup
+    #[serde(skip)]
+    rng: Option<SplitMix64>,
+    #[serde(skip)]
+    in_flight: VecDeque<GnssFix>,
+    #[serde(skip)]
+    pub in_outage: bool,
+    #[serde(skip)]
+    pub last_fix: Option<GnssFix>,
+}
+
+impl Default for Gnss {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            mount: Mount::new(1.0, 0.0, 1.5),
+            rate: 10.0,
+            latency: 0.1,
+            horizontal_noise: 1.5,
+            vertical_noise: 3.0,
+            velocity_noise: 0.1,
+            origin: (48.137, 11.575, 520.0),
+            outages: Vec::new(),
+            outage_rate: 0.0,
+            outage_duration: 10.0,
+            seed: 0,
+            fitted: None,
+            next_fix: 0.0,
+            random_outage_end: 0.0,
+            rng: None,
+            in_flight: VecDeque::new(),
+            in_outage: false,
+            last_fix: None,
+        }
+    }
+}
+
+impl Gnss {
+    /// GNSS receiver fitted to the car but switched off.
+    pub fn disabled() -> Self {
+        Self {
+            enabled: false,
+            ..default()
+        }
+    }
+
+    /// Latitude, longitude and altitude of a world position, on a plane tangent at the
+    /// origin.
+    pub fn geodetic(&self, position: Vec3) -> (f64, f64, f64) {
+        let (latitude, longitude, altitude) = self.origin;
+        let north = position.y as f64 / EARTH_RADIUS;
+        let east = position.x as f64 / (EARTH_RADIUS * latitude.to_radians().cos());
+        (
+            latitude + north.to_degrees(),
+            longitude + east.to_degrees(),
+            altitude + position.z as f64,
+        )
+    }
+}
+
+/// One position and velocity fix.
+#[derive(Message, Clone, Copy, Debug)]
+pub struct GnssFix {
+    pub time: f32,      // s since startup, when measured
+    pub position: Vec3, // m, world frame
+    pub latitude: f64,  // deg
+    pub longitude: f64, // deg
+    pub altitude: f64,  // m
+    #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+    pub velocity: Vec2, // m/s, east and north
+    /// Standard deviation of the horizontal position error.
+    pub horizontal_accuracy: f32, // m
+}
+
+fn sample_gnss(
+    mut receivers: Query<(&mut Gnss, &EgoState), With<Car>>,
+    mut fixes: MessageWriter<GnssFix>,
+    mut faults: ResMut<Faults>,
+    time: Res<Time>,
+) {
+    let now = time.elapsed_secs();
+    let dt = time.delta_secs();
+    for (mut gnss, state) in &mut receivers {
+        while gnss
+            .in_flight
+            .front()
+            .is_some_and(|fix| fix.time + gnss.latency <= now)
+        {
+            let fix = gnss.in_flight.pop_front().unwrap();
+            let Some(mut fix) = faults.apply(FaultTarget::Gnss, fix, now, |fix, error| {
+                fix.position += Vec3::new(error(), error(), 0.0);
+            }) else {
+                continue;
+            };
+            (fix.latitude, fix.longitude, fix.altitude) = gnss.geodetic(fix.position);
+            gnss.last_fix = Some(fix);
+            fixes.write(fix);
+        }
+        if !gnss.enabled {
+            gnss.in_flight.clear();
+            continue;
+        }
+        let fitted = *gnss.fitted.get_or_insert(now);
+        let seed = gnss.seed;
+        let mut rng = gnss.rng.take().unwrap_or(SplitMix64::new(seed));
+
+        // Random outages start as a Poisson process and last an exponential time
+        if now >= gnss.random_outage_end && rng.next_f32() < gnss.outage_rate / 60.0 * dt {
+            gnss.random_outage_end = now - gnss.outage_duration * (1.0 - rng.next_f32()).ln();
+        }
+        let since_fitted = now - fitted;
+        gnss.in_outage = now < gnss.random_outage_end
+            || gnss
+                .outages
+                .iter()
+                .any(|&(start, end)| (start..end).contains(&since_fitted));
+
+        if !gnss.in_outage && now >= gnss.next_fix {
+            gnss.next_fix = now + 1.0 / gnss.rate.max(0.1);
+            let antenna = gnss.mount.world_pose(state).translation;
+            let error = Vec3::new(
+                rng.gaussian() * gnss.horizontal_noise,
+                rng.gaussian() * gnss.horizontal_noise,
+                rng.gaussian() * gnss.vertical_noise,
+            );
+            let position = antenna + error;
+            let velocity = Vec2::from_angle(state.yaw) * state.v
+                + Vec2::new(rng.gaussian(), rng.gaussian()) * gnss.velocity_noise;
+            let (latitude, longitude, altitude) = gnss.geodetic(position);
+            let fix = GnssFix {
+                time: now,
+                position,
+                latitude,
+                longitude,
+                altitude,
+                velocity,
+                horizontal_accuracy: gnss.horizontal_noise,
+            };
+            gnss.in_flight.push_back(fix);
+        }
+        gnss.rng = Some(rng);
+    }
+}
+
+/// Fixes saved as CSV with `--record-gnss <file>`.
+#[cfg(not(target_arch = "wasm32"))]
+mod recording {
+    use bevy::prelude::*;
+    use std::fs::File;
+    use std::io::{BufWriter, Write};
+
+    use super::GnssFix;
+    use crate::utils::launch_argument;
+
+    #[derive(Resource)]
+    pub struct GnssRecorder(BufWriter<File>);
+
+    pub fn setup_recorder(mut commands: Commands) {
+        let Some(path) = launch_argument("record-gnss") else {
+            return;
+        };
+        let mut file = match File::create(&path) {
+            Ok(file) => BufWriter::new(file),
+            Err(e) => {
+                error!("Cannot record GNSS fixes to {}: {}", path, e);
+                return;
+            }
+        };
+        let header = "time,x,y,z,latitude,longitude,altitude,ve,vn,accuracy";
+        if let Err(e) = writeln!(file, "{}", header) {
+            error!("Failed to write {}: {}", path, e);
+            return;
+        }
+        info!("Recording GNSS fixes to {}", path);
+        commands.insert_resource(GnssRecorder(file));
+    }
+
+    pub fn record_fixes(mut fixes: MessageReader<GnssFix>, recorder: Option<ResMut<GnssRecorder>>) {
+        let Some(mut recorder) = recorder else {
+            fixes.clear();
+            return;
+        };
+        for fix in fixes.read() {
+            let p = fix.position;
+            let row = writeln!(
+                recorder.0,
+                "{:.3},{:.3},{:.3},{:.3},{:.8},{:.8},{:.3},{:.3},{:.3},{:.2}",
+                fix.time,
+                p.x,
+                p.y,
+                p.z,
+                fix.latitude,
+                fix.longitude,
+                fix.altitude,
+                fix.velocity.x,
+                fix.velocity.y,
+                fix.horizontal_accuracy
+            );
+            if let Err(e) = row {
+                error!("Failed to record GNSS fixes: {}", e);
+                return;
+            }
+        }
+    }
+}
@@ -0,0 +1,379 @@
+use avian3d::prelude::*;
+use bevy::prelude::*;
+
+use crate::camera_sensor::CameraSensor;
+use crate::car_dynamics::{Ego, EgoState, bicycle_kinematic_model};
+use crate::semantic::SemanticClass;
+use crate::utils::normalize_angle;
+
+pub struct GroundTruthPlugin;
+
+impl Plugin for GroundTruthPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<GroundTruth>()
+            .add_message::<GroundTruthFrame>()
+            .add_systems(
+                Update,
+                (collect_ground_truth, draw_ground_truth_boxes)
+                    .chain()
+                    .after(bicycle_kinematic_model),
+            );
+        #[cfg(not(target_arch = "wasm32"))]
+        app.add_systems(Startup, recording::setup_recorder)
+            .add_systems(Update, recording::record_frames.after(collect_ground_truth));
+    }
+}
+
+// Nearest depth in front of a camera that boxes are clipped to
+const NEAR_PLANE: f32 = 0.1; // m
+
+/// Objects of the current tick, for evaluating perception against.
+#[derive(Resource, Default)]
+pub struct GroundTruth {
+    /// Draw the 3D boxes in the world.
+    pub show_boxes: bool,
+    pub objects: Vec<GroundTruthObject>,
+}
+
+/// Pose and velocity of an object's box centre.
+#[derive(Clone, Copy, Debug)]
+pub struct ObjectState {
+    pub center: Vec3,   // m
+    pub yaw: f32,       // rad
+    #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+    pub velocity: Vec3, // m/s
+}
+
+/// Box of an object in a camera sensor's image, enclosing the whole object whether it
+/// is occluded or not.
+#[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+#[derive(Clone, Copy, Debug)]
+pub struct ImageBox {
+    /// Car carrying the camera sensor.
+    pub camera: Entity,
+    pub rect: Rect, // px, x right and y down from the top left corner
+    /// The box was cut by the image border or the camera's near plane.
+    pub truncated: bool,
+}
+
+/// A vehicle, road user or obstacle with its box aligned to its heading.
+#[derive(Clone, Debug)]
+pub struct GroundTruthObject {
+    #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+    pub entity: Entity,
+    pub class: SemanticClass,
+    pub size: Vec3, // m, length, width and height
+    pub world: ObjectState,
+    /// In the ego vehicle frame, with the velocity relative to the ego.
+    pub ego: ObjectState,
+    #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+    pub image_boxes: Vec<ImageBox>,
+}
+
+impl GroundTruthObject {
+    /// World transform of the box, scaled to its size.
+    pub fn box_transform(&self) -> Transform {
+        Transform::from_translation(self.world.center)
+            .with_rotation(Quat::from_rotation_z(self.world.yaw))
+            .with_scale(self.size)
+    }
+}
+
+/// Ground truth published every tick.
+#[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+#[derive(Message, Clone, Debug)]
+pub struct GroundTruthFrame {
+    pub time: f32, // s since startup
+    pub objects: Vec<GroundTruthObject>,
+}
+
+/// Box of an object's collider aligned to its heading, as the world centre, yaw and size.
+///
+/// Cars' models are rotated in their transform, so the heading of anything moved by an
+/// [`EgoState`] is the state's.
+pub fn heading_box(
+    collider: &Collider,
+    transform: &Transform,
+    state: Option<&EgoState>,
+) -> (Vec3, f32, Vec3) {
+    let yaw = state.map_or_else(
+        || {
+            let forward = transform.rotation * Vec3::X;
+            forward.y.atan2(forward.x)
+        },
+        |state| state.yaw,
+    );
+    let heading = Quat::from_rotation_z(yaw);
+    let aabb = collider.aabb(Vec3::ZERO, Rotation(heading.inverse() * transform.rotation));
+    let center = transform.translation + heading * (aabb.min + aabb.max) / 2.0;
+    (center, yaw, aabb.max - aabb.min)
+}
+
+// Box in a camera's image, from the corners in front of its near plane and the points
+// where the box edges cross it. Corners are in the sensor frame, indexed as in
+// `collect_ground_truth`.
+fn image_box(sensor: &CameraSensor, corners: &[Vec3; 8]) -> Option<(Rect, bool)> {
+    const EDGES: [(usize, usize); 12] = [
+        (0, 1),
+        (2, 3),
+        (4, 5),
+        (6, 7),
+        (0, 2),
+        (1, 3),
+        (4, 6),
+        (5, 7),
+        (0, 4),
+        (1, 5),
+        (2, 6),
+        (3, 7),
+    ];
+    let mut points: Vec<Vec3> = corners
+        .iter()
+        .copied()
+        .filter(|c| c.x >= NEAR_PLANE)
+        .collect();
+    let clipped = points.len() < corners.len();
+    for (a, b) in EDGES.map(|(i, j)| (corners[i], corners[j])) {
+        if (a.x < NEAR_PLANE) != (b.x < NEAR_PLANE) {
+            points.push(a.lerp(b, (NEAR_PLANE - a.x) / (b.x - a.x)));
+        }
+    }
+    let first = sensor.project(*points.first()?);
+    let rect = points[1..]
+        .iter()
+        .fold(Rect::from_corners(first, first), |rect, &p| {
+            rect.union_point(sensor.project(p))
+        });
+    let image = Rect::new(0.0, 0.0, sensor.width as f32, sensor.height as f32);
+    let visible = rect.intersect(image);
+    if visible.is_empty() {
+        return None;
+    }
+    Some((visible, clipped || visible != rect))
+}
+
+#[allow(clippy::type_complexity)]
+fn collect_ground_truth(
+    mut ground_truth: ResMut<GroundTruth>,
+    ego: Single<&EgoState, With<Ego>>,
+    objects: Query<
+        (
+            Entity,
+            &SemanticClass,
+            &Collider,
+            &Transform,
+            Option<&EgoState>,
+            Option<&LinearVelocity>,
+        ),
+        (Without<Ego>, Without<Sensor>),
+    >,
+    cameras: Query<(Entity, &CameraSensor, &EgoState)>,
+    mut frames: MessageWriter<GroundTruthFrame>,
+    time: Res<Time>,
+) {
+    let ego_pose = Transform::from_xyz(ego.x, ego.y, 0.0)
+        .with_rotation(Quat::from_rotation_z(ego.yaw))
+        .compute_affine()
+        .inverse();
+    let ego_velocity = Vec2::from_angle(ego.yaw).extend(0.0) * ego.v;
+    let cameras: Vec<_> = cameras
+        .iter()
+        .filter(|(_, sensor, _)| sensor.enabled)
+        .map(|(car, sensor, state)| {
+            let to_sensor = sensor.mount.world_pose(state).compute_affine().inverse();
+            (car, sensor, to_sensor)
+        })
+        .collect();
+
+    let mut truth = Vec::new();
+    for (entity, &class, collider, transform, state, velocity) in &objects {
+        if !matches!(
+            class,
+            SemanticClass::Vehicle
+                | SemanticClass::Pedestrian
+                | SemanticClass::Cyclist
+                | SemanticClass::Obstacle
+        ) {
+            continue;
+        }
+        let (center, yaw, size) = heading_box(collider, transform, state);
+        let velocity = match state {
+            Some(state) => Vec2::from_angle(state.yaw).extend(0.0) * state.v,
+            None => velocity.map_or(Vec3::ZERO, |v| v.0),
+        };
+
+        // Corner i has bits 4, 2 and 1 set on its +x, +y and +z sides
+        let heading = Quat::from_rotation_z(yaw);
+        let corners: [Vec3; 8] = std::array::from_fn(|i| {
+            let side = |bit| if i & bit == 0 { -0.5 } else { 0.5 };
+            center + heading * (Vec3::new(side(4), side(2), side(1)) * size)
+        });
+        let image_boxes = cameras
+            .iter()
+            .filter(|(car, _, _)| *car != entity)
+            .filter_map(|(car, sensor, to_sensor)| {
+                let corners = corners.map(|c| to_sensor.transform_point3(c));
+                let (rect, truncated) = image_box(sensor, &corners)?;
+                Some(ImageBox {
+                    camera: *car,
+                    rect,
+                    truncated,
+                })
+            })
+            .collect();
+
+        truth.push(GroundTruthObject {
+            entity,
+            class,
+            size,
+            world: ObjectState {
+                center,
+                yaw,
+                velocity,
+            },
+            ego: ObjectState {
+                center: ego_pose.transform_point3(center),
+                yaw: normalize_angle(yaw - ego.yaw),
+                velocity: ego_pose.transform_vector3(velocity - ego_velocity),
+            },
+            image_boxes,
+        });
+    }
+
+    ground_truth.objects = truth.clone();
+    frames.write(GroundTruthFrame {
+        time: time.elapsed_secs(),
+        objects: truth,
+    });
+}
+
+fn draw_ground_truth_boxes(mut gizmos: Gizmos, ground_truth: Res<GroundTruth>) {
+    if !ground_truth.show_boxes {
+        return;
+    }
+    for object in &ground_truth.objects {
+        let [r, g, b] = object.class.color();
+        gizmos.cuboid(object.box_transform(), Color::srgb_u8(r, g, b));
+    }
+}
+
+/// Ground truth saved as CSV with `--record-ground-truth <dir>`: the objects of every
+/// tick to `objects.csv`, and their boxes in each camera image to `image_boxes.csv`.
+#[cfg(not(target_arch = "wasm32"))]
+mod recording {
+    use bevy::prelude::*;
+    use std::fs::File;
+    use std::io::{BufWriter, Write};
+    use std::path::Path;
+
+    use super::GroundTruthFrame;
+    use crate::utils::launch_argument;
+
+    #[derive(Resource)]
+    pub struct GroundTruthRecorder {
+        objects: BufWriter<File>,
+        image_boxes: BufWriter<File>,
+    }
+
+    fn create(dir: &Path, name: &str, header: &str) -> std::io::Result<BufWriter<File>> {
+        let mut file = BufWriter::new(File::create(dir.join(name))?);
+        writeln!(file, "{}", header)?;
+        Ok(file)
+    }
+
+    pub fn setup_recorder(mut commands: Commands) {
+        let Some(dir) = launch_argument("record-ground-truth") else {
+            return;
+        };
+        let dir = Path::new(&dir);
+        let files = std::fs::create_dir_all(dir).and_then(|_| {
+            Ok(GroundTruthRecorder {
+                objects: create(
+                    dir,
+                    "objects.csv",
+                    "time,id,class,x,y,z,yaw,length,width,height,vx,vy,vz,\
+                     ego_x,ego_y,ego_z,ego_yaw,ego_vx,ego_vy,ego_vz",
+                )?,
+                image_boxes: create(
+                    dir,
+                    "image_boxes.csv",
+                    "time,id,camera,x_min,y_min,x_max,y_max,truncated",
+                )?,
+            })
+        });
+        match files {
+            Ok(recorder) => {
+                info!("Recording ground truth to {}", dir.display());
+                commands.insert_resource(recorder);
+            }
+            Err(e) => error!("Cannot record ground truth to {}: {}", dir.display(), e),
+        }
+    }
+
+    fn write_frame(
+        recorder: &mut GroundTruthRecorder,
+        frame: &GroundTruthFrame,
+    ) -> std::io::Result<()> {
+        for object in &frame.objects {
+            let id = object.entity.to_bits();
+            let (world, ego, size) = (object.world, object.ego, object.size);
+            writeln!(
+                recorder.objects,
+                "{:.3},{},{},{:.3},{:.3},{:.3},{:.4},{:.3},{:.3},{:.3},{:.3},{:.3},{:.3},\
+                 {:.3},{:.3},{:.3},{:.4},{:.3},{:.3},{:.3}",
+                frame.time,
+                id,
+                object.class.name(),
+                world.center.x,
+                world.center.y,
+                world.center.z,
+                world.yaw,
+                size.x,
+                size.y,
+                size.z,
+                world.velocity.x,
+                world.velocity.y,
+                world.velocity.z,
+                ego.center.x,
+                ego.center.y,
+                ego.center.z,
+                ego.yaw,
+                ego.velocity.x,
+                ego.velocity.y,
+                ego.velocity.z
+            )?;
+            for image_box in &object.image_boxes {
+                let rect = image_box.rect;
+                writeln!(
+                    recorder.image_boxes,
+                    "{:.3},{},{},{:.1},{:.1},{:.1},{:.1},{}",
+                    frame.time,
+                    id,
+                    image_box.camera.to_bits(),
+                    rect.min.x,
+                    rect.min.y,
+                    rect.max.x,
+                    rect.max.y,
+                    image_box.truncated as u8
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn record_frames(
+        mut frames: MessageReader<GroundTruthFrame>,
+        recorder: Option<ResMut<GroundTruthRecorder>>,
+    ) {
+        let Some(mut recorder) = recorder else {
+            frames.clear();
+            return;
+        };
+        for frame in frames.read() {
+            if let Err(e) = write_frame(&mut recorder, frame) {
+                error!("Failed to record ground truth: {}", e);
+                return;
+            }
+        }
+    }
+}
@@ -0,0 +1,213 @@
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use crate::car_dynamics::{Car, EgoState, bicycle_kinematic_model};
+use crate::faults::{FaultTarget, Faults};
+use crate::utils::{SplitMix64, normalize_angle};
+
+pub struct ImuPlugin;
+
+impl Plugin for ImuPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_message::<ImuSample>()
+            .add_systems(Update, sample_imus.after(bicycle_kinematic_model));
+        #[cfg(not(target_arch = "wasm32"))]
+        app.add_systems(Startup, recording::setup_recorder)
+            .add_systems(Update, recording::record_samples.after(sample_imus));
+    }
+}
+
+const GRAVITY: f32 = 9.8; // m/s², as set for the physics
+
+/// Accelerometer and gyro in the vehicle frame (x forward, y left, z up), sampled from
+/// the change in the vehicle state since the last sample.
+///
+/// Each axis reads the true value plus a bias and white noise. Biases start from a
+/// random turn-on value and drift as a random walk.
+#[derive(Component, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct Imu {
+    pub enabled: bool,
+    pub rate: f32,              // Hz, at most one sample per frame
+    pub accel_noise: f32,       // m/s², standard deviation
+    pub accel_bias: f32,        // m/s², standard deviation of the turn-on bias
+    pub accel_random_walk: f32, // m/s²/√s, bias drift
+    pub gyro_noise: f32,        // rad/s, standard deviation
+    pub gyro_bias: f32,         // rad/s, standard deviation of the turn-on bias
+    pub gyro_random_walk: f32,  // rad/s/√s, bias drift
+    pub seed: u64,
+    #[serde(skip)]
+    next_sample: f32, // s since startup
+    #[serde(skip)]
+    rng: Option<SplitMix64>,
+    #[serde(skip)]
+    previous: Option<(f32, f32, f32)>, // s since startup, with the yaw and speed then
+    /// Current biases, ground truth for evaluating estimators.
+    #[serde(skip)]
+    pub biases: Option<(Vec3, Vec3)>, // accelerometer and gyro
+    #[serde(skip)]
+    pub last_sample: Option<ImuSample>,
+}
+
+impl Default for Imu {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            rate: 100.0,
+            accel_noise: 0.05,
+            accel_bias: 0.05,
+            accel_random_walk: 0.002,
+            gyro_noise: 0.002,
+            gyro_bias: 0.001,
+            gyro_random_walk: 0.0001,
+            seed: 0,
+            next_sample: 0.0,
+            rng: None,
+            previous: None,
+            biases: None,
+            last_sample: None,
+        }
+    }
+}
+
+impl Imu {
+    /// IMU fitted to the car but switched off.
+    pub fn disabled() -> Self {
+        Self {
+            enabled: false,
+            ..default()
+        }
+    }
+}
+
+/// One IMU measurement.
+#[derive(Message, Clone, Copy, Debug)]
+pub struct ImuSample {
+    #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+    pub time: f32,              // s since startup
+    pub acceleration: Vec3,     // m/s², specific force, reads +g on z at rest
+    pub angular_velocity: Vec3, // rad/s
+}
+
+fn gaussian3(rng: &mut SplitMix64) -> Vec3 {
+    Vec3::new(rng.gaussian(), rng.gaussian(), rng.gaussian())
+}
+
+fn sample_imus(
+    mut imus: Query<(&mut Imu, &EgoState), With<Car>>,
+    mut samples: MessageWriter<ImuSample>,
+    mut faults: ResMut<Faults>,
+    time: Res<Time>,
+) {
+    let now = time.elapsed_secs();
+    for (mut imu, state) in &mut imus {
+        if !imu.enabled {
+            imu.previous = None;
+            continue;
+        }
+        let Some((then, yaw, speed)) = imu.previous.filter(|(then, _, _)| now > *then) else {
+            imu.previous = Some((now, state.yaw, state.v));
+            continue;
+        };
+        if now < imu.next_sample {
+            continue;
+        }
+        imu.next_sample = now + 1.0 / imu.rate.max(0.1);
+        let seed = imu.seed;
+        let mut rng = imu.rng.take().unwrap_or(SplitMix64::new(seed));
+        let dt = now - then;
+
+        // Mean rates over the interval, as an integrating IMU measures them
+        let yaw_rate = normalize_angle(state.yaw - yaw) / dt;
+        let mean_speed = (state.v + speed) / 2.0;
+        let acceleration = Vec3::new((state.v - speed) / dt, mean_speed * yaw_rate, GRAVITY);
+        let angular_velocity = Vec3::new(0.0, 0.0, yaw_rate);
+
+        let (mut accel_bias, mut gyro_bias) = imu.biases.unwrap_or_else(|| {
+            (
+                gaussian3(&mut rng) * imu.accel_bias,
+                gaussian3(&mut rng) * imu.gyro_bias,
+            )
+        });
+        accel_bias += gaussian3(&mut rng) * imu.accel_random_walk * dt.sqrt();
+        gyro_bias += gaussian3(&mut rng) * imu.gyro_random_walk * dt.sqrt();
+
+        let sample = ImuSample {
+            time: now,
+            acceleration: acceleration + accel_bias + gaussian3(&mut rng) * imu.accel_noise,
+            angular_velocity: angular_velocity + gyro_bias + gaussian3(&mut rng) * imu.gyro_noise,
+        };
+        imu.rng = Some(rng);
+        imu.biases = Some((accel_bias, gyro_bias));
+        imu.previous = Some((now, state.yaw, state.v));
+        let Some(sample) = faults
+            .apply(FaultTarget::Accelerometer, sample, now, |sample, error| {
+                sample.acceleration += Vec3::new(error(), error(), error());
+            })
+            .and_then(|sample| {
+                faults.apply(FaultTarget::Gyroscope, sample, now, |sample, error| {
+                    sample.angular_velocity += Vec3::new(error(), error(), error());
+                })
+            })
+        else {
+            continue;
+        };
+        imu.last_sample = Some(sample);
+        samples.write(sample);
+    }
+}
+
+/// Samples saved as CSV with `--record-imu <file>`.
+#[cfg(not(target_arch = "wasm32"))]
+mod recording {
+    use bevy::prelude::*;
+    use std::fs::File;
+    use std::io::{BufWriter, Write};
+
+    use super::ImuSample;
+    use crate::utils::launch_argument;
+
+    #[derive(Resource)]
+    pub struct ImuRecorder(BufWriter<File>);
+
+    pub fn setup_recorder(mut commands: Commands) {
+        let Some(path) = launch_argument("record-imu") else {
+            return;
+        };
+        let mut file = match File::create(&path) {
+            Ok(file) => BufWriter::new(file),
+            Err(e) => {
+                error!("Cannot record IMU samples to {}: {}", path, e);
+                return;
+            }
+        };
+        if let Err(e) = writeln!(file, "time,ax,ay,az,wx,wy,wz") {
+            error!("Failed to write {}: {}", path, e);
+            return;
+        }
+        info!("Recording IMU samples to {}", path);
+        commands.insert_resource(ImuRecorder(file));
+    }
+
+    pub fn record_samples(
+        mut samples: MessageReader<ImuSample>,
+        recorder: Option<ResMut<ImuRecorder>>,
+    ) {
+        let Some(mut recorder) = recorder else {
+            samples.clear();
+            return;
+        };
+        for sample in samples.read() {
+            let (a, w) = (sample.acceleration, sample.angular_velocity);
+            let row = writeln!(
+                recorder.0,
+                "{:.4},{:.4},{:.4},{:.4},{:.5},{:.5},{:.5}",
+                sample.time, a.x, a.y, a.z, w.x, w.y, w.z
+            );
+            if let Err(e) = row {
+                error!("Failed to record IMU samples: {}", e);
+                return;
+            }
+        }
+    }
+}
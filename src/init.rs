@@ -1,10 +1,18 @@
 use avian3d::prelude::*;
 use bevy::color::palettes::css::*;
-use bevy::color::palettes::tailwind::GRAY_100;
 use bevy::prelude::*;
 use std::f32::consts::PI;
 
+use crate::camera_sensor::CameraSensor;
 use crate::car_dynamics::*;
+use crate::controller::{ControlReference, VehicleController};
+use crate::gnss::Gnss;
+use crate::imu::Imu;
+use crate::lidar::Lidar;
+use crate::occupancy::OccupancyGrid;
+use crate::radar::Radar;
+use crate::ultrasonic::Ultrasonic;
+use crate::vehicle_bus::VehicleBus;
 
 pub struct InitPlugin;
 
@@ -12,8 +20,7 @@ impl Plugin for InitPlugin {
     fn build(&self, app: &mut App) {
         app.insert_resource(Gravity(Vec3::NEG_Z * 9.8))
             .add_systems(Startup, setup_gizmos)
-            .add_systems(Startup, spawn_ego)
-            .add_systems(Startup, spawn_obstacle);
+            .add_systems(Startup, spawn_ego);
     }
 }
 
@@ -37,19 +44,56 @@ fn setup_gizmos(mut commands: Commands, mut gizmo_assets: ResMut<Assets<GizmoAss
 }
 
 fn spawn_ego(mut commands: Commands, asset_server: Res<AssetServer>) {
-    let car = asset_server.load("car-race.glb#Scene0");
-    let wheel = asset_server.load("wheel.glb#Scene0");
+    let car = spawn_car(&mut commands, &asset_server, EgoState::default());
+    commands.entity(car).insert((
+        Ego,
+        ControlReference::default(),
+        VehicleController::default(),
+        // Off until switched on in the panel or fitted by a scenario
+        Lidar::disabled(),
+        Radar::disabled(),
+        CameraSensor::disabled(),
+        Imu::disabled(),
+        Gnss::disabled(),
+        VehicleBus::disabled(),
+        Ultrasonic::disabled(),
+        OccupancyGrid::disabled(),
+    ));
+}
 
-    // Config
+/// Spawns a car with wheels at `state`, moved by the bicycle model.
+pub fn spawn_car(commands: &mut Commands, asset_server: &AssetServer, state: EgoState) -> Entity {
     // Manual collider for SceneRoot (scene-based colliders not yet supported)
     // These dimensions match the visual car model
-    let car_collider = Collider::cuboid(2., 1.0, 5.0);
+    let car_collider = Collider::cuboid(2., 1.0, CAR_LENGTH);
 
     // Ground is at z=0, wheels are at relative y=-0.25
     // Car height = wheel_radius (0.34) + wheel_offset (0.25) to position wheels on ground
-    let car_height = 0.64;
-    let car_transform = Transform::from_translation(Vec3::new(0.0, 0.0, car_height))
-        .with_rotation(Quat::from_rotation_z(PI / 2.0) * Quat::from_rotation_x(PI / 2.0));
+    let car_transform = Transform::from_translation(Vec3::new(state.x, state.y, RIDE_HEIGHT))
+        .with_rotation(
+            Quat::from_rotation_z(state.yaw + PI / 2.0) * Quat::from_rotation_x(PI / 2.0),
+        );
+
+    let car_entity = spawn_car_model(commands, asset_server, car_transform);
+    commands.entity(car_entity).insert((
+        Car,
+        RigidBody::Kinematic,
+        car_collider,
+        TransformInterpolation,
+        EgoControl::default(),
+        state,
+    ));
+    car_entity
+}
+
+/// Spawns the car body and wheel models at `transform`, without physics.
+pub fn spawn_car_model(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    transform: Transform,
+) -> Entity {
+    let car = asset_server.load("car-race.glb#Scene0");
+    let wheel = asset_server.load("wheel.glb#Scene0");
 
     let wheel_scale = Vec3::new(0.7, 0.34, 0.7);
     let wheels = [
@@ -63,18 +107,7 @@ fn spawn_ego(mut commands: Commands, asset_server: Res<AssetServer>) {
         (Vec3::new(-0.5, -0.25, -1.3), Quat::from_axis_angle(Vec3::Z, PI / 2.0), false),
     ];
 
-    let car_entity = commands
-        .spawn((
-            Car,
-            RigidBody::Kinematic,
-            car_collider,
-            TransformInterpolation,
-            SceneRoot(car),
-            car_transform,
-            EgoControl::default(),
-            EgoState::default(),
-        ))
-        .id();
+    let car_entity = commands.spawn((SceneRoot(car), transform)).id();
 
     for (pos, rot, is_steering) in wheels {
         let init_rotation = InitWheelRotation(rot);
@@ -92,46 +125,7 @@ fn spawn_ego(mut commands: Commands, asset_server: Res<AssetServer>) {
         let wheel_entity = entity.id();
         commands.entity(car_entity).add_child(wheel_entity);
     }
-}
-
-#[derive(Component)]
-struct Obstacle;
-
-#[derive(Component)]
-struct GroundPlane;
-
-fn spawn_obstacle(
-    mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-) {
-
-    let obstacle_pos = Vec3::new(10.0, 0.0, 1.0);
-    let obstacle_collider = Collider::cuboid(5.0, 2.0, 1.0); // Match car dimensions
-    let obstacle_density = ColliderDensity(100.0);
-
-    commands.spawn((
-        Obstacle,
-        RigidBody::Dynamic,
-        obstacle_collider,
-        obstacle_density,
-        Mesh3d(meshes.add(Cuboid::new(5.0, 2.0, 1.0))),
-        MeshMaterial3d(materials.add(StandardMaterial::from_color(RED))),
-        Transform::from_translation(obstacle_pos),
-    ));
-
-    // Ground config - rectangular grid
-    let ground_size = (200.0, 200.0); // width, height
-    let ground_pos = Vec3::new(0.0, 0.0, 0.0);
-
-    commands.spawn((
-        GroundPlane,
-        RigidBody::Static,
-        ColliderConstructor::TrimeshFromMesh,
-        Mesh3d(meshes.add(Plane3d::new(Vec3::Z, Vec2::new(ground_size.0, ground_size.1)))),
-        MeshMaterial3d(materials.add(StandardMaterial::from_color(GRAY_100))),
-        Transform::from_translation(ground_pos),
-    ));
+    car_entity
 }
 
 fn draw_ground_grid(gizmos: &mut GizmoAsset) {
@@ -1,7 +1,10 @@
 use bevy::prelude::*;
 use bevy_panorbit_camera::PanOrbitCamera;
 
-use crate::car_dynamics::{Car, EgoControl, EgoState, MAX_STEERING_ANGLE, STEER_RATIO};
+use crate::car_dynamics::{
+    ControlSystems, Ego, EgoControl, EgoState, Gear, MAX_STEERING_ANGLE, STEER_RATIO,
+};
+use crate::controller::{ControlReference, Controller};
 use crate::utils::normalize_angle;
 
 use std::f32::consts::PI;
@@ -10,13 +13,31 @@ pub struct InputPlugin;
 
 impl Plugin for InputPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Update, keyboard_system);
-        app.add_systems(Update, gamepad_system);
+        app.add_systems(
+            Update,
+            (keyboard_system, gamepad_system).in_set(ControlSystems::Driver),
+        );
+    }
+}
+
+/// Passes the keyboard or gamepad demand straight through.
+pub struct HumanDriver;
+
+impl Controller for HumanDriver {
+    fn name(&self) -> &'static str {
+        "Human"
+    }
+
+    fn update(&mut self, _state: &EgoState, reference: &ControlReference, _dt: f32) -> EgoControl {
+        reference.driver
     }
 }
 
+// gear can only be changed when nearly stopped
+const GEAR_CHANGE_SPEED: f32 = 0.5;
+
 // max wheel speed according to speed
-fn get_steering_rate(v: f32) -> f32 {
+pub fn get_steering_rate(v: f32) -> f32 {
     let omega_min = 2.0; // rad/s
     let omega0 = 12.0; // rad/s
     let lambda = 0.3;
@@ -34,19 +55,20 @@ fn get_steering_angle(v: f32, front_wheel_angle: f32, ratio: f32, delta_time: f3
 
 fn gamepad_system(
     gamepad: Single<&Gamepad>,
-    mut query: Single<&mut EgoControl, With<Car>>,
-    ego_state: Single<&EgoState, With<Car>>,
+    mut reference: Single<&mut ControlReference, With<Ego>>,
+    ego_state: Single<&EgoState, With<Ego>>,
     mut camera: Single<&mut PanOrbitCamera>,
     time: Res<Time>,
 ) {
+    let driver = &mut reference.driver;
     const EPS: f32 = 0.01;
     let right_trigger = gamepad.get(GamepadButton::RightTrigger2).unwrap();
     if right_trigger > EPS {
-        query.throttle = right_trigger.clamp(0.0, 1.0);
+        driver.throttle = right_trigger.clamp(0.0, 1.0);
     }
     let left_trigger = gamepad.get(GamepadButton::LeftTrigger2).unwrap();
     if left_trigger > EPS {
-        query.brake = left_trigger.clamp(0.0, 1.0);
+        driver.brake = left_trigger.clamp(0.0, 1.0);
     }
 
     let left_stick_x = gamepad
@@ -56,9 +78,9 @@ fn gamepad_system(
 
     let ratio = -1.0 * left_stick_x;
 
-    (query.front_wheel_angle, query.steer_wheel_angle) = get_steering_angle(
+    (driver.front_wheel_angle, driver.steer_wheel_angle) = get_steering_angle(
         ego_state.v,
-        query.front_wheel_angle,
+        driver.front_wheel_angle,
         ratio,
         time.delta_secs(),
     );
@@ -81,29 +103,40 @@ fn gamepad_system(
 
 fn keyboard_system(
     key: Res<ButtonInput<KeyCode>>,
-    mut query: Single<&mut EgoControl, With<Car>>,
-    ego_state: Single<&EgoState, With<Car>>,
+    mut reference: Single<&mut ControlReference, With<Ego>>,
+    ego_state: Single<&EgoState, With<Ego>>,
     mut camera: Single<&mut PanOrbitCamera>,
     time: Res<Time>,
 ) {
+    let driver = &mut reference.driver;
     if key.pressed(KeyCode::KeyW) {
-        query.throttle = 1.0;
+        driver.throttle = 1.0;
     } else {
-        query.throttle = 0.0;
+        driver.throttle = 0.0;
     }
     if key.pressed(KeyCode::KeyS) {
-        query.brake = 1.0;
+        driver.brake = 1.0;
     } else {
-        query.brake = 0.0;
+        driver.brake = 0.0;
+    }
+    if key.just_pressed(KeyCode::KeyR) && ego_state.v.abs() < GEAR_CHANGE_SPEED {
+        driver.gear = match driver.gear {
+            Gear::Drive => Gear::Reverse,
+            Gear::Reverse => Gear::Drive,
+        };
     }
     if key.pressed(KeyCode::KeyA) {
-        (query.front_wheel_angle, query.steer_wheel_angle) =
-            get_steering_angle(ego_state.v, query.front_wheel_angle, 1.0, time.delta_secs());
+        (driver.front_wheel_angle, driver.steer_wheel_angle) = get_steering_angle(
+            ego_state.v,
+            driver.front_wheel_angle,
+            1.0,
+            time.delta_secs(),
+        );
     }
     if key.pressed(KeyCode::KeyD) {
-        (query.front_wheel_angle, query.steer_wheel_angle) = get_steering_angle(
+        (driver.front_wheel_angle, driver.steer_wheel_angle) = get_steering_angle(
             ego_state.v,
-            query.front_wheel_angle,
+            driver.front_wheel_angle,
             -1.0,
             time.delta_secs(),
         );
@@ -0,0 +1,352 @@
+use avian3d::prelude::*;
+use bevy::color::palettes::css::{WHITE, YELLOW};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::car_dynamics::Ego;
+use crate::run_log::RunLog;
+use crate::utils::launch_argument;
+
+pub struct LapTimerPlugin;
+
+impl Plugin for LapTimerPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LapTimer>()
+            .add_message::<LapCompleted>()
+            .add_systems(Startup, load_leaderboard)
+            .add_systems(Update, (draw_checkpoints, (time_laps, record_laps).chain()));
+    }
+}
+
+const GATE_LENGTH: f32 = 1.0; // m, along the direction of travel
+const GATE_HEIGHT: f32 = 3.0; // m
+const LEADERBOARD_SIZE: usize = 10; // entries kept per course
+#[cfg(not(target_arch = "wasm32"))]
+const LEADERBOARD_FILE: &str = "leaderboard.ron";
+#[cfg(target_arch = "wasm32")]
+const LEADERBOARD_KEY: &str = "sim.leaderboard";
+
+/// Gate of a lap, placed in a scenario's `checkpoints`; the first is the start/finish line.
+#[derive(Deserialize, Clone, Copy)]
+pub struct CheckpointSpec {
+    pub x: f32,
+    pub y: f32,
+    /// Direction of travel through the gate.
+    pub yaw: f32,
+    #[serde(default = "default_gate_width")]
+    pub width: f32,
+}
+
+fn default_gate_width() -> f32 {
+    10.0
+}
+
+/// Sensor volume the ego has to pass, in `index` order, to complete a lap.
+#[derive(Component)]
+pub struct Checkpoint {
+    pub index: usize,
+    pub width: f32,
+}
+
+#[derive(Clone, Debug)]
+pub struct Lap {
+    pub time: f32,         // s
+    pub sectors: Vec<f32>, // s, between consecutive checkpoints
+}
+
+/// Lap and sector times on the current course.
+#[derive(Resource, Default)]
+pub struct LapTimer {
+    pub course: String,
+    gates: Vec<Entity>,
+    next: usize,            // index of the gate expected next
+    lap_start: Option<f32>, // s since startup, none before the first start line crossing
+    sector_start: f32,      // s since startup
+    pub sectors: Vec<f32>,  // s, splits of the lap in progress
+    pub laps: usize,        // completed
+    pub last_lap: Option<Lap>,
+    pub best_lap: Option<Lap>,
+    pub best_sectors: Vec<Option<f32>>,
+}
+
+impl LapTimer {
+    /// Current lap time, if a lap is in progress.
+    pub fn current(&self, now: f32) -> Option<f32> {
+        self.lap_start.map(|start| now - start)
+    }
+
+    pub fn sector_count(&self) -> usize {
+        self.gates.len()
+    }
+
+    // Returns the lap and whether it is the best so far
+    fn complete_lap(&mut self, now: f32) -> (Lap, bool) {
+        let lap = Lap {
+            time: now - self.lap_start.unwrap_or(now),
+            sectors: std::mem::take(&mut self.sectors),
+        };
+        for (best, &sector) in self.best_sectors.iter_mut().zip(&lap.sectors) {
+            if best.is_none_or(|best| sector < best) {
+                *best = Some(sector);
+            }
+        }
+        let best = self
+            .best_lap
+            .as_ref()
+            .is_none_or(|best| lap.time < best.time);
+        if best {
+            self.best_lap = Some(lap.clone());
+        }
+        self.last_lap = Some(lap.clone());
+        self.laps += 1;
+        (lap, best)
+    }
+}
+
+/// Spawns the gates of a lap and restarts timing on the new course. Returns the gate
+/// entities so the caller can tag them for cleanup.
+pub fn spawn_checkpoints(
+    commands: &mut Commands,
+    course: impl Into<String>,
+    checkpoints: &[CheckpointSpec],
+) -> Vec<Entity> {
+    let gates: Vec<Entity> = checkpoints
+        .iter()
+        .enumerate()
+        .map(|(index, checkpoint)| {
+            commands
+                .spawn((
+                    Checkpoint {
+                        index,
+                        width: checkpoint.width,
+                    },
+                    RigidBody::Static,
+                    Sensor,
+                    CollisionEventsEnabled,
+                    Collider::cuboid(GATE_LENGTH, checkpoint.width, GATE_HEIGHT),
+                    Transform::from_xyz(checkpoint.x, checkpoint.y, GATE_HEIGHT / 2.0)
+                        .with_rotation(Quat::from_rotation_z(checkpoint.yaw)),
+                ))
+                .id()
+        })
+        .collect();
+    commands.insert_resource(LapTimer {
+        course: course.into(),
+        gates: gates.clone(),
+        best_sectors: vec![None; gates.len()],
+        ..default()
+    });
+    gates
+}
+
+#[derive(Message, Clone, Debug)]
+pub struct LapCompleted {
+    pub course: String,
+    pub lap: Lap,
+    pub best: bool,
+}
+
+fn time_laps(
+    mut collisions: MessageReader<CollisionStart>,
+    ego: Single<Entity, With<Ego>>,
+    checkpoints: Query<&Checkpoint>,
+    mut timer: ResMut<LapTimer>,
+    mut completed: MessageWriter<LapCompleted>,
+    mut run_log: ResMut<RunLog>,
+    time: Res<Time>,
+) {
+    let now = time.elapsed_secs();
+    for collision in collisions.read() {
+        let gate = if collision.body1 == Some(*ego) {
+            collision.collider2
+        } else if collision.body2 == Some(*ego) {
+            collision.collider1
+        } else {
+            continue;
+        };
+        // Gates of an earlier course are left alone
+        if !timer.gates.contains(&gate) {
+            continue;
+        }
+        let Ok(checkpoint) = checkpoints.get(gate) else {
+            continue;
+        };
+        let gate_count = timer.gates.len();
+
+        if timer.lap_start.is_none() {
+            if checkpoint.index == 0 {
+                timer.lap_start = Some(now);
+                timer.sector_start = now;
+                timer.next = 1 % gate_count;
+            }
+            continue;
+        }
+        if checkpoint.index != timer.next {
+            // Back at the start line with gates missed, the lap does not count
+            if checkpoint.index == 0 {
+                run_log.record(now, "Lap", "Checkpoint missed, lap not counted");
+                timer.lap_start = Some(now);
+                timer.sector_start = now;
+                timer.sectors.clear();
+                timer.next = 1 % gate_count;
+            }
+            continue;
+        }
+
+        let split = now - timer.sector_start;
+        timer.sectors.push(split);
+        timer.sector_start = now;
+        timer.next = (checkpoint.index + 1) % gate_count;
+        if checkpoint.index == 0 {
+            let (lap, best) = timer.complete_lap(now);
+            timer.lap_start = Some(now);
+            completed.write(LapCompleted {
+                course: timer.course.clone(),
+                lap,
+                best,
+            });
+        }
+    }
+}
+
+fn draw_checkpoints(
+    mut gizmos: Gizmos,
+    checkpoints: Query<(Entity, &Checkpoint, &Transform)>,
+    timer: Res<LapTimer>,
+) {
+    const Z: f32 = 0.04; // above the lane markings
+    for (entity, checkpoint, transform) in &checkpoints {
+        if !timer.gates.contains(&entity) {
+            continue;
+        }
+        let half = transform.rotation * Vec3::Y * checkpoint.width / 2.0;
+        let center = transform.translation.truncate().extend(Z);
+        let color = if checkpoint.index == 0 { WHITE } else { YELLOW };
+        gizmos.line(center - half, center + half, color);
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct LeaderboardEntry {
+    pub course: String,
+    pub driver: String,
+    pub time: f32,         // s
+    pub sectors: Vec<f32>, // s
+}
+
+/// Best laps per course, persisted across runs.
+///
+/// Stored in `leaderboard.ron` in the working directory, or in the browser's
+/// localStorage on the web. Laps are entered under the `--driver <name>` launch
+/// argument (`?driver=<name>` on the web).
+#[derive(Resource, Serialize, Deserialize, Default)]
+pub struct Leaderboard {
+    #[serde(skip)]
+    pub driver: String,
+    entries: Vec<LeaderboardEntry>,
+}
+
+impl Leaderboard {
+    /// Best laps on `course`, fastest first.
+    pub fn course(&self, course: &str) -> impl Iterator<Item = &LeaderboardEntry> {
+        self.entries
+            .iter()
+            .filter(move |entry| entry.course == course)
+    }
+
+    /// Enters a lap, returning its place on the course if it made the board.
+    pub fn submit(&mut self, course: &str, lap: &Lap) -> Option<usize> {
+        let place = self
+            .course(course)
+            .take_while(|entry| entry.time <= lap.time)
+            .count();
+        if place >= LEADERBOARD_SIZE {
+            return None;
+        }
+        let index = self
+            .entries
+            .iter()
+            .position(|entry| entry.course == course && entry.time > lap.time)
+            .unwrap_or(self.entries.len());
+        self.entries.insert(
+            index,
+            LeaderboardEntry {
+                course: course.to_string(),
+                driver: self.driver.clone(),
+                time: lap.time,
+                sectors: lap.sectors.clone(),
+            },
+        );
+        // Drop the entry pushed off the board
+        if let Some(last) = self
+            .entries
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| entry.course == course)
+            .nth(LEADERBOARD_SIZE)
+            .map(|(i, _)| i)
+        {
+            self.entries.remove(last);
+        }
+        Some(place + 1)
+    }
+
+    fn load() -> Option<Self> {
+        #[cfg(not(target_arch = "wasm32"))]
+        let text = std::fs::read_to_string(LEADERBOARD_FILE).ok()?;
+        #[cfg(target_arch = "wasm32")]
+        let text = web_sys::window()?
+            .local_storage()
+            .ok()??
+            .get_item(LEADERBOARD_KEY)
+            .ok()??;
+        ron::from_str(&text)
+            .inspect_err(|e| warn!("Ignoring unreadable leaderboard: {}", e))
+            .ok()
+    }
+
+    fn save(&self) {
+        let text = match ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default()) {
+            Ok(text) => text,
+            Err(e) => {
+                error!("Failed to serialize leaderboard: {}", e);
+                return;
+            }
+        };
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Err(e) = std::fs::write(LEADERBOARD_FILE, text) {
+            error!("Failed to save leaderboard: {}", e);
+        }
+        #[cfg(target_arch = "wasm32")]
+        if let Some(Ok(Some(storage))) = web_sys::window().map(|window| window.local_storage())
+            && storage.set_item(LEADERBOARD_KEY, &text).is_err()
+        {
+            error!("Failed to save leaderboard");
+        }
+    }
+}
+
+fn load_leaderboard(mut commands: Commands) {
+    let mut leaderboard = Leaderboard::load().unwrap_or_default();
+    leaderboard.driver = launch_argument("driver").unwrap_or_else(|| "Driver".to_string());
+    commands.insert_resource(leaderboard);
+}
+
+fn record_laps(
+    mut completed: MessageReader<LapCompleted>,
+    mut leaderboard: ResMut<Leaderboard>,
+    mut run_log: ResMut<RunLog>,
+    time: Res<Time>,
+) {
+    for LapCompleted { course, lap, best } in completed.read() {
+        let mut message = format!("{:.2} s", lap.time);
+        if *best {
+            message += " (best)";
+        }
+        if let Some(place) = leaderboard.submit(course, lap) {
+            leaderboard.save();
+            message += &format!(", #{} on the leaderboard", place);
+        }
+        run_log.record(time.elapsed_secs(), "Lap", message);
+    }
+}
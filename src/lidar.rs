@@ -0,0 +1,270 @@
+use avian3d::prelude::*;
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use crate::car_dynamics::{Car, EgoState};
+use crate::faults::{FaultTarget, Faults};
+use crate::sensor::Mount;
+use crate::utils::SplitMix64;
+
+pub struct LidarPlugin;
+
+impl Plugin for LidarPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_message::<LidarScan>()
+            .add_systems(Update, (scan_lidars, draw_lidar_points));
+        #[cfg(not(target_arch = "wasm32"))]
+        app.add_systems(Startup, recording::setup_recorder)
+            .add_systems(Update, recording::record_scans.after(scan_lidars));
+    }
+}
+
+/// Spinning multi-channel lidar sampling the world with ray casts.
+///
+/// Channels are spread evenly over the vertical field of view and every channel is cast
+/// once per horizontal step, all in the tick a scan is due.
+#[derive(Component, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct Lidar {
+    pub enabled: bool,
+    pub mount: Mount,
+    pub channels: u32,
+    pub vertical_fov: (f32, f32),   // deg, lowest and highest channel
+    pub horizontal_resolution: f32, // deg
+    pub range: f32,                 // m
+    pub rate: f32,                  // Hz, scans per second
+    pub range_noise: f32,           // m, standard deviation
+    pub dropout: f32,               // probability of a return being lost
+    pub seed: u64,
+    #[serde(skip)]
+    next_scan: f32, // s since startup
+    #[serde(skip)]
+    rng: Option<SplitMix64>,
+    /// World positions of the last scan's returns.
+    #[serde(skip)]
+    pub points: Vec<Vec3>,
+}
+
+impl Default for Lidar {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            mount: Mount::new(0.0, 0.0, 1.6),
+            channels: 16,
+            vertical_fov: (-15.0, 15.0),
+            horizontal_resolution: 1.0,
+            range: 100.0,
+            rate: 10.0,
+            range_noise: 0.02,
+            dropout: 0.0,
+            seed: 0,
+            next_scan: 0.0,
+            rng: None,
+            points: Vec::new(),
+        }
+    }
+}
+
+impl Lidar {
+    /// Lidar fitted to the car but switched off.
+    pub fn disabled() -> Self {
+        Self {
+            enabled: false,
+            ..default()
+        }
+    }
+
+    // Unit ray directions in the sensor frame with their channel, scan order
+    fn directions(&self) -> impl Iterator<Item = (u32, Vec3)> + '_ {
+        let (low, high) = self.vertical_fov;
+        let steps = (360.0 / self.horizontal_resolution.max(0.01)).round() as u32;
+        (0..steps).flat_map(move |step| {
+            let azimuth = (step as f32 * self.horizontal_resolution).to_radians();
+            (0..self.channels).map(move |channel| {
+                let elevation = if self.channels > 1 {
+                    low + (high - low) * channel as f32 / (self.channels - 1) as f32
+                } else {
+                    low
+                }
+                .to_radians();
+                let direction = Vec3::new(
+                    elevation.cos() * azimuth.cos(),
+                    elevation.cos() * azimuth.sin(),
+                    elevation.sin(),
+                );
+                (channel, direction)
+            })
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct LidarPoint {
+    pub position: Vec3, // m, in the sensor frame
+    #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+    pub channel: u32,
+}
+
+/// One complete scan, written for every lidar each time it finishes sampling.
+#[derive(Message, Clone, Debug)]
+pub struct LidarScan {
+    #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+    pub time: f32, // s since startup
+    pub sensor_to_world: Transform,
+    pub points: Vec<LidarPoint>,
+}
+
+fn scan_lidars(
+    mut lidars: Query<(Entity, &mut Lidar, &EgoState), With<Car>>,
+    sensors: Query<(), With<Sensor>>,
+    spatial_query: SpatialQuery,
+    mut scans: MessageWriter<LidarScan>,
+    mut faults: ResMut<Faults>,
+    time: Res<Time>,
+) {
+    let now = time.elapsed_secs();
+    for (car, mut lidar, state) in &mut lidars {
+        if !lidar.enabled || now < lidar.next_scan {
+            continue;
+        }
+        lidar.next_scan = now + 1.0 / lidar.rate.max(0.1);
+        let seed = lidar.seed;
+        let mut rng = lidar.rng.take().unwrap_or(SplitMix64::new(seed));
+
+        let pose = lidar.mount.world_pose(state);
+        // The car itself and trigger volumes don't reflect
+        let filter = SpatialQueryFilter::from_excluded_entities([car]);
+        let solid = |entity| !sensors.contains(entity);
+        let mut points = Vec::new();
+        for (channel, direction) in lidar.directions() {
+            let Ok(world_direction) = Dir3::new(pose.rotation * direction) else {
+                continue;
+            };
+            let Some(hit) = spatial_query.cast_ray_predicate(
+                pose.translation,
+                world_direction,
+                lidar.range,
+                true,
+                &filter,
+                &solid,
+            ) else {
+                continue;
+            };
+            if rng.next_f32() < lidar.dropout {
+                continue;
+            }
+            let distance = hit.distance + rng.gaussian() * lidar.range_noise;
+            points.push(LidarPoint {
+                position: direction * distance,
+                channel,
+            });
+        }
+
+        lidar.rng = Some(rng);
+        let scan = LidarScan {
+            time: now,
+            sensor_to_world: pose,
+            points,
+        };
+        let Some(scan) = faults.apply(FaultTarget::Lidar, scan, now, |scan, error| {
+            for point in &mut scan.points {
+                point.position += point.position.normalize_or_zero() * error();
+            }
+        }) else {
+            continue;
+        };
+        lidar.points = scan
+            .points
+            .iter()
+            .map(|point| scan.sensor_to_world.transform_point(point.position))
+            .collect();
+        scans.write(scan);
+    }
+}
+
+fn draw_lidar_points(mut gizmos: Gizmos, lidars: Query<&Lidar>) {
+    const TICK: f32 = 0.05; // m, drawn length of a point
+    for lidar in &lidars {
+        if !lidar.enabled {
+            continue;
+        }
+        for &point in &lidar.points {
+            // Coloured by height, blue at the ground to red 3 m up
+            let hue = 240.0 * (1.0 - (point.z / 3.0).clamp(0.0, 1.0));
+            gizmos.line(point, point + Vec3::Z * TICK, Color::hsl(hue, 1.0, 0.5));
+        }
+    }
+}
+
+/// Scans saved as ASCII PCD files, one per scan in the sensor frame, with
+/// `--record-lidar <dir>`.
+#[cfg(not(target_arch = "wasm32"))]
+mod recording {
+    use bevy::prelude::*;
+    use std::fmt::Write;
+    use std::path::PathBuf;
+
+    use super::LidarScan;
+    use crate::utils::launch_argument;
+
+    #[derive(Resource)]
+    pub struct LidarRecorder {
+        dir: PathBuf,
+        count: usize,
+    }
+
+    pub fn setup_recorder(mut commands: Commands) {
+        let Some(dir) = launch_argument("record-lidar") else {
+            return;
+        };
+        let dir = PathBuf::from(dir);
+        if let Err(e) = std::fs::create_dir_all(&dir) {
+            error!("Cannot record lidar scans to {}: {}", dir.display(), e);
+            return;
+        }
+        info!("Recording lidar scans to {}", dir.display());
+        commands.insert_resource(LidarRecorder { dir, count: 0 });
+    }
+
+    pub fn record_scans(
+        mut scans: MessageReader<LidarScan>,
+        recorder: Option<ResMut<LidarRecorder>>,
+    ) {
+        let Some(mut recorder) = recorder else {
+            scans.clear();
+            return;
+        };
+        for scan in scans.read() {
+            let path = recorder.dir.join(format!("scan_{:06}.pcd", recorder.count));
+            recorder.count += 1;
+            if let Err(e) = std::fs::write(&path, pcd(scan)) {
+                error!("Failed to write {}: {}", path.display(), e);
+            }
+        }
+    }
+
+    fn pcd(scan: &LidarScan) -> String {
+        let t = scan.sensor_to_world.translation;
+        let r = scan.sensor_to_world.rotation;
+        let n = scan.points.len();
+        let mut text = format!(
+            "# .PCD v0.7 - lidar scan at {:.3} s\n\
+             VERSION 0.7\n\
+             FIELDS x y z ring\n\
+             SIZE 4 4 4 2\n\
+             TYPE F F F U\n\
+             COUNT 1 1 1 1\n\
+             WIDTH {n}\n\
+             HEIGHT 1\n\
+             VIEWPOINT {} {} {} {} {} {} {}\n\
+             POINTS {n}\n\
+             DATA ascii\n",
+            scan.time, t.x, t.y, t.z, r.w, r.x, r.y, r.z
+        );
+        for point in &scan.points {
+            let p = point.position;
+            let _ = writeln!(text, "{:.3} {:.3} {:.3} {}", p.x, p.y, p.z, point.channel);
+        }
+        text
+    }
+}
@@ -0,0 +1,118 @@
+use bevy::prelude::*;
+
+use crate::car_dynamics::{
+    ControlSystems, Ego, EgoControl, EgoState, MAX_STEERING_ANGLE, STEER_RATIO,
+};
+use crate::controller::ControlReference;
+use crate::road::{LanePosition, RoadNetwork};
+use crate::run_log::RunLog;
+
+pub struct LkaPlugin;
+
+impl Plugin for LkaPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LkaConfig>()
+            .init_resource::<LkaStatus>()
+            .add_systems(Update, lane_keeping_assist.in_set(ControlSystems::Assist));
+    }
+}
+
+const CAR_HALF_WIDTH: f32 = 1.0;
+const MIN_SPEED: f32 = 5.0; // m/s
+// driver steering further or faster than these (front wheel) suppresses the assist, for
+// as long as it lasts and OVERRIDE_HOLD after
+const DRIVER_OVERRIDE_ANGLE: f32 = 0.02; // rad
+const DRIVER_OVERRIDE_RATE: f32 = 0.15; // rad/s
+const OVERRIDE_HOLD: f32 = 1.0; // s
+
+#[derive(Resource)]
+pub struct LkaConfig {
+    pub enabled: bool,
+    pub offset_gain: f32,    // rad per m
+    pub heading_gain: f32,   // rad per rad
+    pub max_correction: f32, // rad at the front wheel
+    pub max_rate: f32,       // rad/s at the front wheel
+    pub warning_margin: f32, // m from the lane boundary
+}
+
+impl Default for LkaConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            offset_gain: 0.03,
+            heading_gain: 0.4,
+            max_correction: 3.0f32.to_radians(),
+            max_rate: 5.0f32.to_radians(),
+            warning_margin: 0.2,
+        }
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct LkaStatus {
+    pub lane: Option<LanePosition>,
+    pub correction: f32, // rad at the front wheel
+    pub active: bool,
+    pub departure_warning: bool,
+    override_timer: f32,
+    prev_driver_angle: f32,
+}
+
+// The corrective torque is modelled as a bounded, rate limited front wheel angle
+// added on top of whatever the driver or controller commands.
+fn lane_keeping_assist(
+    car: Single<(&EgoState, &ControlReference, &mut EgoControl), With<Ego>>,
+    road: Res<RoadNetwork>,
+    config: Res<LkaConfig>,
+    mut status: ResMut<LkaStatus>,
+    mut run_log: ResMut<RunLog>,
+    time: Res<Time>,
+) {
+    let (state, reference, mut control) = car.into_inner();
+    let dt = time.delta_secs();
+
+    let driver_angle = reference.driver.front_wheel_angle;
+    let driver_rate = if dt > 0.0 {
+        (driver_angle - status.prev_driver_angle).abs() / dt
+    } else {
+        0.0
+    };
+    status.prev_driver_angle = driver_angle;
+    if driver_angle.abs() > DRIVER_OVERRIDE_ANGLE || driver_rate > DRIVER_OVERRIDE_RATE {
+        status.override_timer = OVERRIDE_HOLD;
+    } else {
+        status.override_timer = (status.override_timer - dt).max(0.0);
+    }
+
+    status.lane = road.localize(Vec2::new(state.x, state.y), state.yaw);
+    let Some(lane) = status.lane else {
+        // Off-road there is no lane to keep
+        status.correction = 0.0;
+        status.active = false;
+        status.departure_warning = false;
+        return;
+    };
+
+    let command = (-config.offset_gain * lane.offset - config.heading_gain * lane.heading_error)
+        .clamp(-config.max_correction, config.max_correction);
+
+    let clearance = lane.width / 2.0 - lane.offset.abs() - CAR_HALF_WIDTH;
+    let departing = config.enabled && state.v > MIN_SPEED && clearance < config.warning_margin;
+    if departing && !status.departure_warning {
+        run_log.record(
+            time.elapsed_secs(),
+            "LKA",
+            format!("Lane departure warning, offset {:.2} m", lane.offset),
+        );
+    }
+    status.departure_warning = departing;
+
+    status.active = config.enabled && state.v > MIN_SPEED && status.override_timer == 0.0;
+    let target = if status.active { command } else { 0.0 };
+    let max_step = config.max_rate * dt;
+    status.correction += (target - status.correction).clamp(-max_step, max_step);
+
+    control.front_wheel_angle = (control.front_wheel_angle + status.correction)
+        .clamp(-MAX_STEERING_ANGLE, MAX_STEERING_ANGLE);
+    control.steer_wheel_angle = control.front_wheel_angle * STEER_RATIO;
+}
@@ -5,12 +5,44 @@ use bevy::log::Level;
 use bevy::prelude::*;
 use bevy_egui::EguiPlugin;
 
+mod aeb;
 mod camera;
+mod camera_sensor;
 mod car_dynamics;
+mod controller;
+mod dbc;
+mod faults;
+mod ghost;
+mod gnss;
+mod ground_truth;
+mod imu;
 mod init;
+mod lap_timer;
+mod lidar;
+mod lka;
+mod mpc;
+mod occupancy;
+mod opendrive;
+mod openscenario;
 mod panel;
+mod parking;
+mod path;
+mod path_follower;
+mod radar;
+mod reeds_shepp;
+mod road;
+mod run_log;
+mod scenario;
+mod semantic;
+mod sensor;
 mod sound;
+mod storyboard;
+mod track;
+mod traffic;
+mod traffic_control;
+mod ultrasonic;
 mod utils;
+mod vehicle_bus;
 
 mod input;
 
@@ -61,11 +93,38 @@ fn main() {
         // )
         .add_plugins(EguiPlugin::default())
         .add_plugins(init::InitPlugin)
+        .add_plugins(scenario::ScenarioPlugin)
+        .add_plugins(openscenario::OpenScenarioPlugin)
+        .add_plugins(opendrive::OpenDrivePlugin)
+        .add_plugins(storyboard::StoryboardPlugin)
         .add_plugins(camera::CameraPlugin)
         .add_plugins(sound::SoundPlugin)
         .add_plugins(panel::PanelPlugin)
         .add_plugins(car_dynamics::CarDynamicsPlugin)
         .add_plugins(input::InputPlugin)
+        .add_plugins(controller::ControllerPlugin)
+        .add_plugins(run_log::RunLogPlugin)
+        .add_plugins(aeb::AebPlugin)
+        .add_plugins(road::RoadPlugin)
+        .add_plugins(lka::LkaPlugin)
+        .add_plugins(path_follower::PathFollowerPlugin)
+        .add_plugins(parking::ParkingPlugin)
+        .add_plugins(track::TrackPlugin)
+        .add_plugins(traffic::TrafficPlugin)
+        .add_plugins(traffic_control::TrafficControlPlugin)
+        .add_plugins(lap_timer::LapTimerPlugin)
+        .add_plugins(ghost::GhostPlugin)
+        .add_plugins(lidar::LidarPlugin)
+        .add_plugins(radar::RadarPlugin)
+        .add_plugins(camera_sensor::CameraSensorPlugin)
+        .add_plugins(imu::ImuPlugin)
+        .add_plugins(gnss::GnssPlugin)
+        .add_plugins(dbc::DbcPlugin)
+        .add_plugins(vehicle_bus::VehicleBusPlugin)
+        .add_plugins(ultrasonic::UltrasonicPlugin)
+        .add_plugins(ground_truth::GroundTruthPlugin)
+        .add_plugins(occupancy::OccupancyPlugin)
+        .add_plugins(faults::FaultsPlugin)
         // .add_plugins(usb_cam::UsbCamPlugin)
         .run();
 }
@@ -0,0 +1,362 @@
+use bevy::prelude::*;
+
+use crate::car_dynamics::{EgoState, MAX_ACCELERATION, MAX_STEERING_ANGLE, STEER_RATIO, WHEELBASE};
+use crate::input::get_steering_rate;
+use crate::path::Polyline;
+use crate::utils::normalize_angle;
+
+// Iterative LQR over the kinematic bicycle model used by `bicycle_kinematic_model`,
+// extended with the front wheel angle as a state so its rate can be bounded.
+const NX: usize = 5; // x, y, yaw, v, front wheel angle
+const NU: usize = 2; // front wheel angle rate, acceleration
+
+type State = [f32; NX];
+type Input = [f32; NU];
+type Mat<const R: usize, const C: usize> = [[f32; C]; R];
+
+const HORIZON: usize = 20;
+const STEP: f32 = 0.1; // s
+const MAX_ITERATIONS: usize = 5;
+const LINE_SEARCH: [f32; 4] = [1.0, 0.5, 0.25, 0.1];
+
+// Cost weights
+const W_POSITION: f32 = 1.0;
+const W_YAW: f32 = 2.0;
+const W_SPEED: f32 = 0.5;
+const W_STEER: f32 = 0.1;
+const R_STEER_RATE: f32 = 0.5;
+const R_ACCELERATION: f32 = 0.1;
+const TERMINAL_SCALE: f32 = 5.0;
+const INPUT_WEIGHTS: Input = [R_STEER_RATE, R_ACCELERATION];
+
+// Comfort limits for the reference speed profile
+const COMFORT_ACCELERATION: f32 = 2.0; // m/s^2
+const COMFORT_DECELERATION: f32 = 3.0; // m/s^2
+
+#[derive(Copy, Clone)]
+struct Reference {
+    position: Vec2,
+    yaw: f32,
+    v: f32,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct MpcCommand {
+    pub front_wheel_angle: f32,
+    pub acceleration: f32,
+}
+
+/// Receding horizon controller planning steering and acceleration jointly.
+#[derive(Default)]
+pub struct MpcSolver {
+    controls: Vec<Input>, // warm start from the previous tick
+    elapsed: f32,         // s since `controls` was last shifted, less than STEP
+    regularization: f32,
+    predicted: Vec<Vec2>,
+    steering: Option<f32>, // last commanded front wheel angle
+}
+
+impl MpcSolver {
+    /// Positions along the last optimised trajectory.
+    pub fn predicted(&self) -> &[Vec2] {
+        &self.predicted
+    }
+
+    pub fn reset(&mut self) {
+        self.controls.clear();
+        self.elapsed = 0.0;
+        self.predicted.clear();
+        self.steering = None;
+    }
+
+    pub fn solve(
+        &mut self,
+        state: &EgoState,
+        front_wheel_angle: f32,
+        path: &Polyline,
+        target_speed: f32,
+        dt: f32,
+    ) -> Option<MpcCommand> {
+        // Continue from our own last command, the measured angle is only the initial guess
+        let front_wheel_angle = self.steering.unwrap_or(front_wheel_angle);
+        let position = Vec2::new(state.x, state.y);
+        let nearest = path.project(position)?;
+        let references = reference_trajectory(path, nearest.s, state.v, target_speed);
+        let x0 = [state.x, state.y, state.yaw, state.v, front_wheel_angle];
+
+        // Shift the previous solution by the steps elapsed since as the initial guess,
+        // holding its last control over the end of the horizon
+        if self.controls.len() == HORIZON {
+            self.elapsed += dt;
+            let shift = ((self.elapsed / STEP) as usize).min(HORIZON);
+            self.elapsed %= STEP;
+            let last = self.controls[HORIZON - 1];
+            self.controls.drain(..shift);
+            self.controls.resize(HORIZON, last);
+        } else {
+            self.controls = vec![[0.0; NU]; HORIZON];
+            self.elapsed = 0.0;
+            self.regularization = 1e-3;
+        }
+        let (mut states, mut cost) = rollout(&x0, &mut self.controls, &references);
+
+        for _ in 0..MAX_ITERATIONS {
+            let (feedforward, feedback) =
+                backward_pass(&states, &self.controls, &references, self.regularization);
+            let mut improved = false;
+            for alpha in LINE_SEARCH {
+                let mut controls = self.controls.clone();
+                let mut x = x0;
+                let mut new_states = Vec::with_capacity(HORIZON + 1);
+                new_states.push(x);
+                for (k, u) in controls.iter_mut().enumerate() {
+                    let dx = state_difference(&x, &states[k]);
+                    let correction = mat_vec(&feedback[k], &dx);
+                    let delta_u =
+                        std::array::from_fn(|i| alpha * feedforward[k][i] + correction[i]);
+                    *u = clamp_input(&add_vec(u, &delta_u), x[3]);
+                    x = step(&x, u);
+                    new_states.push(x);
+                }
+                let new_cost = trajectory_cost(&new_states, &controls, &references);
+                if new_cost < cost {
+                    self.controls = controls;
+                    states = new_states;
+                    cost = new_cost;
+                    improved = true;
+                    break;
+                }
+            }
+            if improved {
+                self.regularization = (self.regularization * 0.5).max(1e-4);
+            } else {
+                self.regularization = (self.regularization * 10.0).min(1e2);
+                break;
+            }
+        }
+
+        self.predicted = states.iter().map(|x| Vec2::new(x[0], x[1])).collect();
+        let [steer_rate, acceleration] = self.controls[0];
+        let command = MpcCommand {
+            front_wheel_angle: (front_wheel_angle + steer_rate * dt)
+                .clamp(-MAX_STEERING_ANGLE, MAX_STEERING_ANGLE),
+            acceleration,
+        };
+        self.steering = Some(command.front_wheel_angle);
+        Some(command)
+    }
+}
+
+fn reference_trajectory(path: &Polyline, s0: f32, v0: f32, target_speed: f32) -> Vec<Reference> {
+    let mut references = Vec::with_capacity(HORIZON + 1);
+    let mut s = s0;
+    let mut v = v0.max(0.0);
+    for _ in 0..=HORIZON {
+        references.push(Reference {
+            position: path.sample(s),
+            yaw: path.heading_at(s),
+            v,
+        });
+        let next_v =
+            v + (target_speed - v).clamp(-COMFORT_DECELERATION * STEP, COMFORT_ACCELERATION * STEP);
+        s += 0.5 * (v + next_v) * STEP;
+        v = next_v;
+    }
+    references
+}
+
+fn step(x: &State, u: &Input) -> State {
+    let [px, py, yaw, v, delta] = *x;
+    [
+        px + v * yaw.cos() * STEP,
+        py + v * yaw.sin() * STEP,
+        yaw + v * delta.tan() / WHEELBASE * STEP,
+        v + u[1] * STEP,
+        (delta + u[0] * STEP).clamp(-MAX_STEERING_ANGLE, MAX_STEERING_ANGLE),
+    ]
+}
+
+fn jacobians(x: &State) -> (Mat<NX, NX>, Mat<NX, NU>) {
+    let [_, _, yaw, v, delta] = *x;
+    let mut a = identity::<NX>();
+    a[0][2] = -v * yaw.sin() * STEP;
+    a[0][3] = yaw.cos() * STEP;
+    a[1][2] = v * yaw.cos() * STEP;
+    a[1][3] = yaw.sin() * STEP;
+    a[2][3] = delta.tan() / WHEELBASE * STEP;
+    a[2][4] = v / (WHEELBASE * delta.cos().powi(2)) * STEP;
+    let mut b = [[0.0; NU]; NX];
+    b[3][1] = STEP;
+    b[4][0] = STEP;
+    (a, b)
+}
+
+// Steering rate follows the same speed dependent limit as the driver input
+fn clamp_input(u: &Input, v: f32) -> Input {
+    let max_rate = get_steering_rate(v) / STEER_RATIO;
+    [
+        u[0].clamp(-max_rate, max_rate),
+        u[1].clamp(-MAX_ACCELERATION, MAX_ACCELERATION),
+    ]
+}
+
+fn state_difference(a: &State, b: &State) -> State {
+    let mut d: State = std::array::from_fn(|i| a[i] - b[i]);
+    d[2] = normalize_angle(d[2]);
+    d
+}
+
+fn tracking_error(x: &State, r: &Reference) -> State {
+    [
+        x[0] - r.position.x,
+        x[1] - r.position.y,
+        normalize_angle(x[2] - r.yaw),
+        x[3] - r.v,
+        x[4],
+    ]
+}
+
+fn state_weights(terminal: bool) -> State {
+    let scale = if terminal { TERMINAL_SCALE } else { 1.0 };
+    [W_POSITION, W_POSITION, W_YAW, W_SPEED, W_STEER].map(|w| w * scale)
+}
+
+fn trajectory_cost(states: &[State], controls: &[Input], references: &[Reference]) -> f32 {
+    let mut cost = 0.0;
+    for (k, x) in states.iter().enumerate() {
+        let e = tracking_error(x, &references[k]);
+        let w = state_weights(k == HORIZON);
+        cost += (0..NX).map(|i| w[i] * e[i] * e[i]).sum::<f32>();
+    }
+    for u in controls {
+        cost += (0..NU).map(|i| INPUT_WEIGHTS[i] * u[i] * u[i]).sum::<f32>();
+    }
+    cost
+}
+
+fn rollout(x0: &State, controls: &mut [Input], references: &[Reference]) -> (Vec<State>, f32) {
+    let mut states = Vec::with_capacity(HORIZON + 1);
+    let mut x = *x0;
+    states.push(x);
+    for u in controls.iter_mut() {
+        *u = clamp_input(u, x[3]);
+        x = step(&x, u);
+        states.push(x);
+    }
+    let cost = trajectory_cost(&states, controls, references);
+    (states, cost)
+}
+
+fn backward_pass(
+    states: &[State],
+    controls: &[Input],
+    references: &[Reference],
+    regularization: f32,
+) -> (Vec<Input>, Vec<Mat<NU, NX>>) {
+    let mut feedforward = vec![[0.0; NU]; HORIZON];
+    let mut feedback = vec![[[0.0; NX]; NU]; HORIZON];
+
+    // Quadratic value function at the end of the horizon
+    let w = state_weights(true);
+    let e = tracking_error(&states[HORIZON], &references[HORIZON]);
+    let mut vx: State = std::array::from_fn(|i| 2.0 * w[i] * e[i]);
+    let mut vxx = diagonal(&w.map(|w| 2.0 * w));
+
+    for k in (0..HORIZON).rev() {
+        let (a, b) = jacobians(&states[k]);
+        let at = transpose(&a);
+        let bt = transpose(&b);
+        let w = state_weights(false);
+        let e = tracking_error(&states[k], &references[k]);
+        let u = controls[k];
+
+        let qx = add_vec(
+            &std::array::from_fn(|i| 2.0 * w[i] * e[i]),
+            &mat_vec(&at, &vx),
+        );
+        let qu = add_vec(
+            &std::array::from_fn(|i| 2.0 * INPUT_WEIGHTS[i] * u[i]),
+            &mat_vec(&bt, &vx),
+        );
+        let qxx = add_mat(
+            &diagonal(&w.map(|w| 2.0 * w)),
+            &mat_mul(&at, &mat_mul(&vxx, &a)),
+        );
+        let mut quu = add_mat(
+            &diagonal(&INPUT_WEIGHTS.map(|w| 2.0 * w)),
+            &mat_mul(&bt, &mat_mul(&vxx, &b)),
+        );
+        for (i, row) in quu.iter_mut().enumerate() {
+            row[i] += regularization;
+        }
+        let qux = mat_mul(&bt, &mat_mul(&vxx, &a));
+
+        let quu_inv = inverse2(&quu);
+        let kff = mat_vec(&quu_inv, &qu).map(|x| -x);
+        let kfb = mat_mul(&quu_inv, &qux).map(|row| row.map(|x| -x));
+
+        let kt = transpose(&kfb);
+        let quxt = transpose(&qux);
+        vx = add_vec(
+            &add_vec(&qx, &mat_vec(&kt, &mat_vec(&quu, &kff))),
+            &add_vec(&mat_vec(&kt, &qu), &mat_vec(&quxt, &kff)),
+        );
+        vxx = add_mat(
+            &add_mat(&qxx, &mat_mul(&kt, &mat_mul(&quu, &kfb))),
+            &add_mat(&mat_mul(&kt, &qux), &mat_mul(&quxt, &kfb)),
+        );
+        vxx = symmetrize(&vxx);
+
+        feedforward[k] = kff;
+        feedback[k] = kfb;
+    }
+    (feedforward, feedback)
+}
+
+fn identity<const N: usize>() -> Mat<N, N> {
+    diagonal(&[1.0; N])
+}
+
+fn diagonal<const N: usize>(d: &[f32; N]) -> Mat<N, N> {
+    std::array::from_fn(|i| std::array::from_fn(|j| if i == j { d[i] } else { 0.0 }))
+}
+
+fn transpose<const R: usize, const C: usize>(m: &Mat<R, C>) -> Mat<C, R> {
+    std::array::from_fn(|i| std::array::from_fn(|j| m[j][i]))
+}
+
+fn mat_mul<const R: usize, const K: usize, const C: usize>(
+    a: &Mat<R, K>,
+    b: &Mat<K, C>,
+) -> Mat<R, C> {
+    std::array::from_fn(|i| std::array::from_fn(|j| (0..K).map(|k| a[i][k] * b[k][j]).sum()))
+}
+
+fn mat_vec<const R: usize, const C: usize>(m: &Mat<R, C>, v: &[f32; C]) -> [f32; R] {
+    std::array::from_fn(|i| (0..C).map(|j| m[i][j] * v[j]).sum())
+}
+
+fn add_vec<const N: usize>(a: &[f32; N], b: &[f32; N]) -> [f32; N] {
+    std::array::from_fn(|i| a[i] + b[i])
+}
+
+fn add_mat<const R: usize, const C: usize>(a: &Mat<R, C>, b: &Mat<R, C>) -> Mat<R, C> {
+    std::array::from_fn(|i| add_vec(&a[i], &b[i]))
+}
+
+fn symmetrize<const N: usize>(m: &Mat<N, N>) -> Mat<N, N> {
+    std::array::from_fn(|i| std::array::from_fn(|j| 0.5 * (m[i][j] + m[j][i])))
+}
+
+fn inverse2(m: &Mat<2, 2>) -> Mat<2, 2> {
+    let det = m[0][0] * m[1][1] - m[0][1] * m[1][0];
+    let inv_det = if det.abs() > f32::EPSILON {
+        1.0 / det
+    } else {
+        0.0
+    };
+    [
+        [m[1][1] * inv_det, -m[0][1] * inv_det],
+        [-m[1][0] * inv_det, m[0][0] * inv_det],
+    ]
+}
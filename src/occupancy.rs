@@ -0,0 +1,418 @@
+use avian3d::prelude::*;
+use bevy::asset::RenderAssetUsages;
+use bevy::prelude::*;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use bevy_egui::{EguiContexts, EguiPrimaryContextPass, EguiTextureHandle, egui};
+use serde::Deserialize;
+
+use crate::car_dynamics::{Car, EgoState, bicycle_kinematic_model};
+use crate::ground_truth::heading_box;
+use crate::road::RoadNetwork;
+use crate::semantic::SemanticClass;
+
+pub struct OccupancyPlugin;
+
+impl Plugin for OccupancyPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_message::<OccupancyFrame>()
+            .add_systems(Update, update_grids.after(bicycle_kinematic_model))
+            .add_systems(EguiPrimaryContextPass, draw_grids);
+        #[cfg(not(target_arch = "wasm32"))]
+        app.init_resource::<export::GridExport>()
+            .add_systems(Startup, export::setup_recorder)
+            .add_systems(Update, export::save_grids.after(update_grids));
+    }
+}
+
+const OVERLAY_WIDTH: f32 = 240.0; // egui points
+const CAR_SIZE: Vec2 = Vec2::new(5.0, 2.0); // m, drawn on the overlay
+
+/// State of a grid cell, where a cell that is both road and occupied is occupied.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u8)]
+pub enum Cell {
+    #[default]
+    Free = 0,
+    Road = 1,
+    Occupied = 2,
+}
+
+impl Cell {
+    /// Grey level in exported images: black off the road, mid grey on it and white where
+    /// occupied.
+    #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+    pub fn gray(self) -> u8 {
+        match self {
+            Cell::Free => 0,
+            Cell::Road => 128,
+            Cell::Occupied => 255,
+        }
+    }
+
+    fn color(self) -> [u8; 4] {
+        match self {
+            Cell::Free => [20, 20, 20, 255],
+            Cell::Road => [90, 90, 110, 255],
+            Cell::Occupied => [230, 60, 40, 255],
+        }
+    }
+}
+
+/// Rolling bird's-eye-view grid around a vehicle, aligned to its heading, marking the
+/// road area and the cells covered by colliders.
+///
+/// Lanes are rasterised from the road network and colliders from their boxes aligned to
+/// their heading, every tick. Cells are taken at their centres, with collider boxes grown
+/// by half a cell so that objects smaller than a cell still show.
+#[derive(Component, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct OccupancyGrid {
+    pub enabled: bool,
+    pub size: (f32, f32), // m, along and across the heading
+    pub resolution: f32,  // m, cell side
+    pub offset: f32,      // m, of the grid centre ahead of the vehicle
+    /// Cells in rows from the front, each from the left.
+    #[serde(skip)]
+    pub cells: Vec<Cell>,
+    #[serde(skip)]
+    image: Handle<Image>, // colour coded cells for the overlay
+}
+
+impl Default for OccupancyGrid {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            size: (60.0, 40.0),
+            resolution: 0.25,
+            offset: 10.0,
+            cells: Vec::new(),
+            image: Handle::default(),
+        }
+    }
+}
+
+impl OccupancyGrid {
+    /// Occupancy grid fitted to the car but switched off.
+    pub fn disabled() -> Self {
+        Self {
+            enabled: false,
+            ..default()
+        }
+    }
+
+    /// Rows and columns.
+    pub fn dimensions(&self) -> (u32, u32) {
+        let resolution = self.resolution.max(0.01);
+        (
+            (self.size.0 / resolution).round().max(1.0) as u32,
+            (self.size.1 / resolution).round().max(1.0) as u32,
+        )
+    }
+
+    // Vehicle frame position of the front left corner of the grid
+    fn front_left(&self) -> Vec2 {
+        let (rows, columns) = self.dimensions();
+        Vec2::new(
+            self.offset + rows as f32 * self.resolution / 2.0,
+            columns as f32 * self.resolution / 2.0,
+        )
+    }
+
+    /// Vehicle frame position of the centre of a cell.
+    pub fn cell_center(&self, row: u32, column: u32) -> Vec2 {
+        self.front_left() - (Vec2::new(row as f32, column as f32) + 0.5) * self.resolution
+    }
+
+    // Marks the cells between `min` and `max` in the vehicle frame whose centres are
+    // `inside`, unless they already hold a higher state
+    fn fill(&mut self, min: Vec2, max: Vec2, value: Cell, inside: impl Fn(Vec2) -> bool) {
+        let (rows, columns) = self.dimensions();
+        let front_left = self.front_left();
+        let index = |distance: f32, count: u32| {
+            (distance / self.resolution).clamp(0.0, count as f32) as u32
+        };
+        let (first_row, last_row) = (
+            index(front_left.x - max.x, rows),
+            index(front_left.x - min.x + self.resolution, rows),
+        );
+        let (first_column, last_column) = (
+            index(front_left.y - max.y, columns),
+            index(front_left.y - min.y + self.resolution, columns),
+        );
+        for row in first_row..last_row {
+            for column in first_column..last_column {
+                let center = self.cell_center(row, column);
+                let cell = &mut self.cells[(row * columns + column) as usize];
+                if *cell < value && inside(center) {
+                    *cell = value;
+                }
+            }
+        }
+    }
+}
+
+/// One grid, as written every tick for each enabled grid.
+#[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+#[derive(Message, Clone, Debug)]
+pub struct OccupancyFrame {
+    pub time: f32,             // s since startup
+    pub pose: (f32, f32, f32), // x, y and yaw of the vehicle
+    pub rows: u32,
+    pub columns: u32,
+    pub resolution: f32, // m
+    pub offset: f32,     // m
+    pub cells: Vec<Cell>,
+}
+
+// Distance from `p` to the segment from `a` to `b`
+fn segment_distance(p: Vec2, a: Vec2, b: Vec2) -> f32 {
+    let ab = b - a;
+    let t = ((p - a).dot(ab) / ab.length_squared().max(f32::EPSILON)).clamp(0.0, 1.0);
+    p.distance(a + ab * t)
+}
+
+#[allow(clippy::type_complexity)]
+fn update_grids(
+    mut grids: Query<(Entity, &mut OccupancyGrid, &EgoState), With<Car>>,
+    objects: Query<
+        (
+            Entity,
+            &Collider,
+            &Transform,
+            Option<&EgoState>,
+            Option<&SemanticClass>,
+        ),
+        Without<Sensor>,
+    >,
+    road: Res<RoadNetwork>,
+    mut images: ResMut<Assets<Image>>,
+    mut frames: MessageWriter<OccupancyFrame>,
+    time: Res<Time>,
+) {
+    for (car, mut grid, state) in &mut grids {
+        if !grid.enabled {
+            continue;
+        }
+        let (rows, columns) = grid.dimensions();
+        grid.cells.clear();
+        grid.cells.resize((rows * columns) as usize, Cell::Free);
+        let to_vehicle =
+            Isometry2d::new(Vec2::new(state.x, state.y), Rot2::radians(state.yaw)).inverse();
+        let (grid_min, grid_max) = (grid.cell_center(rows, columns), grid.front_left());
+
+        for lane in &road.lanes {
+            let half_width = lane.width / 2.0;
+            let mut points: Vec<Vec2> = lane
+                .centerline
+                .points()
+                .iter()
+                .map(|&p| to_vehicle * p)
+                .collect();
+            if lane.centerline.is_closed()
+                && let Some(&first) = points.first()
+            {
+                points.push(first);
+            }
+            for segment in points.windows(2) {
+                let (a, b) = (segment[0], segment[1]);
+                let (min, max) = (a.min(b) - half_width, a.max(b) + half_width);
+                if max.cmplt(grid_min).any() || min.cmpgt(grid_max).any() {
+                    continue;
+                }
+                grid.fill(min, max, Cell::Road, |p| {
+                    segment_distance(p, a, b) <= half_width
+                });
+            }
+        }
+
+        let margin = grid.resolution / 2.0;
+        for (entity, collider, transform, object_state, class) in &objects {
+            if entity == car || matches!(class, Some(SemanticClass::Ground | SemanticClass::Road)) {
+                continue;
+            }
+            let (center, yaw, size) = heading_box(collider, transform, object_state);
+            let center = to_vehicle * center.truncate();
+            let rotation = Rot2::radians(yaw - state.yaw);
+            let half_size = size.truncate() / 2.0 + margin;
+            let reach = Vec2::splat(half_size.length());
+            grid.fill(center - reach, center + reach, Cell::Occupied, |p| {
+                let local = rotation.inverse() * (p - center);
+                local.x.abs() <= half_size.x && local.y.abs() <= half_size.y
+            });
+        }
+
+        // Overlay image, replaced when the grid is resized
+        let extent = Extent3d {
+            width: columns,
+            height: rows,
+            depth_or_array_layers: 1,
+        };
+        let pixels: Vec<u8> = grid.cells.iter().flat_map(|cell| cell.color()).collect();
+        match images.get_mut(&grid.image) {
+            Some(image) if image.texture_descriptor.size == extent => {
+                image.data = Some(pixels);
+            }
+            _ => {
+                grid.image = images.add(Image::new(
+                    extent,
+                    TextureDimension::D2,
+                    pixels,
+                    TextureFormat::Rgba8UnormSrgb,
+                    RenderAssetUsages::default(),
+                ));
+            }
+        }
+
+        frames.write(OccupancyFrame {
+            time: time.elapsed_secs(),
+            pose: (state.x, state.y, state.yaw),
+            rows,
+            columns,
+            resolution: grid.resolution,
+            offset: grid.offset,
+            cells: grid.cells.clone(),
+        });
+    }
+}
+
+fn draw_grids(
+    mut contexts: EguiContexts,
+    grids: Query<(Entity, &OccupancyGrid)>,
+    #[cfg(not(target_arch = "wasm32"))] mut export: ResMut<export::GridExport>,
+) -> Result {
+    for (car, grid) in &grids {
+        if !grid.enabled || grid.cells.is_empty() {
+            continue;
+        }
+        let texture = contexts.add_image(EguiTextureHandle::Weak(grid.image.id()));
+        let (rows, columns) = grid.dimensions();
+        let scale = OVERLAY_WIDTH / columns as f32; // points per cell
+        let size = egui::vec2(OVERLAY_WIDTH, rows as f32 * scale);
+        egui::Window::new("Occupancy grid")
+            .id(egui::Id::new(("occupancy_grid", car)))
+            .default_pos([15.0, 400.0])
+            .resizable(false)
+            .show(contexts.ctx_mut()?, |ui| {
+                let response = ui.image((texture, size));
+                // The vehicle, forward pointing up
+                let origin = grid.front_left() / grid.resolution * scale;
+                let center = response.rect.min + egui::vec2(origin.y, origin.x);
+                let car_size = CAR_SIZE / grid.resolution * scale;
+                ui.painter().rect_filled(
+                    egui::Rect::from_center_size(center, egui::vec2(car_size.y, car_size.x)),
+                    2.0,
+                    egui::Color32::from_rgb(80, 160, 255),
+                );
+                ui.label(format!(
+                    "{:.0}x{:.0} m, {:.2} m cells",
+                    grid.size.0, grid.size.1, grid.resolution
+                ));
+                #[cfg(not(target_arch = "wasm32"))]
+                if ui.button("Save PNG").clicked() {
+                    export.snapshot = true;
+                }
+            });
+    }
+    Ok(())
+}
+
+/// Grids saved as 8-bit PNG files of [`Cell::gray`] levels, rows from the front: every
+/// grid to `--record-occupancy <dir>` with the vehicle pose of each in `grids.csv`, or
+/// the next one to the working directory with the overlay's save button.
+#[cfg(not(target_arch = "wasm32"))]
+mod export {
+    use bevy::asset::RenderAssetUsages;
+    use bevy::prelude::*;
+    use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+    use bevy::tasks::IoTaskPool;
+    use std::fs::File;
+    use std::io::{BufWriter, Write};
+    use std::path::PathBuf;
+
+    use super::OccupancyFrame;
+    use crate::utils::launch_argument;
+
+    #[derive(Resource, Default)]
+    pub struct GridExport {
+        pub snapshot: bool,
+        dir: Option<PathBuf>,
+        index: Option<BufWriter<File>>,
+        count: usize,
+    }
+
+    pub fn setup_recorder(mut export: ResMut<GridExport>) {
+        let Some(dir) = launch_argument("record-occupancy") else {
+            return;
+        };
+        let dir = PathBuf::from(dir);
+        let index = std::fs::create_dir_all(&dir)
+            .and_then(|_| File::create(dir.join("grids.csv")))
+            .map(BufWriter::new)
+            .and_then(|mut index| {
+                writeln!(index, "time,file,x,y,yaw,resolution,offset")?;
+                Ok(index)
+            });
+        match index {
+            Ok(index) => {
+                info!("Recording occupancy grids to {}", dir.display());
+                export.dir = Some(dir);
+                export.index = Some(index);
+            }
+            Err(e) => error!("Cannot record occupancy grids to {}: {}", dir.display(), e),
+        }
+    }
+
+    pub fn save_grids(mut frames: MessageReader<OccupancyFrame>, mut export: ResMut<GridExport>) {
+        let export = &mut *export;
+        for frame in frames.read() {
+            if let (Some(dir), Some(index)) = (&export.dir, &mut export.index) {
+                let name = format!("grid_{:06}.png", export.count);
+                let (x, y, yaw) = frame.pose;
+                let row = writeln!(
+                    index,
+                    "{:.3},{},{:.3},{:.3},{:.4},{},{}",
+                    frame.time, name, x, y, yaw, frame.resolution, frame.offset
+                );
+                if let Err(e) = row {
+                    error!("Failed to record occupancy grids: {}", e);
+                }
+                save_grid(frame, dir.join(name));
+                export.count += 1;
+            }
+            if export.snapshot {
+                export.snapshot = false;
+                info!("Saving occupancy grid at {:.2} s", frame.time);
+                save_grid(
+                    frame,
+                    PathBuf::from(format!("occupancy_{:.2}.png", frame.time)),
+                );
+            }
+        }
+    }
+
+    // Encoded off the main thread, like camera frames
+    fn save_grid(frame: &OccupancyFrame, path: PathBuf) {
+        let image = Image::new(
+            Extent3d {
+                width: frame.columns,
+                height: frame.rows,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            frame.cells.iter().map(|cell| cell.gray()).collect(),
+            TextureFormat::R8Unorm,
+            RenderAssetUsages::MAIN_WORLD,
+        );
+        IoTaskPool::get()
+            .spawn(async move {
+                let result = image
+                    .try_into_dynamic()
+                    .map_err(|e| e.to_string())
+                    .and_then(|image| image.save(&path).map_err(|e| e.to_string()));
+                if let Err(e) = result {
+                    error!("Failed to save {}: {}", path.display(), e);
+                }
+            })
+            .detach();
+    }
+}
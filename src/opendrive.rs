@@ -0,0 +1,462 @@
+use bevy::asset::io::Reader;
+use bevy::asset::{AssetLoader, AsyncReadExt, LoadContext, RenderAssetUsages};
+use bevy::mesh::{Indices, PrimitiveTopology};
+use bevy::prelude::*;
+use roxmltree::{Document, Node};
+
+use crate::path::Polyline;
+use crate::road::{Lane, RoadReference};
+
+// OpenDRIVE 1.x reader for flat roads: plan view geometries (line, arc, spiral,
+// poly3 and paramPoly3), lane offsets, lane sections and lane widths.
+// Elevation, superelevation and road marks are ignored.
+
+pub struct OpenDrivePlugin;
+
+impl Plugin for OpenDrivePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<OpenDrive>()
+            .register_asset_loader(OpenDriveLoader);
+    }
+}
+
+const SAMPLE_STEP: f32 = 1.0; // m along s
+const SPIRAL_STEP: f32 = 0.1; // m, integration step for spirals
+
+/// Road network loaded from an `.xodr` file.
+#[derive(Asset, TypePath)]
+pub struct OpenDrive {
+    pub roads: Vec<OdrRoad>,
+}
+
+pub struct OdrRoad {
+    pub id: String,
+    pub length: f32,
+    geometries: Vec<Geometry>,
+    lane_offsets: Vec<Cubic>,
+    sections: Vec<LaneSection>,
+}
+
+struct Geometry {
+    s: f32,
+    origin: Vec2,
+    hdg: f32,
+    length: f32,
+    kind: GeometryKind,
+}
+
+enum GeometryKind {
+    Line,
+    Arc {
+        curvature: f32,
+    },
+    Spiral {
+        start: f32,
+        end: f32,
+    },
+    Poly3 {
+        a: f32,
+        b: f32,
+        c: f32,
+        d: f32,
+    },
+    ParamPoly3 {
+        u: [f32; 4],
+        v: [f32; 4],
+        normalized: bool,
+    },
+}
+
+/// `a + b*ds + c*ds^2 + d*ds^3` starting at `s`.
+#[derive(Copy, Clone)]
+struct Cubic {
+    s: f32,
+    a: f32,
+    b: f32,
+    c: f32,
+    d: f32,
+}
+
+struct LaneSection {
+    s: f32,
+    lanes: Vec<OdrLane>, // left and right lanes, without the centre lane
+}
+
+struct OdrLane {
+    id: i32, // positive on the left of the reference line
+    kind: String,
+    widths: Vec<Cubic>, // `s` relative to the lane section
+}
+
+fn cubic(coefficients: [f32; 4], x: f32) -> f32 {
+    let [a, b, c, d] = coefficients;
+    a + x * (b + x * (c + x * d))
+}
+
+fn cubic_slope(coefficients: [f32; 4], x: f32) -> f32 {
+    let [_, b, c, d] = coefficients;
+    b + x * (2.0 * c + x * 3.0 * d)
+}
+
+impl Cubic {
+    // Value of the last record starting at or before `s`
+    fn eval(records: &[Cubic], s: f32) -> f32 {
+        records
+            .iter()
+            .rev()
+            .find(|r| r.s <= s)
+            .or(records.first())
+            .map_or(0.0, |r| cubic([r.a, r.b, r.c, r.d], s - r.s))
+    }
+}
+
+impl Geometry {
+    /// Pose on the reference line `ds` metres into this geometry.
+    fn pose(&self, ds: f32) -> (Vec2, f32) {
+        let (local, heading) = match self.kind {
+            GeometryKind::Line => (Vec2::new(ds, 0.0), 0.0),
+            GeometryKind::Arc { curvature } if curvature.abs() < 1e-9 => (Vec2::new(ds, 0.0), 0.0),
+            GeometryKind::Arc { curvature } => {
+                let heading = curvature * ds;
+                (
+                    Vec2::new(heading.sin(), 1.0 - heading.cos()) / curvature,
+                    heading,
+                )
+            }
+            GeometryKind::Spiral { start, end } => {
+                let rate = (end - start) / self.length;
+                let heading_at = |s: f32| start * s + 0.5 * rate * s * s;
+                let steps = (ds / SPIRAL_STEP).ceil().max(1.0) as usize;
+                let h = ds / steps as f32;
+                let local = (0..steps)
+                    .map(|i| Vec2::from_angle(heading_at((i as f32 + 0.5) * h)) * h)
+                    .sum();
+                (local, heading_at(ds))
+            }
+            GeometryKind::Poly3 { a, b, c, d } => {
+                let coefficients = [a, b, c, d];
+                (
+                    Vec2::new(ds, cubic(coefficients, ds)),
+                    cubic_slope(coefficients, ds).atan(),
+                )
+            }
+            GeometryKind::ParamPoly3 { u, v, normalized } => {
+                let p = if normalized { ds / self.length } else { ds };
+                (
+                    Vec2::new(cubic(u, p), cubic(v, p)),
+                    cubic_slope(v, p).atan2(cubic_slope(u, p)),
+                )
+            }
+        };
+        (
+            self.origin + Vec2::from_angle(self.hdg).rotate(local),
+            self.hdg + heading,
+        )
+    }
+}
+
+impl LaneSection {
+    /// Lateral positions of the inner and outer border of `lane` at `ds` into the section.
+    fn borders(&self, lane: &OdrLane, ds: f32) -> (f32, f32) {
+        let side = lane.id.signum();
+        let inner: f32 = self
+            .lanes
+            .iter()
+            .filter(|other| other.id.signum() == side && other.id.abs() < lane.id.abs())
+            .map(|other| Cubic::eval(&other.widths, ds))
+            .sum();
+        let outer = inner + Cubic::eval(&lane.widths, ds);
+        (inner * side as f32, outer * side as f32)
+    }
+
+    // Outermost borders on the right and the left
+    fn extent(&self, ds: f32) -> (f32, f32) {
+        self.lanes
+            .iter()
+            .map(|lane| self.borders(lane, ds).1)
+            .fold((0.0, 0.0), |(right, left), t| (right.min(t), left.max(t)))
+    }
+}
+
+impl OdrRoad {
+    /// Pose of the reference line at `s`.
+    pub fn reference(&self, s: f32) -> (Vec2, f32) {
+        let geometry = self
+            .geometries
+            .iter()
+            .rev()
+            .find(|g| g.s <= s)
+            .or(self.geometries.first());
+        match geometry {
+            Some(g) => g.pose((s - g.s).clamp(0.0, g.length)),
+            None => (Vec2::ZERO, 0.0),
+        }
+    }
+
+    /// World position of road coordinates (s, t), `t` positive to the left.
+    pub fn point(&self, s: f32, t: f32) -> Vec2 {
+        let (position, heading) = self.reference(s);
+        let t = t + Cubic::eval(&self.lane_offsets, s);
+        position + Vec2::from_angle(heading).perp() * t
+    }
+
+    // Sampled s values of each lane section, both ends included
+    fn section_samples(&self) -> impl Iterator<Item = (&LaneSection, Vec<f32>)> {
+        self.sections.iter().enumerate().map(|(i, section)| {
+            let end = self.sections.get(i + 1).map_or(self.length, |next| next.s);
+            let count = ((end - section.s) / SAMPLE_STEP).ceil().max(1.0) as usize;
+            let samples = (0..=count)
+                .map(|k| section.s + (end - section.s) * k as f32 / count as f32)
+                .collect();
+            (section, samples)
+        })
+    }
+
+    fn reference_line(&self) -> Polyline {
+        let count = (self.length / SAMPLE_STEP).ceil().max(1.0) as usize;
+        let points = (0..=count)
+            .map(|k| self.reference(self.length * k as f32 / count as f32).0)
+            .collect();
+        Polyline::new(points, false)
+    }
+
+    /// Flat road surface spanning all lanes.
+    pub fn surface_mesh(&self) -> Mesh {
+        let mut positions: Vec<[f32; 3]> = Vec::new();
+        let mut uvs: Vec<[f32; 2]> = Vec::new();
+        let mut indices: Vec<u32> = Vec::new();
+        for (section, samples) in self.section_samples() {
+            let first = positions.len() as u32;
+            for &s in &samples {
+                let (right, left) = section.extent(s - section.s);
+                for (t, u) in [(right, 0.0), (left, 1.0)] {
+                    positions.push(self.point(s, t).extend(0.0).to_array());
+                    uvs.push([u, s]);
+                }
+            }
+            for k in 0..samples.len() as u32 - 1 {
+                let (r0, l0, r1, l1) = (
+                    first + 2 * k,
+                    first + 2 * k + 1,
+                    first + 2 * k + 2,
+                    first + 2 * k + 3,
+                );
+                indices.extend([r0, r1, l1, r0, l1, l0]);
+            }
+        }
+        let normals = vec![[0.0, 0.0, 1.0]; positions.len()];
+        Mesh::new(
+            PrimitiveTopology::TriangleList,
+            RenderAssetUsages::default(),
+        )
+        .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, positions)
+        .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, normals)
+        .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, uvs)
+        .with_inserted_indices(Indices::U32(indices))
+    }
+}
+
+impl OpenDrive {
+    /// Driving lanes, oriented in their direction of travel for right-hand traffic.
+    pub fn lanes(&self) -> Vec<Lane> {
+        let mut lanes = Vec::new();
+        for road in &self.roads {
+            for (section, samples) in road.section_samples() {
+                for lane in section.lanes.iter().filter(|l| l.kind == "driving") {
+                    let (mut points, widths): (Vec<Vec2>, Vec<f32>) = samples
+                        .iter()
+                        .map(|&s| {
+                            let (inner, outer) = section.borders(lane, s - section.s);
+                            (road.point(s, (inner + outer) / 2.0), (outer - inner).abs())
+                        })
+                        .unzip();
+                    if lane.id > 0 {
+                        points.reverse();
+                    }
+                    lanes.push(Lane {
+                        centerline: Polyline::new(points, false),
+                        width: widths.iter().sum::<f32>() / widths.len() as f32,
+                    });
+                }
+            }
+        }
+        lanes
+    }
+
+    pub fn reference_lines(&self) -> Vec<RoadReference> {
+        self.roads
+            .iter()
+            .map(|road| RoadReference {
+                id: road.id.clone(),
+                reference: road.reference_line(),
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug)]
+pub enum OpenDriveError {
+    Io(std::io::Error),
+    Xml(roxmltree::Error),
+    Invalid(String),
+}
+
+impl std::fmt::Display for OpenDriveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "could not read file: {e}"),
+            Self::Xml(e) => write!(f, "could not parse XML: {e}"),
+            Self::Invalid(e) => write!(f, "invalid OpenDRIVE: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for OpenDriveError {}
+
+struct OpenDriveLoader;
+
+impl AssetLoader for OpenDriveLoader {
+    type Asset = OpenDrive;
+    type Settings = ();
+    type Error = OpenDriveError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        _load_context: &mut LoadContext<'_>,
+    ) -> Result<OpenDrive, OpenDriveError> {
+        let mut text = String::new();
+        reader
+            .read_to_string(&mut text)
+            .await
+            .map_err(OpenDriveError::Io)?;
+        let document = Document::parse(&text).map_err(OpenDriveError::Xml)?;
+        let roads = document
+            .root_element()
+            .children()
+            .filter(|n| n.has_tag_name("road"))
+            .map(parse_road)
+            .collect::<Result<_, _>>()?;
+        Ok(OpenDrive { roads })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["xodr"]
+    }
+}
+
+fn number(node: Node, name: &str) -> Result<f32, OpenDriveError> {
+    let text = node.attribute(name).ok_or_else(|| {
+        OpenDriveError::Invalid(format!("{} is missing {}", node.tag_name().name(), name))
+    })?;
+    text.trim()
+        .parse::<f64>()
+        .map(|value| value as f32)
+        .map_err(|_| OpenDriveError::Invalid(format!("{name}=\"{text}\" is not a number")))
+}
+
+fn cubic_record(node: Node, s: &str) -> Result<Cubic, OpenDriveError> {
+    Ok(Cubic {
+        s: number(node, s)?,
+        a: number(node, "a")?,
+        b: number(node, "b")?,
+        c: number(node, "c")?,
+        d: number(node, "d")?,
+    })
+}
+
+fn elements<'a, 'input>(
+    node: Node<'a, 'input>,
+    name: &'a str,
+) -> impl Iterator<Item = Node<'a, 'input>> {
+    node.children().filter(move |n| n.has_tag_name(name))
+}
+
+fn parse_road(road: Node) -> Result<OdrRoad, OpenDriveError> {
+    let id = road.attribute("id").unwrap_or_default().to_string();
+    let mut geometries = Vec::new();
+    for geometry in elements(road, "planView").flat_map(|p| elements(p, "geometry")) {
+        let Some(shape) = geometry.first_element_child() else {
+            continue;
+        };
+        let kind = match shape.tag_name().name() {
+            "line" => GeometryKind::Line,
+            "arc" => GeometryKind::Arc {
+                curvature: number(shape, "curvature")?,
+            },
+            "spiral" => GeometryKind::Spiral {
+                start: number(shape, "curvStart")?,
+                end: number(shape, "curvEnd")?,
+            },
+            "poly3" => GeometryKind::Poly3 {
+                a: number(shape, "a")?,
+                b: number(shape, "b")?,
+                c: number(shape, "c")?,
+                d: number(shape, "d")?,
+            },
+            "paramPoly3" => GeometryKind::ParamPoly3 {
+                u: [
+                    number(shape, "aU")?,
+                    number(shape, "bU")?,
+                    number(shape, "cU")?,
+                    number(shape, "dU")?,
+                ],
+                v: [
+                    number(shape, "aV")?,
+                    number(shape, "bV")?,
+                    number(shape, "cV")?,
+                    number(shape, "dV")?,
+                ],
+                normalized: shape.attribute("pRange") == Some("normalized"),
+            },
+            other => {
+                warn!("OpenDRIVE: road {} has unsupported geometry {}", id, other);
+                GeometryKind::Line
+            }
+        };
+        geometries.push(Geometry {
+            s: number(geometry, "s")?,
+            origin: Vec2::new(number(geometry, "x")?, number(geometry, "y")?),
+            hdg: number(geometry, "hdg")?,
+            length: number(geometry, "length")?,
+            kind,
+        });
+    }
+
+    let lanes = elements(road, "lanes").next();
+    let lane_offsets = lanes
+        .into_iter()
+        .flat_map(|l| elements(l, "laneOffset"))
+        .map(|offset| cubic_record(offset, "s"))
+        .collect::<Result<_, _>>()?;
+    let mut sections = Vec::new();
+    for section in lanes.into_iter().flat_map(|l| elements(l, "laneSection")) {
+        let mut section_lanes = Vec::new();
+        for lane in ["left", "right"]
+            .into_iter()
+            .flat_map(|side| elements(section, side))
+            .flat_map(|side| elements(side, "lane"))
+        {
+            section_lanes.push(OdrLane {
+                id: number(lane, "id")? as i32,
+                kind: lane.attribute("type").unwrap_or("none").to_string(),
+                widths: elements(lane, "width")
+                    .map(|width| cubic_record(width, "sOffset"))
+                    .collect::<Result<_, _>>()?,
+            });
+        }
+        sections.push(LaneSection {
+            s: number(section, "s")?,
+            lanes: section_lanes,
+        });
+    }
+
+    Ok(OdrRoad {
+        id,
+        length: number(road, "length")?,
+        geometries,
+        lane_offsets,
+        sections,
+    })
+}
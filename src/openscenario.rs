@@ -0,0 +1,499 @@
+use bevy::asset::io::Reader;
+use bevy::asset::{AssetLoader, AsyncReadExt, LoadContext};
+use bevy::prelude::*;
+use roxmltree::{Document, Node};
+use std::collections::HashMap;
+
+use crate::road::LANE_WIDTH;
+use crate::scenario::{EgoStart, Scenario};
+use crate::storyboard::{
+    ActorKind, ActorSpec, Condition, ConditionKind, Dimension, Dynamics, EGO_NAME, Rule, Shape,
+    StoryAction, StoryEvent, Trigger, Triggering,
+};
+
+// Importer for a practical subset of OpenSCENARIO 1.x:
+// - entities with bounding boxes, the ego being the object named `Ego` or `hero`,
+//   otherwise the first one; `Pedestrian` entities and `bicycle` vehicles become
+//   pedestrian and cyclist actors
+// - `WorldPosition` teleports and absolute speeds in `Init`
+// - speed, lane change and teleport actions in events
+// - simulation time, distance, relative distance, reach position, traveled distance
+//   and speed conditions with delays, in start and stop triggers
+// - `$name` references to top level parameter declarations
+// - the OpenDRIVE road network given by `RoadNetwork/LogicFile`
+// Unsupported elements are skipped with a warning.
+
+pub struct OpenScenarioPlugin;
+
+impl Plugin for OpenScenarioPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_asset_loader(OpenScenarioLoader);
+    }
+}
+
+#[derive(Debug)]
+pub enum OpenScenarioError {
+    Io(std::io::Error),
+    Xml(roxmltree::Error),
+    Invalid(String),
+}
+
+impl std::fmt::Display for OpenScenarioError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "could not read file: {e}"),
+            Self::Xml(e) => write!(f, "could not parse XML: {e}"),
+            Self::Invalid(e) => write!(f, "invalid OpenSCENARIO: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for OpenScenarioError {}
+
+struct OpenScenarioLoader;
+
+impl AssetLoader for OpenScenarioLoader {
+    type Asset = Scenario;
+    type Settings = ();
+    type Error = OpenScenarioError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        load_context: &mut LoadContext<'_>,
+    ) -> Result<Scenario, OpenScenarioError> {
+        let mut text = String::new();
+        reader
+            .read_to_string(&mut text)
+            .await
+            .map_err(OpenScenarioError::Io)?;
+        let document = Document::parse(&text).map_err(OpenScenarioError::Xml)?;
+        let root = document.root_element();
+        let mut scenario = Importer::new(root).import(root)?;
+        // OpenSCENARIO file paths are relative to the scenario file
+        if let Some(path) = &scenario.road_network {
+            let resolved = load_context
+                .asset_path()
+                .resolve_embed(path)
+                .map_err(|e| invalid(format!("bad road network path {path}: {e}")))?;
+            scenario.road_network = Some(resolved.to_string());
+        }
+        scenario.load_dependencies(load_context);
+        Ok(scenario)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["xosc"]
+    }
+}
+
+fn invalid(message: impl Into<String>) -> OpenScenarioError {
+    OpenScenarioError::Invalid(message.into())
+}
+
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children().find(|n| n.has_tag_name(name))
+}
+
+fn children<'a, 'input>(
+    node: Node<'a, 'input>,
+    name: &'a str,
+) -> impl Iterator<Item = Node<'a, 'input>> {
+    node.children().filter(move |n| n.has_tag_name(name))
+}
+
+fn descend<'a, 'input>(node: Node<'a, 'input>, path: &[&str]) -> Option<Node<'a, 'input>> {
+    path.iter().try_fold(node, |node, name| child(node, name))
+}
+
+struct Importer {
+    parameters: HashMap<String, String>,
+    ego: String, // object name mapped to `EGO_NAME`
+}
+
+impl Importer {
+    fn new(root: Node) -> Self {
+        let parameters = descend(root, &["ParameterDeclarations"])
+            .into_iter()
+            .flat_map(|declarations| children(declarations, "ParameterDeclaration"))
+            .filter_map(|p| {
+                Some((
+                    p.attribute("name")?.to_string(),
+                    p.attribute("value")?.to_string(),
+                ))
+            })
+            .collect();
+        Self {
+            parameters,
+            ego: String::new(),
+        }
+    }
+
+    fn text(&self, node: Node, name: &str) -> Result<String, OpenScenarioError> {
+        let value = node
+            .attribute(name)
+            .ok_or_else(|| invalid(format!("{} is missing {}", node.tag_name().name(), name)))?;
+        match value.strip_prefix('$') {
+            Some(parameter) => self
+                .parameters
+                .get(parameter)
+                .cloned()
+                .ok_or_else(|| invalid(format!("undeclared parameter {value}"))),
+            None => Ok(value.to_string()),
+        }
+    }
+
+    fn number(&self, node: Node, name: &str) -> Result<f32, OpenScenarioError> {
+        let text = self.text(node, name)?;
+        text.parse()
+            .map_err(|_| invalid(format!("{name}=\"{text}\" is not a number")))
+    }
+
+    fn number_or(&self, node: Node, name: &str, default: f32) -> Result<f32, OpenScenarioError> {
+        match node.attribute(name) {
+            Some(_) => self.number(node, name),
+            None => Ok(default),
+        }
+    }
+
+    fn entity(&self, node: Node, name: &str) -> Result<String, OpenScenarioError> {
+        let entity = self.text(node, name)?;
+        Ok(if entity == self.ego {
+            EGO_NAME.to_string()
+        } else {
+            entity
+        })
+    }
+
+    fn import(mut self, root: Node) -> Result<Scenario, OpenScenarioError> {
+        let name = descend(root, &["FileHeader"])
+            .and_then(|header| header.attribute("description"))
+            .filter(|description| !description.is_empty())
+            .unwrap_or("OpenSCENARIO")
+            .to_string();
+
+        let objects: Vec<Node> = descend(root, &["Entities"])
+            .ok_or_else(|| invalid("no Entities"))?
+            .children()
+            .filter(|n| n.has_tag_name("ScenarioObject"))
+            .collect();
+        let names = objects
+            .iter()
+            .map(|object| self.text(*object, "name"))
+            .collect::<Result<Vec<_>, _>>()?;
+        self.ego = names
+            .iter()
+            .find(|name| matches!(name.to_lowercase().as_str(), "ego" | "hero"))
+            .or(names.first())
+            .cloned()
+            .ok_or_else(|| invalid("no ScenarioObject"))?;
+
+        let storyboard = descend(root, &["Storyboard"]).ok_or_else(|| invalid("no Storyboard"))?;
+        let mut ego = EgoStart::default();
+        let mut actors = Vec::new();
+        for (object, name) in objects.iter().zip(&names) {
+            let mut actor = ActorSpec {
+                name: name.clone(),
+                x: 0.0,
+                y: 0.0,
+                yaw: 0.0,
+                speed: 0.0,
+                kind: ActorKind::Vehicle,
+                size: None,
+                color: (0.2, 0.4, 1.0),
+                waypoints: Vec::new(),
+            };
+            for entity in object.children().filter(Node::is_element) {
+                match entity.tag_name().name() {
+                    "Pedestrian" => actor.kind = ActorKind::Pedestrian,
+                    "Vehicle" if entity.attribute("vehicleCategory") == Some("bicycle") => {
+                        actor.kind = ActorKind::Cyclist
+                    }
+                    _ => {}
+                }
+            }
+            if let Some(dimensions) = object
+                .children()
+                .find_map(|entity| descend(entity, &["BoundingBox", "Dimensions"]))
+            {
+                actor.size = Some((
+                    self.number(dimensions, "length")?,
+                    self.number(dimensions, "width")?,
+                    self.number(dimensions, "height")?,
+                ));
+            } else {
+                warn!(
+                    "OpenSCENARIO: no bounding box for {}, using the default size",
+                    name
+                );
+            }
+            self.apply_init(storyboard, name, &mut actor)?;
+            if *name == self.ego {
+                ego = EgoStart {
+                    x: actor.x,
+                    y: actor.y,
+                    yaw: actor.yaw,
+                    speed: actor.speed,
+                };
+            } else {
+                actors.push(actor);
+            }
+        }
+
+        let mut story = Vec::new();
+        for group in storyboard
+            .descendants()
+            .filter(|n| n.has_tag_name("ManeuverGroup"))
+        {
+            let act_trigger = group
+                .parent()
+                .and_then(|act| child(act, "StartTrigger"))
+                .map(|trigger| self.trigger(trigger))
+                .transpose()?;
+            let group_actors = descend(group, &["Actors"])
+                .into_iter()
+                .flat_map(|actors| children(actors, "EntityRef"))
+                .map(|entity| self.entity(entity, "entityRef"))
+                .collect::<Result<Vec<_>, _>>()?;
+            for event in group.descendants().filter(|n| n.has_tag_name("Event")) {
+                // An event can only start once its act has
+                let (trigger, after) = match child(event, "StartTrigger") {
+                    Some(trigger) => (self.trigger(trigger)?, act_trigger.clone()),
+                    None => (act_trigger.clone().unwrap_or_default(), None),
+                };
+                let mut actions = Vec::new();
+                for action in children(event, "Action") {
+                    match child(action, "PrivateAction") {
+                        Some(private) => actions.extend(self.action(private)?),
+                        None => warn!("OpenSCENARIO: only private actions are supported"),
+                    }
+                }
+                story.push(StoryEvent {
+                    name: self.text(event, "name")?,
+                    actors: group_actors.clone(),
+                    actions,
+                    trigger,
+                    after,
+                });
+            }
+        }
+
+        let stop_trigger = child(storyboard, "StopTrigger")
+            .map(|trigger| self.trigger(trigger))
+            .transpose()?;
+
+        let mut scenario = Scenario::default();
+        scenario.name = name;
+        scenario.ego = ego;
+        scenario.actors = actors;
+        scenario.story = story;
+        scenario.stop_trigger = stop_trigger;
+        scenario.road_network = descend(root, &["RoadNetwork", "LogicFile"])
+            .map(|file| self.text(file, "filepath"))
+            .transpose()?;
+        scenario.obstacles.clear();
+        scenario.parking_bay = None;
+        Ok(scenario)
+    }
+
+    fn apply_init(
+        &self,
+        storyboard: Node,
+        name: &str,
+        actor: &mut ActorSpec,
+    ) -> Result<(), OpenScenarioError> {
+        let Some(actions) = descend(storyboard, &["Init", "Actions"]) else {
+            return Ok(());
+        };
+        for private in children(actions, "Private") {
+            if self.text(private, "entityRef")? != name {
+                continue;
+            }
+            for action in children(private, "PrivateAction") {
+                if let Some(action) = self.action(action)? {
+                    match action {
+                        StoryAction::Teleport { x, y, yaw } => {
+                            actor.x = x;
+                            actor.y = y;
+                            actor.yaw = yaw;
+                        }
+                        StoryAction::Speed { target, .. } => actor.speed = target,
+                        StoryAction::LaneChange { .. } => {
+                            warn!("OpenSCENARIO: lane changes in Init are ignored")
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn action(&self, private: Node) -> Result<Option<StoryAction>, OpenScenarioError> {
+        if let Some(speed) = descend(private, &["LongitudinalAction", "SpeedAction"]) {
+            let Some(target) = descend(speed, &["SpeedActionTarget", "AbsoluteTargetSpeed"]) else {
+                warn!("OpenSCENARIO: only absolute target speeds are supported");
+                return Ok(None);
+            };
+            return Ok(Some(StoryAction::Speed {
+                target: self.number(target, "value")?,
+                dynamics: self.dynamics(child(speed, "SpeedActionDynamics"))?,
+            }));
+        }
+        if let Some(lane_change) = descend(private, &["LateralAction", "LaneChangeAction"]) {
+            let Some(target) = descend(lane_change, &["LaneChangeTarget", "RelativeTargetLane"])
+            else {
+                warn!("OpenSCENARIO: only relative target lanes are supported");
+                return Ok(None);
+            };
+            let lanes = self.number(target, "value")?;
+            return Ok(Some(StoryAction::LaneChange {
+                offset: lanes * LANE_WIDTH
+                    + self.number_or(lane_change, "targetLaneOffset", 0.0)?,
+                dynamics: self.dynamics(child(lane_change, "LaneChangeActionDynamics"))?,
+            }));
+        }
+        if let Some(position) = descend(private, &["TeleportAction", "Position", "WorldPosition"]) {
+            return Ok(Some(StoryAction::Teleport {
+                x: self.number(position, "x")?,
+                y: self.number(position, "y")?,
+                yaw: self.number_or(position, "h", 0.0)?,
+            }));
+        }
+        warn!(
+            "OpenSCENARIO: unsupported action {:?}",
+            private.first_element_child().map(|n| n.tag_name().name())
+        );
+        Ok(None)
+    }
+
+    fn dynamics(&self, node: Option<Node>) -> Result<Dynamics, OpenScenarioError> {
+        let Some(node) = node else {
+            return Ok(Dynamics {
+                shape: Shape::Step,
+                dimension: Dimension::Time,
+                value: 0.0,
+            });
+        };
+        let shape = match self.text(node, "dynamicsShape")?.as_str() {
+            "step" => Shape::Step,
+            "linear" => Shape::Linear,
+            "sinusoidal" => Shape::Sinusoidal,
+            "cubic" => Shape::Cubic,
+            other => return Err(invalid(format!("unknown dynamicsShape {other}"))),
+        };
+        let dimension = match self.text(node, "dynamicsDimension")?.as_str() {
+            "time" => Dimension::Time,
+            "distance" => Dimension::Distance,
+            "rate" => Dimension::Rate,
+            other => return Err(invalid(format!("unknown dynamicsDimension {other}"))),
+        };
+        Ok(Dynamics {
+            shape,
+            dimension,
+            value: self.number(node, "value")?,
+        })
+    }
+
+    fn trigger(&self, trigger: Node) -> Result<Trigger, OpenScenarioError> {
+        let mut groups = Vec::new();
+        'groups: for group in children(trigger, "ConditionGroup") {
+            let mut conditions = Vec::new();
+            for condition in children(group, "Condition") {
+                match self.condition(condition)? {
+                    Some(kind) => conditions.push(Condition::new(
+                        kind,
+                        self.number_or(condition, "delay", 0.0)?,
+                    )),
+                    // A group missing one of its conditions could fire too early
+                    None => continue 'groups,
+                }
+            }
+            groups.push(conditions);
+        }
+        Ok(Trigger { groups })
+    }
+
+    fn rule(&self, node: Node) -> Result<Rule, OpenScenarioError> {
+        Ok(match self.text(node, "rule")?.as_str() {
+            "greaterThan" => Rule::GreaterThan,
+            "lessThan" => Rule::LessThan,
+            "equalTo" => Rule::EqualTo,
+            "greaterOrEqual" => Rule::GreaterOrEqual,
+            "lessOrEqual" => Rule::LessOrEqual,
+            other => return Err(invalid(format!("unsupported rule {other}"))),
+        })
+    }
+
+    fn condition(&self, condition: Node) -> Result<Option<ConditionKind>, OpenScenarioError> {
+        if let Some(time) = descend(condition, &["ByValueCondition", "SimulationTimeCondition"]) {
+            return Ok(Some(ConditionKind::SimulationTime {
+                value: self.number(time, "value")?,
+                rule: self.rule(time)?,
+            }));
+        }
+        let Some(by_entity) = child(condition, "ByEntityCondition") else {
+            warn!(
+                "OpenSCENARIO: unsupported condition {:?}",
+                condition.attribute("name")
+            );
+            return Ok(None);
+        };
+        let triggering = child(by_entity, "TriggeringEntities")
+            .ok_or_else(|| invalid("ByEntityCondition without TriggeringEntities"))?;
+        let entities = Triggering {
+            names: children(triggering, "EntityRef")
+                .map(|entity| self.entity(entity, "entityRef"))
+                .collect::<Result<_, _>>()?,
+            all: self.text(triggering, "triggeringEntitiesRule")? == "all",
+        };
+        let Some(kind) = child(by_entity, "EntityCondition").and_then(|c| c.first_element_child())
+        else {
+            return Err(invalid("ByEntityCondition without EntityCondition"));
+        };
+        let world_position = |node: Node| -> Result<Option<Vec2>, OpenScenarioError> {
+            descend(node, &["Position", "WorldPosition"])
+                .map(|p| Ok(Vec2::new(self.number(p, "x")?, self.number(p, "y")?)))
+                .transpose()
+        };
+        Ok(match kind.tag_name().name() {
+            "RelativeDistanceCondition" => Some(ConditionKind::RelativeDistance {
+                entities,
+                other: self.entity(kind, "entityRef")?,
+                value: self.number(kind, "value")?,
+                rule: self.rule(kind)?,
+            }),
+            "DistanceCondition" | "ReachPositionCondition" => {
+                let Some(position) = world_position(kind)? else {
+                    warn!("OpenSCENARIO: only world positions are supported in conditions");
+                    return Ok(None);
+                };
+                let (value, rule) = if kind.has_tag_name("DistanceCondition") {
+                    (self.number(kind, "value")?, self.rule(kind)?)
+                } else {
+                    (self.number(kind, "tolerance")?, Rule::LessOrEqual)
+                };
+                Some(ConditionKind::Distance {
+                    entities,
+                    x: position.x,
+                    y: position.y,
+                    value,
+                    rule,
+                })
+            }
+            "TraveledDistanceCondition" => Some(ConditionKind::TraveledDistance {
+                entities,
+                value: self.number(kind, "value")?,
+            }),
+            "SpeedCondition" => Some(ConditionKind::Speed {
+                entities,
+                value: self.number(kind, "value")?,
+                rule: self.rule(kind)?,
+            }),
+            other => {
+                warn!("OpenSCENARIO: unsupported entity condition {}", other);
+                None
+            }
+        })
+    }
+}
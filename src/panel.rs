@@ -1,11 +1,35 @@
+use bevy::ecs::system::SystemParam;
 use bevy::prelude::*;
 use bevy_egui::egui::TextWrapMode;
 use std::f32::consts::{FRAC_PI_2, FRAC_PI_6, PI, TAU};
 
 use bevy_egui::{EguiContexts, EguiPlugin, EguiPrimaryContextPass, egui};
 
+use crate::aeb::{AebConfig, AebStage, AebStatus};
 use crate::camera::CameraMode;
-use crate::car_dynamics::{Car, EgoControl, EgoState};
+use crate::camera_sensor::CameraSensor;
+use crate::car_dynamics::{Ego, EgoControl, EgoState};
+use crate::controller::{ControlReference, VehicleController};
+use crate::faults::{Faults, presets};
+use crate::ghost::GhostReplay;
+use crate::gnss::Gnss;
+use crate::ground_truth::{GroundTruth, GroundTruthObject};
+use crate::imu::Imu;
+use crate::lap_timer::{LapTimer, Leaderboard};
+use crate::lidar::Lidar;
+use crate::lka::{LkaConfig, LkaStatus};
+use crate::occupancy::OccupancyGrid;
+use crate::parking::{Parking, ParkingState};
+use crate::path_follower::{PathController, PathFollower};
+use crate::radar::Radar;
+use crate::road::RoadNetwork;
+use crate::run_log::RunLog;
+use crate::scenario::ActiveScenario;
+use crate::track::{RaceTrack, Track};
+use crate::traffic::{TrafficAgent, TrafficConfig};
+use crate::traffic_control::RuleViolations;
+use crate::ultrasonic::{Ultrasonic, proximity_color};
+use crate::vehicle_bus::VehicleBus;
 
 #[derive(Resource, Default)]
 pub struct DebugPanelVisible(bool);
@@ -185,6 +209,73 @@ impl egui::Widget for PedalIndicator {
     }
 }
 
+/// Top view of the car with an arc per park distance sensor at its echo distance.
+struct ParkingDisplay<'a> {
+    height: f32,
+    ultrasonic: &'a Ultrasonic,
+}
+
+impl<'a> ParkingDisplay<'a> {
+    fn new(height: f32, ultrasonic: &'a Ultrasonic) -> Self {
+        Self { height, ultrasonic }
+    }
+}
+
+impl egui::Widget for ParkingDisplay<'_> {
+    fn ui(self, ui: &mut egui::Ui) -> egui::Response {
+        const CAR_SIZE: (f32, f32) = (5.0, 2.0); // m, length and width
+        let (rect, response) = ui.allocate_exact_size(
+            egui::Vec2::new(self.height * 0.65, self.height),
+            egui::Sense::hover(),
+        );
+        let painter = ui.painter();
+        let ultrasonic = self.ultrasonic;
+        // Vehicle frame to screen, x forward pointing up
+        let scale = self.height / (CAR_SIZE.0 + 2.0 * ultrasonic.range);
+        let center = rect.center();
+        let to_screen = |p: Vec2| center + egui::Vec2::new(-p.y, -p.x) * scale;
+        painter.rect(
+            egui::Rect::from_center_size(center, egui::Vec2::new(CAR_SIZE.1, CAR_SIZE.0) * scale),
+            CAR_SIZE.1 * scale * 0.3,
+            egui::Color32::from_gray(70),
+            egui::Stroke::new(2.0, egui::Color32::from_gray(150)),
+            egui::StrokeKind::Middle,
+        );
+
+        let half_fov = ultrasonic.horizontal_fov.to_radians() / 2.0;
+        for (i, mount) in ultrasonic.sensors.iter().enumerate() {
+            let distance = ultrasonic.distances.get(i).copied().flatten();
+            let (radius, color) = match distance {
+                Some(distance) => {
+                    let [r, g, b, _] = proximity_color(distance).to_u8_array();
+                    (distance, egui::Color32::from_rgb(r, g, b))
+                }
+                None => (ultrasonic.range, egui::Color32::from_gray(60)),
+            };
+            let origin = Vec2::new(mount.position.0, mount.position.1);
+            let yaw = mount.rotation.2;
+            let arc = (0..=8)
+                .map(|k| {
+                    let angle = yaw - half_fov + 2.0 * half_fov * k as f32 / 8.0;
+                    to_screen(origin + Vec2::from_angle(angle) * radius)
+                })
+                .collect();
+            painter.add(egui::Shape::line(arc, egui::Stroke::new(3.0, color)));
+        }
+
+        if let Some(nearest) = ultrasonic.nearest() {
+            painter.text(
+                center,
+                egui::Align2::CENTER_CENTER,
+                format!("{:.2} m", nearest),
+                egui::FontId::proportional(self.height * 0.07),
+                egui::Color32::WHITE,
+            );
+        }
+        response
+    }
+}
+
 fn setup_debug_panel(mut commands: Commands) {
     commands.insert_resource(DebugPanelVisible(true));
     commands.insert_resource(HelpMenuVisible(false));
@@ -202,17 +293,106 @@ fn toggle_help_menu(mut help_menu: ResMut<HelpMenuVisible>, key: Res<ButtonInput
     }
 }
 
+/// Sensors on the ego that the panel switches on and off.
+type EgoSensors = (
+    &'static mut Lidar,
+    &'static mut Radar,
+    &'static mut CameraSensor,
+    &'static mut Imu,
+    &'static mut Gnss,
+    &'static mut VehicleBus,
+    &'static mut Ultrasonic,
+    &'static mut OccupancyGrid,
+);
+
+/// Settings and status of the assistance and autonomy features.
+#[derive(SystemParam)]
+struct FeatureParams<'w, 's> {
+    aeb_config: ResMut<'w, AebConfig>,
+    aeb_status: Res<'w, AebStatus>,
+    lka_config: ResMut<'w, LkaConfig>,
+    lka_status: Res<'w, LkaStatus>,
+    follower: Query<'w, 's, &'static mut PathFollower, With<Ego>>,
+    parking: ResMut<'w, Parking>,
+    road: Res<'w, RoadNetwork>,
+    race_track: Res<'w, RaceTrack>,
+    traffic: ResMut<'w, TrafficConfig>,
+    traffic_agents: Query<'w, 's, (), With<TrafficAgent>>,
+    violations: Res<'w, RuleViolations>,
+    lap_timer: Res<'w, LapTimer>,
+    leaderboard: Res<'w, Leaderboard>,
+    ghost: ResMut<'w, GhostReplay>,
+    sensors: Query<'w, 's, EgoSensors, With<Ego>>,
+    ground_truth: ResMut<'w, GroundTruth>,
+    faults: ResMut<'w, Faults>,
+    time: Res<'w, Time>,
+}
+
+#[allow(clippy::too_many_arguments)]
 fn update_debug_panel(
     mut contexts: EguiContexts,
     window: Single<&Window>,
     debug_panel: Res<DebugPanelVisible>,
     help_menu: Res<HelpMenuVisible>,
-    query: Single<&EgoState, With<Car>>,
-    control: Single<&EgoControl, With<Car>>,
+    query: Single<&EgoState, With<Ego>>,
+    control: Single<&EgoControl, With<Ego>>,
     camera_mode: ResMut<CameraMode>,
+    run_log: Res<RunLog>,
+    controller: Single<(&VehicleController, &mut ControlReference), With<Ego>>,
+    mut features: FeatureParams,
+    scenario: Res<ActiveScenario>,
 ) {
+    let FeatureParams {
+        aeb_config,
+        aeb_status,
+        lka_config,
+        lka_status,
+        follower,
+        parking,
+        road,
+        race_track,
+        traffic,
+        traffic_agents,
+        violations,
+        lap_timer,
+        leaderboard,
+        ghost,
+        sensors,
+        ground_truth,
+        faults,
+        time,
+    } = &mut features;
     let ctx = contexts.ctx_mut().unwrap();
 
+    // AEB warning is shown even when the debug panel is hidden
+    if aeb_status.stage >= AebStage::Warning {
+        egui::Area::new(egui::Id::new("aeb_warning"))
+            .anchor(egui::Align2::CENTER_TOP, egui::Vec2::new(0.0, 20.0))
+            .show(ctx, |ui| {
+                let text = match aeb_status.stage {
+                    AebStage::Warning => "COLLISION WARNING",
+                    _ => "EMERGENCY BRAKING",
+                };
+                ui.label(
+                    egui::RichText::new(text)
+                        .size(32.0)
+                        .strong()
+                        .color(egui::Color32::RED),
+                );
+            });
+    } else if lka_status.departure_warning {
+        egui::Area::new(egui::Id::new("lane_departure_warning"))
+            .anchor(egui::Align2::CENTER_TOP, egui::Vec2::new(0.0, 20.0))
+            .show(ctx, |ui| {
+                ui.label(
+                    egui::RichText::new("LANE DEPARTURE")
+                        .size(32.0)
+                        .strong()
+                        .color(egui::Color32::YELLOW),
+                );
+            });
+    }
+
     // Show help menu
     if help_menu.0 {
         egui::Area::new(egui::Id::new("help_menu"))
@@ -233,7 +413,10 @@ fn update_debug_panel(
             let height = window.height() * 0.2;
             let radius = height / 2.0;
             ui.horizontal(|ui| {
-                ui.add(Speedometer::new(radius, query.v, query.s));
+                if lap_timer.sector_count() > 0 {
+                    draw_lap_times(ui, lap_timer, ghost.delta, time.elapsed_secs());
+                }
+                ui.add(Speedometer::new(radius, query.v.abs(), query.s));
                 ui.add(PedalIndicator::new(
                     height,
                     control.brake,
@@ -245,6 +428,11 @@ fn update_debug_panel(
                     egui::Color32::from_rgb(80, 200, 80),
                 ));
                 ui.add(SteerWheel::new(radius, control.steer_wheel_angle));
+                if let Ok((.., ultrasonic, _)) = sensors.single()
+                    && ultrasonic.enabled
+                {
+                    ui.add(ParkingDisplay::new(height, ultrasonic));
+                }
             });
         });
 
@@ -253,6 +441,48 @@ fn update_debug_panel(
         .anchor(egui::Align2::RIGHT_BOTTOM, egui::Vec2::new(-15.0, -10.0))
         .show(ctx, |ui| {
             draw_basic_info(ui, &query, &control, camera_mode);
+            let (controller, mut reference) = controller.into_inner();
+            draw_controller_info(ui, controller, &mut reference);
+            draw_aeb_info(ui, aeb_config, aeb_status);
+            draw_road_info(ui, road, &query);
+            draw_lka_info(ui, lka_config, lka_status);
+            if let Ok(mut follower) = follower.single_mut() {
+                draw_path_follower_info(ui, &mut follower);
+            }
+            draw_parking_info(ui, parking);
+            if let Some(track) = &race_track.track {
+                draw_track_info(ui, track);
+            }
+            draw_traffic_info(ui, traffic, traffic_agents.iter().len());
+            draw_rule_violations(ui, violations);
+            if let Ok((
+                mut lidar,
+                mut radar,
+                mut camera,
+                mut imu,
+                mut gnss,
+                mut bus,
+                mut ultrasonic,
+                mut occupancy,
+            )) = sensors.single_mut()
+            {
+                draw_sensor_info(
+                    ui,
+                    &mut lidar,
+                    &mut radar,
+                    &mut camera,
+                    &mut ultrasonic,
+                    &mut occupancy,
+                );
+                draw_navigation_info(ui, &mut imu, &mut gnss, &mut bus);
+            }
+            draw_ground_truth_info(ui, ground_truth);
+            draw_fault_info(ui, faults);
+            if lap_timer.sector_count() > 0 {
+                draw_leaderboard(ui, leaderboard, &lap_timer.course, &mut ghost.enabled);
+            }
+            draw_scenario_info(ui, &scenario);
+            draw_run_log(ui, &run_log);
         });
 }
 
@@ -288,6 +518,7 @@ fn draw_basic_info(
         ui.label(format!("Trip Distance: {:.2}m", query.s));
         ui.label(format!("Throttle: {:.2}", control.throttle));
         ui.label(format!("Brake: {:.2}", control.brake));
+        ui.label(format!("Gear: {:?}", control.gear));
         ui.label(format!(
             "Steer Angle: {:.2}°",
             control.front_wheel_angle.to_degrees()
@@ -312,6 +543,371 @@ fn draw_basic_info(
     });
 }
 
+fn draw_controller_info(
+    ui: &mut egui::Ui,
+    controller: &VehicleController,
+    reference: &mut ControlReference,
+) {
+    ui.vertical(|ui| {
+        ui.style_mut().wrap_mode = Some(TextWrapMode::Extend);
+        ui.add_space(5.0);
+        ui.label(format!("Controller: {}", controller.0.name()));
+        if !controller.is_human() {
+            ui.add(egui::Slider::new(&mut reference.speed, 0.0..=30.0).text("Set Speed (m/s)"));
+        }
+    });
+}
+
+fn draw_aeb_info(ui: &mut egui::Ui, config: &mut AebConfig, status: &AebStatus) {
+    ui.vertical(|ui| {
+        ui.style_mut().wrap_mode = Some(TextWrapMode::Extend);
+        ui.add_space(5.0);
+        ui.checkbox(&mut config.enabled, "AEB");
+        ui.checkbox(&mut config.use_radar, "AEB uses radar");
+        match status.ttc {
+            Some(ttc) => ui.label(format!("TTC: {:.2} s ({:?})", ttc, status.stage)),
+            None => ui.label("TTC: -"),
+        };
+    });
+}
+
+fn draw_road_info(ui: &mut egui::Ui, road: &RoadNetwork, state: &EgoState) {
+    let Some(coordinates) = road.road_coordinates(Vec2::new(state.x, state.y)) else {
+        return;
+    };
+    ui.vertical(|ui| {
+        ui.style_mut().wrap_mode = Some(TextWrapMode::Extend);
+        ui.add_space(5.0);
+        ui.label(format!(
+            "Road {} s: {:.1}m t: {:.2}m",
+            road.roads[coordinates.road].id, coordinates.s, coordinates.t
+        ));
+    });
+}
+
+fn draw_track_info(ui: &mut egui::Ui, track: &Track) {
+    ui.vertical(|ui| {
+        ui.style_mut().wrap_mode = Some(TextWrapMode::Extend);
+        ui.add_space(5.0);
+        ui.label(format!("Track Seed: {}", track.seed));
+        ui.label(format!(
+            "Length: {:.0}m Width: {:.1}m Bank: {:.1}°",
+            track.centerline.length(),
+            track.width,
+            track.max_bank.to_degrees()
+        ));
+    });
+}
+
+fn draw_traffic_info(ui: &mut egui::Ui, config: &mut TrafficConfig, vehicles: usize) {
+    ui.vertical(|ui| {
+        ui.style_mut().wrap_mode = Some(TextWrapMode::Extend);
+        ui.add_space(5.0);
+        ui.add(
+            egui::Slider::new(&mut config.density, 0.0..=60.0)
+                .step_by(5.0)
+                .text("Traffic (veh/km)"),
+        );
+        ui.checkbox(&mut config.lane_changes, "Lane Changes (MOBIL)");
+        ui.label(format!("Traffic Vehicles: {}", vehicles));
+    });
+}
+
+fn draw_rule_violations(ui: &mut egui::Ui, violations: &RuleViolations) {
+    ui.vertical(|ui| {
+        ui.style_mut().wrap_mode = Some(TextWrapMode::Extend);
+        ui.add_space(5.0);
+        ui.label(format!("Rule Violations: {}", violations.entries.len()));
+        if let Some((time, violation)) = violations.entries.last() {
+            ui.label(format!("Last: {} at {:.1}s", violation.kind.name(), time));
+        }
+    });
+}
+
+fn draw_sensor_info(
+    ui: &mut egui::Ui,
+    lidar: &mut Lidar,
+    radar: &mut Radar,
+    camera: &mut CameraSensor,
+    ultrasonic: &mut Ultrasonic,
+    occupancy: &mut OccupancyGrid,
+) {
+    ui.vertical(|ui| {
+        ui.style_mut().wrap_mode = Some(TextWrapMode::Extend);
+        ui.add_space(5.0);
+        ui.checkbox(&mut lidar.enabled, "Lidar");
+        if lidar.enabled {
+            ui.label(format!(
+                "Lidar: {} ch, {} points @ {:.0} Hz",
+                lidar.channels,
+                lidar.points.len(),
+                lidar.rate
+            ));
+        }
+        ui.checkbox(&mut radar.enabled, "Radar");
+        if radar.enabled {
+            ui.label(format!(
+                "Radar: {} detections @ {:.0} Hz",
+                radar.detections.len(),
+                radar.rate
+            ));
+        }
+        ui.checkbox(&mut camera.enabled, "Camera");
+        if camera.enabled {
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut camera.depth, "Depth");
+                ui.checkbox(&mut camera.segmentation, "Segmentation");
+            });
+        }
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut ultrasonic.enabled, "Ultrasonic");
+            if ultrasonic.enabled {
+                ui.checkbox(&mut ultrasonic.beep, "Beep");
+            }
+        });
+        ui.checkbox(&mut occupancy.enabled, "Occupancy grid");
+    });
+}
+
+fn draw_navigation_info(ui: &mut egui::Ui, imu: &mut Imu, gnss: &mut Gnss, bus: &mut VehicleBus) {
+    ui.vertical(|ui| {
+        ui.style_mut().wrap_mode = Some(TextWrapMode::Extend);
+        ui.checkbox(&mut imu.enabled, "IMU");
+        if let (true, Some(sample)) = (imu.enabled, imu.last_sample) {
+            let (a, w) = (sample.acceleration, sample.angular_velocity);
+            ui.label(format!(
+                "IMU: a ({:.2}, {:.2}, {:.2}) m/s² ω {:.1}°/s",
+                a.x,
+                a.y,
+                a.z,
+                w.z.to_degrees()
+            ));
+        }
+        ui.checkbox(&mut gnss.enabled, "GNSS");
+        if gnss.enabled {
+            match (gnss.in_outage, gnss.last_fix) {
+                (true, _) => ui.label("GNSS: outage"),
+                (false, Some(fix)) => ui.label(format!(
+                    "GNSS: {:.6}°, {:.6}° ±{:.1} m",
+                    fix.latitude, fix.longitude, fix.horizontal_accuracy
+                )),
+                (false, None) => ui.label("GNSS: no fix"),
+            };
+        }
+        ui.checkbox(&mut bus.enabled, "Vehicle bus");
+        if let (true, Some(angle)) = (bus.enabled, bus.value("SteeringWheelAngle")) {
+            let speed = |wheel: &str| bus.value(&format!("WheelSpeed{wheel}")).unwrap_or(0.0);
+            ui.label(format!(
+                "Wheels: {:.1} {:.1} {:.1} {:.1} km/h",
+                speed("FL"),
+                speed("FR"),
+                speed("RL"),
+                speed("RR")
+            ));
+            ui.label(format!("Steering wheel: {:.1}°", angle));
+        }
+    });
+}
+
+fn draw_ground_truth_info(ui: &mut egui::Ui, ground_truth: &mut GroundTruth) {
+    ui.vertical(|ui| {
+        ui.style_mut().wrap_mode = Some(TextWrapMode::Extend);
+        ui.checkbox(&mut ground_truth.show_boxes, "Ground truth boxes");
+        if ground_truth.show_boxes {
+            ui.label(format!(
+                "Ground truth: {} objects",
+                ground_truth.objects.len()
+            ));
+            let distance = |object: &GroundTruthObject| object.ego.center.truncate().length();
+            let nearest = ground_truth
+                .objects
+                .iter()
+                .min_by(|a, b| distance(a).total_cmp(&distance(b)));
+            if let Some(object) = nearest {
+                ui.label(format!(
+                    "Nearest: {} at {:.1} m",
+                    object.class.name(),
+                    distance(object)
+                ));
+            }
+        }
+    });
+}
+
+fn draw_fault_info(ui: &mut egui::Ui, faults: &mut Faults) {
+    ui.vertical(|ui| {
+        ui.style_mut().wrap_mode = Some(TextWrapMode::Extend);
+        for fault in &mut faults.faults {
+            let status = if fault.active { "active" } else { "armed" };
+            ui.checkbox(
+                &mut fault.enabled,
+                format!("{} ({:?}, {})", fault.spec.name, fault.spec.target, status),
+            );
+        }
+        ui.menu_button("Inject fault", |ui| {
+            for preset in presets() {
+                if ui.button(&preset.name).clicked() {
+                    faults.inject(preset);
+                }
+            }
+        });
+    });
+}
+
+fn draw_lap_times(ui: &mut egui::Ui, timer: &LapTimer, delta: Option<f32>, now: f32) {
+    let lap_time = |time: Option<f32>| time.map_or("-".to_string(), |t| format!("{:.2} s", t));
+    ui.vertical(|ui| {
+        ui.style_mut().wrap_mode = Some(TextWrapMode::Extend);
+        ui.label(format!("Lap {}", timer.laps + 1));
+        ui.label(format!("Current: {}", lap_time(timer.current(now))));
+        // Against the ghost of the best lap, red when behind
+        if let Some(delta) = delta {
+            let color = if delta > 0.0 {
+                egui::Color32::RED
+            } else {
+                egui::Color32::GREEN
+            };
+            ui.label(egui::RichText::new(format!("Delta: {:+.2} s", delta)).color(color));
+        }
+        ui.label(format!(
+            "Last: {}",
+            lap_time(timer.last_lap.as_ref().map(|lap| lap.time))
+        ));
+        ui.label(format!(
+            "Best: {}",
+            lap_time(timer.best_lap.as_ref().map(|lap| lap.time))
+        ));
+        // Splits of the lap in progress, green when matching the best sector
+        for (i, best) in timer.best_sectors.iter().enumerate() {
+            let split = timer.sectors.get(i).copied();
+            let text = egui::RichText::new(format!(
+                "S{}: {} (best {})",
+                i + 1,
+                lap_time(split),
+                lap_time(*best)
+            ));
+            match (split, best) {
+                (Some(split), Some(best)) if split <= *best => {
+                    ui.label(text.color(egui::Color32::GREEN))
+                }
+                _ => ui.label(text),
+            };
+        }
+    });
+}
+
+fn draw_leaderboard(
+    ui: &mut egui::Ui,
+    leaderboard: &Leaderboard,
+    course: &str,
+    ghost_enabled: &mut bool,
+) {
+    const MAX_ENTRIES: usize = 5;
+    ui.vertical(|ui| {
+        ui.style_mut().wrap_mode = Some(TextWrapMode::Extend);
+        ui.add_space(5.0);
+        ui.checkbox(ghost_enabled, "Ghost Car (best lap)");
+        ui.label(format!("Leaderboard: {}", course));
+        for (i, entry) in leaderboard.course(course).take(MAX_ENTRIES).enumerate() {
+            ui.label(format!("{}. {} {:.2} s", i + 1, entry.driver, entry.time));
+        }
+    });
+}
+
+fn draw_lka_info(ui: &mut egui::Ui, config: &mut LkaConfig, status: &LkaStatus) {
+    ui.vertical(|ui| {
+        ui.style_mut().wrap_mode = Some(TextWrapMode::Extend);
+        ui.add_space(5.0);
+        ui.checkbox(&mut config.enabled, "Lane Keeping Assist");
+        match status.lane {
+            Some(lane) => ui.label(format!(
+                "Lane {} s: {:.1}m Offset: {:.2}m Heading: {:.1}°",
+                lane.lane,
+                lane.s,
+                lane.offset,
+                lane.heading_error.to_degrees()
+            )),
+            None => ui.label("Lane: -"),
+        };
+        if status.active {
+            ui.label(format!(
+                "LKA Correction: {:.2}°",
+                status.correction.to_degrees()
+            ));
+        }
+    });
+}
+
+fn draw_path_follower_info(ui: &mut egui::Ui, follower: &mut PathFollower) {
+    ui.vertical(|ui| {
+        ui.style_mut().wrap_mode = Some(TextWrapMode::Extend);
+        ui.add_space(5.0);
+        ui.checkbox(&mut follower.enabled, "Path Follower");
+        let mut controller = follower.controller;
+        for option in [
+            PathController::PURE_PURSUIT,
+            PathController::STANLEY,
+            PathController::MPC,
+        ] {
+            let selected = std::mem::discriminant(&controller) == std::mem::discriminant(&option);
+            if ui.radio(selected, option.name()).clicked() {
+                controller = option;
+            }
+        }
+        if let PathController::Mpc { target_speed } = &mut controller {
+            ui.add(egui::Slider::new(target_speed, 0.0..=30.0).text("Target Speed (m/s)"));
+        }
+        if controller != follower.controller {
+            follower.controller = controller;
+        }
+    });
+}
+
+fn draw_parking_info(ui: &mut egui::Ui, parking: &mut Parking) {
+    ui.vertical(|ui| {
+        ui.style_mut().wrap_mode = Some(TextWrapMode::Extend);
+        ui.add_space(5.0);
+        let state = match parking.state {
+            ParkingState::Idle => "Idle",
+            ParkingState::Failed => "No Path",
+            ParkingState::Executing => "Executing",
+            ParkingState::Parked => "Parked",
+        };
+        ui.label(format!("Auto Park: {}", state));
+        ui.checkbox(&mut parking.show_search_tree, "Show Search Tree");
+    });
+}
+
+fn draw_scenario_info(ui: &mut egui::Ui, scenario: &ActiveScenario) {
+    ui.vertical(|ui| {
+        ui.style_mut().wrap_mode = Some(TextWrapMode::Extend);
+        ui.add_space(5.0);
+        ui.label(format!("Scenario: {}", scenario.name));
+        if let Some(outcome) = &scenario.outcome {
+            ui.label(format!("Ended: {}", outcome));
+        }
+    });
+}
+
+fn draw_run_log(ui: &mut egui::Ui, run_log: &RunLog) {
+    const MAX_ENTRIES: usize = 5;
+    let entries = run_log.entries();
+    if entries.is_empty() {
+        return;
+    }
+    ui.vertical(|ui| {
+        ui.style_mut().wrap_mode = Some(TextWrapMode::Extend);
+        ui.add_space(5.0);
+        ui.label("Events:");
+        for entry in entries.iter().rev().take(MAX_ENTRIES) {
+            ui.label(format!(
+                "[{:.1}s] {}: {}",
+                entry.time, entry.source, entry.message
+            ));
+        }
+    });
+}
+
 fn draw_speed_scale(painter: &egui::Painter, center: egui::Pos2, radius: f32, max_speed: f32) {
     let major_step = 20.0; // Major tick interval
     let minor_step = 10.0; // Minor tick interval
@@ -427,6 +1023,30 @@ fn draw_help_menu(ui: &mut egui::Ui) {
             ui.label(egui::RichText::new("D").strong().color(egui::Color32::YELLOW));
             ui.label(": Steer Right");
         });
+        ui.horizontal(|ui| {
+            ui.label(egui::RichText::new("R").strong().color(egui::Color32::YELLOW));
+            ui.label(": Toggle Reverse Gear");
+        });
+        ui.horizontal(|ui| {
+            ui.label(egui::RichText::new("C").strong().color(egui::Color32::YELLOW));
+            ui.label(": Toggle Cruise Control");
+        });
+        ui.horizontal(|ui| {
+            ui.label(egui::RichText::new("P").strong().color(egui::Color32::YELLOW));
+            ui.label(": Toggle Path Follower");
+        });
+        ui.horizontal(|ui| {
+            ui.label(egui::RichText::new("O").strong().color(egui::Color32::YELLOW));
+            ui.label(": Switch Path Controller");
+        });
+        ui.horizontal(|ui| {
+            ui.label(egui::RichText::new("M").strong().color(egui::Color32::YELLOW));
+            ui.label(": Plan / Cancel Auto Park");
+        });
+        ui.horizontal(|ui| {
+            ui.label(egui::RichText::new("G").strong().color(egui::Color32::YELLOW));
+            ui.label(": Generate Race Track");
+        });
 
         ui.add_space(10.0);
 
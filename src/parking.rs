@@ -0,0 +1,533 @@
+use avian3d::prelude::*;
+use bevy::color::palettes::css::{AQUA, DARK_GRAY, FUCHSIA, SILVER, WHITE};
+use bevy::prelude::*;
+use serde::Deserialize;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+use std::f32::consts::{FRAC_PI_2, TAU};
+
+use crate::car_dynamics::{
+    ControlSystems, Ego, EgoControl, EgoState, Gear, MAX_STEERING_ANGLE, STEER_RATIO, WHEELBASE,
+    pedals_for_acceleration,
+};
+use crate::path::Polyline;
+use crate::path_follower::pure_pursuit;
+use crate::reeds_shepp::{PathPose, shortest_path};
+use crate::run_log::RunLog;
+use crate::scenario::Passable;
+use crate::semantic::SemanticClass;
+use crate::utils::normalize_angle;
+
+pub struct ParkingPlugin;
+
+impl Plugin for ParkingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Parking>()
+            .add_systems(Update, (plan_parking, draw_parking_slot))
+            .add_systems(Update, execute_parking.in_set(ControlSystems::Autopilot));
+    }
+}
+
+// Planner footprint, the car collider inflated by a safety margin
+const FOOTPRINT_LENGTH: f32 = 5.4;
+const FOOTPRINT_WIDTH: f32 = 2.4;
+const FOOTPRINT_HEIGHT: f32 = 0.6;
+const FOOTPRINT_Z: f32 = 0.64;
+
+// Hybrid A* search
+const XY_RESOLUTION: f32 = 1.0; // m
+const YAW_BINS: usize = 72;
+const PRIMITIVE_LENGTH: f32 = 1.6; // m
+const COLLISION_STEP: f32 = 0.4; // m
+const STEER_STEPS: usize = 2; // per side
+const MAX_EXPANSIONS: usize = 4000;
+const ANALYTIC_RANGE: f32 = 20.0; // m
+const REVERSE_PENALTY: f32 = 1.5;
+const SWITCH_PENALTY: f32 = 5.0;
+const STEER_PENALTY: f32 = 0.5;
+const STEER_CHANGE_PENALTY: f32 = 1.0;
+
+// Path execution
+const PARKING_SPEED: f32 = 1.5; // m/s
+const PARKING_DECELERATION: f32 = 1.0; // m/s^2
+const SPEED_GAIN: f32 = 1.5;
+const LOOKAHEAD: f32 = 2.5; // m
+const SEGMENT_END_DISTANCE: f32 = 0.3; // m
+const STOPPED_SPEED: f32 = 0.2; // m/s
+
+/// Target pose of the car centre inside a parking slot.
+#[derive(Component)]
+pub struct ParkingSlot {
+    pub position: Vec2,
+    pub yaw: f32,
+}
+
+#[derive(Component)]
+#[require(SemanticClass::Vehicle)]
+struct ParkedCar;
+
+/// Slot and parked cars of a parking bay.
+#[derive(Component)]
+pub struct ParkingBay;
+
+/// Parking bay as configured in a scenario's `parking_bay`: a slot at the car centre
+/// pose, between two parked cars.
+#[derive(Deserialize, Clone, Copy)]
+pub struct ParkingBaySpec {
+    pub x: f32,
+    pub y: f32,
+    pub yaw: f32, // rad
+}
+
+#[derive(Component)]
+struct ParkingGizmo;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum ParkingState {
+    #[default]
+    Idle,
+    Failed,
+    Executing,
+    Parked,
+}
+
+#[derive(Resource, Default)]
+pub struct Parking {
+    pub state: ParkingState,
+    pub show_search_tree: bool,
+    segments: Vec<(Polyline, bool)>, // path split at direction changes, reverse flag
+    current: usize,
+}
+
+/// Spawns the slot and the cars either side of it, returning all three entities.
+pub fn spawn_parking_bay(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+    spec: &ParkingBaySpec,
+) -> Vec<Entity> {
+    let slot = Vec2::new(spec.x, spec.y);
+    let mut entities = vec![
+        commands
+            .spawn((
+                ParkingBay,
+                ParkingSlot {
+                    position: slot,
+                    yaw: spec.yaw,
+                },
+            ))
+            .id(),
+    ];
+
+    // Parked cars lie along the slot's heading, with the car model's length on y
+    let size = Vec3::new(2.0, 5.0, 1.5);
+    let mesh = meshes.add(Cuboid::from_size(size));
+    let material = materials.add(StandardMaterial::from_color(SILVER));
+    let side = Vec2::from_angle(spec.yaw).perp();
+    for offset in [-3.5, 3.5] {
+        let position = slot + side * offset;
+        entities.push(
+            commands
+                .spawn((
+                    ParkingBay,
+                    ParkedCar,
+                    RigidBody::Static,
+                    Collider::cuboid(size.x, size.y, size.z),
+                    Mesh3d(mesh.clone()),
+                    MeshMaterial3d(material.clone()),
+                    Transform::from_translation(position.extend(size.z / 2.0))
+                        .with_rotation(Quat::from_rotation_z(spec.yaw - FRAC_PI_2)),
+                ))
+                .id(),
+        );
+    }
+    entities
+}
+
+fn draw_parking_slot(mut gizmos: Gizmos, slots: Query<&ParkingSlot>) {
+    for slot in &slots {
+        gizmos.rect(
+            Isometry3d::new(slot.position.extend(0.03), Quat::from_rotation_z(slot.yaw)),
+            Vec2::new(5.5, 2.8),
+            WHITE,
+        );
+    }
+}
+
+#[derive(Copy, Clone)]
+struct Node {
+    position: Vec2,
+    yaw: f32,
+    g: f32,
+    steer: f32,
+    reverse: bool,
+    parent: Option<usize>,
+}
+
+struct OpenEntry {
+    f: f32,
+    index: usize,
+}
+
+impl PartialEq for OpenEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.f == other.f
+    }
+}
+
+impl Eq for OpenEntry {}
+
+impl PartialOrd for OpenEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// Reversed so the binary heap pops the lowest cost first
+impl Ord for OpenEntry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.f.total_cmp(&self.f)
+    }
+}
+
+struct Planner<'a> {
+    spatial_query: &'a SpatialQuery<'a, 'a>,
+    filter: SpatialQueryFilter,
+    passable: &'a Query<'a, 'a, (), Passable>, // overlaps that don't block the car
+    footprint: Collider,
+    radius: f32, // minimum turning radius
+}
+
+struct Plan {
+    path: Vec<PathPose>,
+    tree: Vec<Vec<Vec2>>, // expanded primitives, for drawing
+}
+
+impl Planner<'_> {
+    fn is_free(&self, position: Vec2, yaw: f32) -> bool {
+        let mut free = true;
+        self.spatial_query.shape_intersections_callback(
+            &self.footprint,
+            position.extend(FOOTPRINT_Z),
+            Quat::from_rotation_z(yaw),
+            &self.filter,
+            |entity| {
+                free = self.passable.contains(entity);
+                free
+            },
+        );
+        free
+    }
+
+    fn heuristic(&self, position: Vec2, yaw: f32, goal: &ParkingSlot) -> f32 {
+        shortest_path(position, yaw, goal.position, goal.yaw, self.radius)
+            .map_or(f32::INFINITY, |path| path.length)
+            .max(position.distance(goal.position))
+    }
+
+    // Poses along a primitive every `COLLISION_STEP`, excluding the start
+    fn primitive(&self, node: &Node, steer: f32, reverse: bool) -> Vec<(Vec2, f32)> {
+        let steps = (PRIMITIVE_LENGTH / COLLISION_STEP).ceil() as usize;
+        let ds = PRIMITIVE_LENGTH / steps as f32 * if reverse { -1.0 } else { 1.0 };
+        let mut position = node.position;
+        let mut yaw = node.yaw;
+        (0..steps)
+            .map(|_| {
+                position += Vec2::from_angle(yaw) * ds;
+                yaw = normalize_angle(yaw + ds * steer.tan() / WHEELBASE);
+                (position, yaw)
+            })
+            .collect()
+    }
+
+    fn analytic_expansion(&self, node: &Node, goal: &ParkingSlot) -> Option<Vec<PathPose>> {
+        let path = shortest_path(
+            node.position,
+            node.yaw,
+            goal.position,
+            goal.yaw,
+            self.radius,
+        )?;
+        let poses = path.sample(node.position, node.yaw, self.radius, COLLISION_STEP);
+        poses
+            .iter()
+            .all(|p| self.is_free(p.position, p.yaw))
+            .then_some(poses)
+    }
+
+    fn plan(
+        &self,
+        start: Vec2,
+        start_yaw: f32,
+        goal: &ParkingSlot,
+    ) -> Result<Plan, Vec<Vec<Vec2>>> {
+        let max_steer = (WHEELBASE / self.radius).atan();
+        let steers: Vec<f32> = (-(STEER_STEPS as i32)..=STEER_STEPS as i32)
+            .map(|i| max_steer * i as f32 / STEER_STEPS as f32)
+            .collect();
+        let key = |position: Vec2, yaw: f32| {
+            (
+                (position.x / XY_RESOLUTION).round() as i32,
+                (position.y / XY_RESOLUTION).round() as i32,
+                ((yaw.rem_euclid(TAU) / TAU * YAW_BINS as f32).round() as usize) % YAW_BINS,
+            )
+        };
+
+        let mut nodes = vec![Node {
+            position: start,
+            yaw: start_yaw,
+            g: 0.0,
+            steer: 0.0,
+            reverse: false,
+            parent: None,
+        }];
+        let mut segments: Vec<Vec<(Vec2, f32)>> = vec![Vec::new()];
+        let mut best_g = HashMap::from([(key(start, start_yaw), 0.0)]);
+        let mut open = BinaryHeap::from([OpenEntry {
+            f: self.heuristic(start, start_yaw, goal),
+            index: 0,
+        }]);
+        let mut tree = Vec::new();
+
+        let mut expansions = 0;
+        while let Some(OpenEntry { index, .. }) = open.pop() {
+            if expansions >= MAX_EXPANSIONS {
+                break;
+            }
+            expansions += 1;
+            let node = nodes[index];
+
+            if node.position.distance(goal.position) < ANALYTIC_RANGE
+                && let Some(tail) = self.analytic_expansion(&node, goal)
+            {
+                let mut path = Vec::new();
+                let mut current = Some(index);
+                while let Some(i) = current {
+                    let reverse = nodes[i].reverse;
+                    for &(position, yaw) in segments[i].iter().rev() {
+                        path.push(PathPose {
+                            position,
+                            yaw,
+                            reverse,
+                        });
+                    }
+                    current = nodes[i].parent;
+                }
+                path.push(PathPose {
+                    position: start,
+                    yaw: start_yaw,
+                    reverse: false,
+                });
+                path.reverse();
+                path.extend(tail);
+                return Ok(Plan { path, tree });
+            }
+
+            for reverse in [false, true] {
+                for &steer in &steers {
+                    let poses = self.primitive(&node, steer, reverse);
+                    if !poses.iter().all(|&(p, yaw)| self.is_free(p, yaw)) {
+                        continue;
+                    }
+                    let (position, yaw) = *poses.last().unwrap();
+                    let mut cost = PRIMITIVE_LENGTH * if reverse { REVERSE_PENALTY } else { 1.0 }
+                        + STEER_PENALTY * steer.abs()
+                        + STEER_CHANGE_PENALTY * (steer - node.steer).abs();
+                    if node.parent.is_some() && reverse != node.reverse {
+                        cost += SWITCH_PENALTY;
+                    }
+                    let g = node.g + cost;
+                    let k = key(position, yaw);
+                    if best_g.get(&k).is_some_and(|&best| best <= g) {
+                        continue;
+                    }
+                    best_g.insert(k, g);
+
+                    let mut branch = vec![node.position];
+                    branch.extend(poses.iter().map(|(p, _)| *p));
+                    tree.push(branch);
+
+                    nodes.push(Node {
+                        position,
+                        yaw,
+                        g,
+                        steer,
+                        reverse,
+                        parent: Some(index),
+                    });
+                    segments.push(poses);
+                    open.push(OpenEntry {
+                        f: g + self.heuristic(position, yaw, goal),
+                        index: nodes.len() - 1,
+                    });
+                }
+            }
+        }
+        Err(tree)
+    }
+}
+
+// Split into direction segments for the path follower
+fn split_segments(path: &[PathPose]) -> Vec<(Polyline, bool)> {
+    let mut segments: Vec<(Vec<Vec2>, bool)> = Vec::new();
+    for pose in path {
+        match segments.last_mut() {
+            Some((points, reverse)) if *reverse == pose.reverse => points.push(pose.position),
+            last => {
+                // The cusp belongs to both segments
+                let cusp = last.and_then(|(points, _)| points.last().copied());
+                segments.push((
+                    cusp.into_iter().chain([pose.position]).collect(),
+                    pose.reverse,
+                ));
+            }
+        }
+    }
+    segments
+        .into_iter()
+        .filter(|(points, _)| points.len() > 1)
+        .map(|(points, reverse)| (Polyline::new(points, false), reverse))
+        .collect()
+}
+
+#[allow(clippy::too_many_arguments)]
+fn plan_parking(
+    mut commands: Commands,
+    key: Res<ButtonInput<KeyCode>>,
+    spatial_query: SpatialQuery,
+    car: Single<(Entity, &EgoState), With<Ego>>,
+    passable: Query<(), Passable>,
+    slot: Single<&ParkingSlot>,
+    mut parking: ResMut<Parking>,
+    mut gizmo_assets: ResMut<Assets<GizmoAsset>>,
+    old_gizmos: Query<Entity, With<ParkingGizmo>>,
+    mut run_log: ResMut<RunLog>,
+    time: Res<Time>,
+) {
+    if !key.just_pressed(KeyCode::KeyM) {
+        return;
+    }
+    if parking.state == ParkingState::Executing {
+        parking.state = ParkingState::Idle;
+        run_log.record(time.elapsed_secs(), "Parking", "Cancelled");
+        return;
+    }
+
+    let (entity, state) = *car;
+    let planner = Planner {
+        spatial_query: &spatial_query,
+        filter: SpatialQueryFilter::from_excluded_entities([entity]),
+        passable: &passable,
+        footprint: Collider::cuboid(FOOTPRINT_LENGTH, FOOTPRINT_WIDTH, FOOTPRINT_HEIGHT),
+        radius: WHEELBASE / MAX_STEERING_ANGLE.tan() * 1.2,
+    };
+    let start = Vec2::new(state.x, state.y);
+    let result = planner.plan(start, state.yaw, &slot);
+
+    // Same retained gizmo mechanism as the ground grid in `init::setup_gizmos`
+    for entity in &old_gizmos {
+        commands.entity(entity).despawn();
+    }
+    let mut gizmos = GizmoAsset::new();
+    let tree = match &result {
+        Ok(plan) => &plan.tree,
+        Err(tree) => tree,
+    };
+    if parking.show_search_tree {
+        for branch in tree {
+            gizmos.linestrip(branch.iter().map(|p| p.extend(0.04)), DARK_GRAY);
+        }
+    }
+
+    match result {
+        Ok(plan) => {
+            for w in plan.path.windows(2) {
+                let color = if w[1].reverse { FUCHSIA } else { AQUA };
+                gizmos.line(
+                    w[0].position.extend(0.06),
+                    w[1].position.extend(0.06),
+                    color,
+                );
+            }
+            parking.segments = split_segments(&plan.path);
+            parking.current = 0;
+            parking.state = ParkingState::Executing;
+            run_log.record(
+                time.elapsed_secs(),
+                "Parking",
+                format!(
+                    "Planned {} segments after {} nodes",
+                    parking.segments.len(),
+                    plan.tree.len()
+                ),
+            );
+        }
+        Err(tree) => {
+            parking.state = ParkingState::Failed;
+            run_log.record(
+                time.elapsed_secs(),
+                "Parking",
+                format!("No path found after {} nodes", tree.len()),
+            );
+        }
+    }
+    commands.spawn((
+        ParkingGizmo,
+        Gizmo {
+            handle: gizmo_assets.add(gizmos),
+            ..default()
+        },
+    ));
+}
+
+fn execute_parking(
+    car: Single<(&EgoState, &mut EgoControl), With<Ego>>,
+    mut parking: ResMut<Parking>,
+    mut run_log: ResMut<RunLog>,
+    time: Res<Time>,
+) {
+    if parking.state != ParkingState::Executing {
+        return;
+    }
+    let (state, mut control) = car.into_inner();
+    let position = Vec2::new(state.x, state.y);
+
+    let Some((path, reverse)) = parking.segments.get(parking.current) else {
+        parking.state = ParkingState::Parked;
+        return;
+    };
+    let reverse = *reverse;
+    let Some(nearest) = path.project(position) else {
+        return;
+    };
+    let remaining = path.length() - nearest.s;
+
+    if remaining < SEGMENT_END_DISTANCE && state.v.abs() < STOPPED_SPEED {
+        parking.current += 1;
+        if parking.current >= parking.segments.len() {
+            parking.state = ParkingState::Parked;
+            run_log.record(time.elapsed_secs(), "Parking", "Parked");
+        }
+        control.throttle = 0.0;
+        control.brake = 1.0;
+        return;
+    }
+
+    // Reversing is pure pursuit on a car facing backwards with mirrored steering
+    let target = path.sample(nearest.s + LOOKAHEAD);
+    let delta = if reverse {
+        -pure_pursuit(position, state.yaw + std::f32::consts::PI, target)
+    } else {
+        pure_pursuit(position, state.yaw, target)
+    };
+
+    let direction = if reverse { -1.0 } else { 1.0 };
+    let target_speed = PARKING_SPEED.min((2.0 * PARKING_DECELERATION * remaining.max(0.0)).sqrt());
+    let speed = state.v * direction;
+    let acceleration = SPEED_GAIN * (target_speed - speed);
+
+    control.gear = if reverse { Gear::Reverse } else { Gear::Drive };
+    (control.throttle, control.brake) = pedals_for_acceleration(acceleration, speed);
+    control.front_wheel_angle = delta.clamp(-MAX_STEERING_ANGLE, MAX_STEERING_ANGLE);
+    control.steer_wheel_angle = control.front_wheel_angle * STEER_RATIO;
+}
@@ -0,0 +1,123 @@
+use bevy::prelude::*;
+
+/// Polyline in the ground plane, parameterised by arc length `s`.
+#[derive(Clone, Debug, Default)]
+pub struct Polyline {
+    points: Vec<Vec2>,
+    s: Vec<f32>, // arc length at each point
+    closed: bool,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct PathProjection {
+    pub s: f32,
+    pub point: Vec2,
+    pub heading: f32,
+    pub lateral: f32, // signed distance, positive on the left of the path
+}
+
+impl Polyline {
+    pub fn new(points: Vec<Vec2>, closed: bool) -> Self {
+        let mut s = Vec::with_capacity(points.len() + 1);
+        let mut length = 0.0;
+        s.push(length);
+        for w in points.windows(2) {
+            length += w[0].distance(w[1]);
+            s.push(length);
+        }
+        if closed && points.len() > 1 {
+            length += points[points.len() - 1].distance(points[0]);
+            s.push(length);
+        }
+        Self { points, s, closed }
+    }
+
+    pub fn points(&self) -> &[Vec2] {
+        &self.points
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    pub fn length(&self) -> f32 {
+        self.s.last().copied().unwrap_or(0.0)
+    }
+
+    fn segment_count(&self) -> usize {
+        self.s.len().saturating_sub(1)
+    }
+
+    fn segment(&self, i: usize) -> (Vec2, Vec2) {
+        (self.points[i], self.points[(i + 1) % self.points.len()])
+    }
+
+    // Wraps `s` on closed paths, clamps it on open ones
+    fn normalize_s(&self, s: f32) -> f32 {
+        let length = self.length();
+        if self.closed && length > 0.0 {
+            s.rem_euclid(length)
+        } else {
+            s.clamp(0.0, length)
+        }
+    }
+
+    fn segment_at(&self, s: f32) -> usize {
+        let i = self.s.partition_point(|&si| si <= s);
+        i.saturating_sub(1)
+            .min(self.segment_count().saturating_sub(1))
+    }
+
+    pub fn sample(&self, s: f32) -> Vec2 {
+        if self.points.len() < 2 {
+            return self.points.first().copied().unwrap_or(Vec2::ZERO);
+        }
+        let s = self.normalize_s(s);
+        let i = self.segment_at(s);
+        let (a, b) = self.segment(i);
+        let seg_len = self.s[i + 1] - self.s[i];
+        if seg_len <= f32::EPSILON {
+            return a;
+        }
+        a.lerp(b, (s - self.s[i]) / seg_len)
+    }
+
+    pub fn heading_at(&self, s: f32) -> f32 {
+        if self.points.len() < 2 {
+            return 0.0;
+        }
+        let (a, b) = self.segment(self.segment_at(self.normalize_s(s)));
+        let d = b - a;
+        d.y.atan2(d.x)
+    }
+
+    /// Closest point on the path to `p`.
+    pub fn project(&self, p: Vec2) -> Option<PathProjection> {
+        let mut best: Option<(f32, PathProjection)> = None;
+        for i in 0..self.segment_count() {
+            let (a, b) = self.segment(i);
+            let d = b - a;
+            let len_sq = d.length_squared();
+            let t = if len_sq > 0.0 {
+                ((p - a).dot(d) / len_sq).clamp(0.0, 1.0)
+            } else {
+                0.0
+            };
+            let point = a + d * t;
+            let dist_sq = p.distance_squared(point);
+            if best.is_none_or(|(best_sq, _)| dist_sq < best_sq) {
+                let lateral = d.normalize_or_zero().perp_dot(p - point);
+                best = Some((
+                    dist_sq,
+                    PathProjection {
+                        s: self.s[i] + len_sq.sqrt() * t,
+                        point,
+                        heading: d.y.atan2(d.x),
+                        lateral,
+                    },
+                ));
+            }
+        }
+        best.map(|(_, projection)| projection)
+    }
+}
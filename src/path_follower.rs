@@ -0,0 +1,203 @@
+use bevy::color::palettes::css::{LIME, ORANGE, YELLOW};
+use bevy::prelude::*;
+use std::f32::consts::PI;
+
+use crate::car_dynamics::{
+    ControlSystems, Ego, EgoControl, EgoState, MAX_STEERING_ANGLE, STEER_RATIO, WHEELBASE,
+    pedals_for_acceleration,
+};
+use crate::mpc::MpcSolver;
+use crate::path::Polyline;
+use crate::utils::normalize_angle;
+
+pub struct PathFollowerPlugin;
+
+impl Plugin for PathFollowerPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(PostStartup, attach_demo_path)
+            .add_systems(Update, toggle_path_follower)
+            .add_systems(Update, follow_path.in_set(ControlSystems::Autopilot))
+            .add_systems(Update, draw_path);
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PathController {
+    /// Lookahead distance grows with speed: `min_lookahead + lookahead_gain * v`.
+    PurePursuit {
+        min_lookahead: f32,  // m
+        lookahead_gain: f32, // s
+    },
+    /// Cross-track gain `gain` with low-speed `softening` (m/s).
+    Stanley { gain: f32, softening: f32 },
+    /// Joint steering and speed tracking, also drives the pedals.
+    Mpc { target_speed: f32 }, // m/s
+}
+
+impl PathController {
+    pub const PURE_PURSUIT: Self = Self::PurePursuit {
+        min_lookahead: 4.0,
+        lookahead_gain: 0.8,
+    };
+    pub const STANLEY: Self = Self::Stanley {
+        gain: 1.5,
+        softening: 1.0,
+    };
+    pub const MPC: Self = Self::Mpc { target_speed: 10.0 };
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::PurePursuit { .. } => "Pure Pursuit",
+            Self::Stanley { .. } => "Stanley",
+            Self::Mpc { .. } => "MPC",
+        }
+    }
+}
+
+/// Steers the car along `path` when enabled.
+#[derive(Component)]
+pub struct PathFollower {
+    pub path: Polyline,
+    pub controller: PathController,
+    pub enabled: bool,
+    target: Option<Vec2>, // lookahead or nearest path point, for drawing
+    mpc: MpcSolver,
+}
+
+impl PathFollower {
+    pub fn new(path: Polyline, controller: PathController) -> Self {
+        Self {
+            path,
+            controller,
+            enabled: false,
+            target: None,
+            mpc: MpcSolver::default(),
+        }
+    }
+}
+
+// Stadium shaped loop starting at the origin with a left turn, clear of the obstacle
+fn demo_path() -> Polyline {
+    let radius = 15.0;
+    let straight = 40.0;
+    let steps = 24;
+    let mut points = Vec::new();
+    for i in 0..steps {
+        let a = -PI / 2.0 + PI * i as f32 / steps as f32;
+        points.push(Vec2::new(0.0, radius) + Vec2::from_angle(a) * radius);
+    }
+    for i in 0..steps {
+        let a = PI / 2.0 + PI * i as f32 / steps as f32;
+        points.push(Vec2::new(-straight, radius) + Vec2::from_angle(a) * radius);
+    }
+    Polyline::new(points, true)
+}
+
+fn attach_demo_path(mut commands: Commands, car: Single<Entity, With<Ego>>) {
+    commands
+        .entity(*car)
+        .insert(PathFollower::new(demo_path(), PathController::PURE_PURSUIT));
+}
+
+fn toggle_path_follower(
+    key: Res<ButtonInput<KeyCode>>,
+    mut follower: Single<&mut PathFollower, With<Ego>>,
+) {
+    if key.just_pressed(KeyCode::KeyP) {
+        follower.enabled = !follower.enabled;
+    }
+    if key.just_pressed(KeyCode::KeyO) {
+        follower.controller = match follower.controller {
+            PathController::PurePursuit { .. } => PathController::STANLEY,
+            PathController::Stanley { .. } => PathController::MPC,
+            PathController::Mpc { .. } => PathController::PURE_PURSUIT,
+        };
+    }
+}
+
+/// Front wheel angle putting the rear axle at `rear` on an arc through `target`.
+pub fn pure_pursuit(rear: Vec2, yaw: f32, target: Vec2) -> f32 {
+    let to_target = target - rear;
+    let alpha = Vec2::from_angle(yaw).angle_to(to_target);
+    (2.0 * WHEELBASE * alpha.sin()).atan2(to_target.length())
+}
+
+fn follow_path(
+    car: Single<(&EgoState, &mut EgoControl, &mut PathFollower), With<Ego>>,
+    time: Res<Time>,
+) {
+    let (state, mut control, mut follower) = car.into_inner();
+    if !follower.enabled || !matches!(follower.controller, PathController::Mpc { .. }) {
+        follower.mpc.reset();
+    }
+    if !follower.enabled {
+        follower.target = None;
+        return;
+    }
+
+    // EgoState is referenced at the vehicle centre, midway between the axles
+    let centre = Vec2::new(state.x, state.y);
+    let heading = Vec2::from_angle(state.yaw);
+    let rear = centre - heading * WHEELBASE / 2.0;
+    let (delta, target) = match follower.controller {
+        PathController::PurePursuit {
+            min_lookahead,
+            lookahead_gain,
+        } => {
+            let Some(nearest) = follower.path.project(rear) else {
+                return;
+            };
+            let lookahead = min_lookahead + lookahead_gain * state.v.max(0.0);
+            let target = follower.path.sample(nearest.s + lookahead);
+            (pure_pursuit(rear, state.yaw, target), target)
+        }
+        PathController::Stanley { gain, softening } => {
+            let front = rear + heading * WHEELBASE;
+            let Some(nearest) = follower.path.project(front) else {
+                return;
+            };
+            let heading_error = normalize_angle(nearest.heading - state.yaw);
+            let cross_track = (-gain * nearest.lateral).atan2(softening + state.v.max(0.0));
+            (heading_error + cross_track, nearest.point)
+        }
+        PathController::Mpc { target_speed } => {
+            let follower = &mut *follower;
+            let Some(command) = follower.mpc.solve(
+                state,
+                control.front_wheel_angle,
+                &follower.path,
+                target_speed,
+                time.delta_secs(),
+            ) else {
+                return;
+            };
+            (control.throttle, control.brake) =
+                pedals_for_acceleration(command.acceleration, state.v);
+            let target = follower.mpc.predicted().last().copied().unwrap_or(centre);
+            (command.front_wheel_angle, target)
+        }
+    };
+
+    control.front_wheel_angle = delta.clamp(-MAX_STEERING_ANGLE, MAX_STEERING_ANGLE);
+    control.steer_wheel_angle = control.front_wheel_angle * STEER_RATIO;
+    follower.target = Some(target);
+}
+
+fn draw_path(mut gizmos: Gizmos, follower: Single<&PathFollower, With<Ego>>) {
+    const Z: f32 = 0.05; // just above the ground plane
+    let points = follower.path.points();
+    let mut strip: Vec<Vec3> = points.iter().map(|p| p.extend(Z)).collect();
+    if let Some(first) = strip.first().copied()
+        && follower.path.is_closed()
+    {
+        strip.push(first);
+    }
+    gizmos.linestrip(strip, YELLOW);
+    let predicted = follower.mpc.predicted();
+    if !predicted.is_empty() {
+        gizmos.linestrip(predicted.iter().map(|p| p.extend(Z)), LIME);
+    }
+    if let Some(target) = follower.target {
+        gizmos.sphere(Isometry3d::from_translation(target.extend(Z)), 0.4, ORANGE);
+    }
+}
@@ -0,0 +1,321 @@
+use avian3d::prelude::*;
+use bevy::color::palettes::css::{GRAY, ORANGE_RED};
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use crate::car_dynamics::{CAR_LENGTH, Car, EgoState};
+use crate::faults::{FaultTarget, Faults};
+use crate::scenario::GroundPlane;
+use crate::sensor::Mount;
+use crate::utils::{SplitMix64, normalize_angle};
+
+pub struct RadarPlugin;
+
+impl Plugin for RadarPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_message::<RadarScan>()
+            .add_systems(Update, (scan_radars, draw_radar_detections).chain());
+        #[cfg(not(target_arch = "wasm32"))]
+        app.add_systems(Startup, recording::setup_recorder)
+            .add_systems(Update, recording::record_scans.after(scan_radars));
+    }
+}
+
+// Strength of clutter returns, dBsm
+const CLUTTER_RCS: (f32, f32) = (-20.0, -5.0);
+
+/// Object-level radar: one detection per visible object, from the point of its collider
+/// nearest the sensor.
+#[derive(Component, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct Radar {
+    pub enabled: bool,
+    pub mount: Mount,
+    pub fov: f32,                   // deg, full horizontal field of view
+    pub range: f32,                 // m
+    pub rate: f32,                  // Hz, scans per second
+    pub range_noise: f32,           // m, standard deviation
+    pub range_rate_noise: f32,      // m/s, standard deviation
+    pub azimuth_noise: f32,         // deg, standard deviation
+    pub rcs_noise: f32,             // dB, standard deviation
+    pub detection_probability: f32, // of each visible object, per scan
+    pub clutter: f32,               // mean false detections per scan
+    pub seed: u64,
+    #[serde(skip)]
+    next_scan: f32, // s since startup
+    #[serde(skip)]
+    rng: Option<SplitMix64>,
+    /// Detections of the last scan.
+    #[serde(skip)]
+    pub detections: Vec<RadarDetection>,
+    #[serde(skip)]
+    pub sensor_to_world: Transform,
+}
+
+impl Default for Radar {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            mount: Mount::new(CAR_LENGTH / 2.0, 0.0, 0.5),
+            fov: 90.0,
+            range: 150.0,
+            rate: 20.0,
+            range_noise: 0.1,
+            range_rate_noise: 0.1,
+            azimuth_noise: 0.5,
+            rcs_noise: 1.0,
+            detection_probability: 0.95,
+            clutter: 0.5,
+            seed: 0,
+            next_scan: 0.0,
+            rng: None,
+            detections: Vec::new(),
+            sensor_to_world: Transform::default(),
+        }
+    }
+}
+
+impl Radar {
+    /// Radar fitted to the car but switched off.
+    pub fn disabled() -> Self {
+        Self {
+            enabled: false,
+            ..default()
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct RadarDetection {
+    pub range: f32,      // m
+    pub range_rate: f32, // m/s, negative when closing
+    pub azimuth: f32,    // rad, positive to the left of the boresight
+    pub rcs: f32,        // dBsm, from the visible cross-section of the target
+    /// Detected entity, none for clutter. Ground truth, for evaluation only.
+    pub target: Option<Entity>,
+}
+
+impl RadarDetection {
+    /// Position in the sensor frame, on the ground plane of the sensor.
+    pub fn position(&self) -> Vec2 {
+        Vec2::from_angle(self.azimuth) * self.range
+    }
+}
+
+/// Detections of one scan, written for every radar each time it samples.
+#[derive(Message, Clone, Debug)]
+pub struct RadarScan {
+    #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+    pub time: f32, // s since startup
+    pub sensor_to_world: Transform,
+    pub detections: Vec<RadarDetection>,
+}
+
+#[allow(clippy::too_many_arguments)]
+fn scan_radars(
+    mut radars: Query<(Entity, &mut Radar, &EgoState), With<Car>>,
+    objects: Query<(Entity, &Collider, &Position, &Rotation), Without<GroundPlane>>,
+    states: Query<&EgoState>,
+    velocities: Query<&LinearVelocity>,
+    sensors: Query<(), With<Sensor>>,
+    spatial_query: SpatialQuery,
+    mut scans: MessageWriter<RadarScan>,
+    mut faults: ResMut<Faults>,
+    time: Res<Time>,
+) {
+    let now = time.elapsed_secs();
+    for (car, mut radar, state) in &mut radars {
+        if !radar.enabled || now < radar.next_scan {
+            continue;
+        }
+        radar.next_scan = now + 1.0 / radar.rate.max(0.1);
+        let seed = radar.seed;
+        let mut rng = radar.rng.take().unwrap_or(SplitMix64::new(seed));
+
+        let pose = radar.mount.world_pose(state);
+        let to_sensor = pose.compute_affine().inverse();
+        let half_fov = radar.fov.to_radians() / 2.0;
+        let ego_velocity = Vec2::from_angle(state.yaw) * state.v;
+        let filter = SpatialQueryFilter::from_excluded_entities([car]);
+        let solid = |entity| !sensors.contains(entity);
+
+        let mut detections = Vec::new();
+        for (entity, collider, position, rotation) in &objects {
+            if entity == car || sensors.contains(entity) {
+                continue;
+            }
+            // Reflection from the nearest point of the target, if nothing is in between
+            let (point, _) = collider.project_point(*position, *rotation, pose.translation, true);
+            let local = to_sensor.transform_point3(point);
+            let range = local.length();
+            let azimuth = local.y.atan2(local.x);
+            if range > radar.range || range < f32::EPSILON || azimuth.abs() > half_fov {
+                continue;
+            }
+            let line_of_sight = (point - pose.translation) / range;
+            let first_hit = spatial_query.cast_ray_predicate(
+                pose.translation,
+                Dir3::new_unchecked(line_of_sight),
+                range + 0.1,
+                true,
+                &filter,
+                &solid,
+            );
+            if first_hit.is_some_and(|hit| hit.entity != entity) {
+                continue;
+            }
+            if rng.next_f32() >= radar.detection_probability {
+                continue;
+            }
+
+            let target_velocity = match states.get(entity) {
+                Ok(target) => Vec2::from_angle(target.yaw) * target.v,
+                Err(_) => velocities.get(entity).map_or(Vec2::ZERO, |v| v.truncate()),
+            };
+            let range_rate = (target_velocity - ego_velocity).dot(line_of_sight.truncate());
+            // Cross-section seen along the line of sight, from the bounding box
+            let aabb = collider.aabb(position.0, *rotation);
+            let size = aabb.max - aabb.min;
+            let across = (size.x * line_of_sight.y).abs() + (size.y * line_of_sight.x).abs();
+            let rcs = 10.0 * (across * size.z).max(0.01).log10();
+
+            detections.push(RadarDetection {
+                range: range + rng.gaussian() * radar.range_noise,
+                range_rate: range_rate + rng.gaussian() * radar.range_rate_noise,
+                azimuth: azimuth + rng.gaussian() * radar.azimuth_noise.to_radians(),
+                rcs: rcs + rng.gaussian() * radar.rcs_noise,
+                target: Some(entity),
+            });
+        }
+
+        // Clutter from the stationary surroundings, a Poisson number of returns
+        let threshold = (-radar.clutter).exp();
+        let mut p = rng.next_f32();
+        while p > threshold {
+            let azimuth = rng.range(-half_fov, half_fov);
+            let heading = normalize_angle(azimuth + pose.rotation.to_euler(EulerRot::ZYX).0);
+            detections.push(RadarDetection {
+                range: rng.range(0.0, radar.range),
+                range_rate: -ego_velocity.dot(Vec2::from_angle(heading)),
+                azimuth,
+                rcs: rng.range(CLUTTER_RCS.0, CLUTTER_RCS.1),
+                target: None,
+            });
+            p *= rng.next_f32();
+        }
+
+        radar.rng = Some(rng);
+        let scan = RadarScan {
+            time: now,
+            sensor_to_world: pose,
+            detections,
+        };
+        let Some(scan) = faults.apply(FaultTarget::Radar, scan, now, |scan, error| {
+            for detection in &mut scan.detections {
+                detection.range = (detection.range + error()).max(0.0);
+            }
+        }) else {
+            continue;
+        };
+        radar.sensor_to_world = scan.sensor_to_world;
+        radar.detections = scan.detections.clone();
+        scans.write(scan);
+    }
+}
+
+fn draw_radar_detections(mut gizmos: Gizmos, radars: Query<&Radar>) {
+    for radar in &radars {
+        if !radar.enabled {
+            continue;
+        }
+        let pose = radar.sensor_to_world;
+        // Field of view edges
+        let half_fov = radar.fov.to_radians() / 2.0;
+        for angle in [-half_fov, half_fov] {
+            let edge = Vec2::from_angle(angle) * radar.range;
+            gizmos.line(
+                pose.translation,
+                pose.transform_point(edge.extend(0.0)),
+                GRAY,
+            );
+        }
+        for detection in &radar.detections {
+            let position = pose.transform_point(detection.position().extend(0.0));
+            let color = if detection.target.is_some() {
+                ORANGE_RED
+            } else {
+                GRAY
+            };
+            gizmos.sphere(Isometry3d::from_translation(position), 0.3, color);
+        }
+    }
+}
+
+/// Detections saved as CSV, one row per detection in the sensor frame, with
+/// `--record-radar <file>`.
+#[cfg(not(target_arch = "wasm32"))]
+mod recording {
+    use bevy::prelude::*;
+    use std::fs::File;
+    use std::io::{BufWriter, Write};
+
+    use super::RadarScan;
+    use crate::utils::launch_argument;
+
+    #[derive(Resource)]
+    pub struct RadarRecorder(BufWriter<File>);
+
+    pub fn setup_recorder(mut commands: Commands) {
+        let Some(path) = launch_argument("record-radar") else {
+            return;
+        };
+        let mut file = match File::create(&path) {
+            Ok(file) => BufWriter::new(file),
+            Err(e) => {
+                error!("Cannot record radar detections to {}: {}", path, e);
+                return;
+            }
+        };
+        if let Err(e) = writeln!(
+            file,
+            "time,sensor_x,sensor_y,sensor_yaw,range,range_rate,azimuth,rcs"
+        ) {
+            error!("Failed to write {}: {}", path, e);
+            return;
+        }
+        info!("Recording radar detections to {}", path);
+        commands.insert_resource(RadarRecorder(file));
+    }
+
+    pub fn record_scans(
+        mut scans: MessageReader<RadarScan>,
+        recorder: Option<ResMut<RadarRecorder>>,
+    ) {
+        let Some(mut recorder) = recorder else {
+            scans.clear();
+            return;
+        };
+        for scan in scans.read() {
+            let sensor = scan.sensor_to_world;
+            let (yaw, _, _) = sensor.rotation.to_euler(EulerRot::ZYX);
+            for detection in &scan.detections {
+                let row = writeln!(
+                    recorder.0,
+                    "{:.3},{:.3},{:.3},{:.4},{:.3},{:.3},{:.4},{:.1}",
+                    scan.time,
+                    sensor.translation.x,
+                    sensor.translation.y,
+                    yaw,
+                    detection.range,
+                    detection.range_rate,
+                    detection.azimuth,
+                    detection.rcs
+                );
+                if let Err(e) = row {
+                    error!("Failed to record radar detections: {}", e);
+                    return;
+                }
+            }
+        }
+    }
+}
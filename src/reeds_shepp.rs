@@ -0,0 +1,259 @@
+use bevy::prelude::*;
+use std::f32::consts::{FRAC_PI_2, PI};
+
+use crate::utils::normalize_angle;
+
+// Shortest Reeds-Shepp paths over the CSC and CCC families, each with its
+// time-flipped, reflected and backwards variants (Reeds & Shepp, 1990).
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PathPose {
+    pub position: Vec2,
+    pub yaw: f32,
+    pub reverse: bool,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Steer {
+    Left,
+    Straight,
+    Right,
+}
+
+#[derive(Clone, Debug)]
+pub struct ReedsSheppPath {
+    segments: Vec<(Steer, f32)>, // signed length, negative when reversing
+    pub length: f32,             // m
+}
+
+impl ReedsSheppPath {
+    /// Poses every `step` metres along the path, starting at `start`.
+    pub fn sample(&self, start: Vec2, start_yaw: f32, radius: f32, step: f32) -> Vec<PathPose> {
+        let mut poses = Vec::new();
+        let mut position = start;
+        let mut yaw = start_yaw;
+        for &(steer, length) in &self.segments {
+            let reverse = length < 0.0;
+            poses.push(PathPose {
+                position,
+                yaw,
+                reverse,
+            });
+            let distance = length.abs() * radius;
+            let count = (distance / step).ceil().max(1.0) as usize;
+            let ds = length.signum() * distance / count as f32;
+            for _ in 0..count {
+                (position, yaw) = advance(position, yaw, steer, ds, radius);
+                poses.push(PathPose {
+                    position,
+                    yaw,
+                    reverse,
+                });
+            }
+        }
+        poses
+    }
+}
+
+fn advance(position: Vec2, yaw: f32, steer: Steer, ds: f32, radius: f32) -> (Vec2, f32) {
+    let curvature = match steer {
+        Steer::Left => 1.0 / radius,
+        Steer::Straight => 0.0,
+        Steer::Right => -1.0 / radius,
+    };
+    if curvature == 0.0 {
+        return (position + Vec2::from_angle(yaw) * ds, yaw);
+    }
+    let new_yaw = yaw + curvature * ds;
+    let offset = Vec2::new(new_yaw.sin() - yaw.sin(), -(new_yaw.cos() - yaw.cos())) / curvature;
+    (position + offset, normalize_angle(new_yaw))
+}
+
+/// Shortest path from `start` to `goal` for a car with minimum turning `radius`.
+pub fn shortest_path(
+    start: Vec2,
+    start_yaw: f32,
+    goal: Vec2,
+    goal_yaw: f32,
+    radius: f32,
+) -> Option<ReedsSheppPath> {
+    // Goal in the start frame, scaled to a unit turning radius
+    let d = Vec2::from_angle(-start_yaw).rotate(goal - start) / radius;
+    let phi = normalize_angle(goal_yaw - start_yaw);
+
+    let mut best: Option<Vec<(Steer, f32)>> = None;
+    let mut best_length = f32::INFINITY;
+    for segments in candidate_paths(d.x, d.y, phi) {
+        let length = segments.iter().map(|(_, l)| l.abs()).sum::<f32>();
+        if length < best_length {
+            best_length = length;
+            best = Some(segments);
+        }
+    }
+    best.map(|segments| ReedsSheppPath {
+        segments,
+        length: best_length * radius,
+    })
+}
+
+type Word = [(Steer, f32); 3];
+type Family = fn(f32, f32, f32) -> Option<Word>;
+
+fn candidate_paths(x: f32, y: f32, phi: f32) -> Vec<Vec<(Steer, f32)>> {
+    let mut paths = Vec::new();
+    let mut push = |word: Option<Word>, flip: bool, reflect: bool| {
+        if let Some(word) = word {
+            paths.push(
+                word.iter()
+                    .map(|&(steer, length)| {
+                        let steer = match (reflect, steer) {
+                            (true, Steer::Left) => Steer::Right,
+                            (true, Steer::Right) => Steer::Left,
+                            (_, steer) => steer,
+                        };
+                        (steer, if flip { -length } else { length })
+                    })
+                    .collect(),
+            );
+        }
+    };
+
+    let families: [Family; 4] = [
+        left_straight_left,
+        left_straight_right,
+        left_x_right_x_left,
+        left_x_right_left,
+    ];
+    for family in families {
+        push(family(x, y, phi), false, false);
+        push(family(-x, y, -phi), true, false);
+        push(family(x, -y, -phi), false, true);
+        push(family(-x, -y, phi), true, true);
+    }
+
+    paths
+}
+
+fn polar(x: f32, y: f32) -> (f32, f32) {
+    (x.hypot(y), y.atan2(x))
+}
+
+fn left_straight_left(x: f32, y: f32, phi: f32) -> Option<Word> {
+    let (u, t) = polar(x - phi.sin(), y - 1.0 + phi.cos());
+    if (0.0..=PI).contains(&t) {
+        let v = normalize_angle(phi - t);
+        if (0.0..=PI).contains(&v) {
+            return Some([(Steer::Left, t), (Steer::Straight, u), (Steer::Left, v)]);
+        }
+    }
+    None
+}
+
+fn left_straight_right(x: f32, y: f32, phi: f32) -> Option<Word> {
+    let (u1, t1) = polar(x + phi.sin(), y - 1.0 - phi.cos());
+    let u1 = u1 * u1;
+    if u1 < 4.0 {
+        return None;
+    }
+    let u = (u1 - 4.0).sqrt();
+    let t = normalize_angle(t1 + 2.0f32.atan2(u));
+    let v = normalize_angle(t - phi);
+    if t < 0.0 || v < 0.0 {
+        return None;
+    }
+    Some([(Steer::Left, t), (Steer::Straight, u), (Steer::Right, v)])
+}
+
+fn left_x_right_x_left(x: f32, y: f32, phi: f32) -> Option<Word> {
+    let (u1, theta) = polar(x - phi.sin(), y - 1.0 + phi.cos());
+    if u1 > 4.0 {
+        return None;
+    }
+    let a = (0.25 * u1).acos();
+    let t = normalize_angle(a + theta + FRAC_PI_2);
+    let u = normalize_angle(PI - 2.0 * a);
+    let v = normalize_angle(phi - t - u);
+    Some([(Steer::Left, t), (Steer::Right, -u), (Steer::Left, v)])
+}
+
+// C|C|C run backwards from the goal, the same arcs in reverse order
+fn left_x_right_left(x: f32, y: f32, phi: f32) -> Option<Word> {
+    let xb = x * phi.cos() + y * phi.sin();
+    let yb = x * phi.sin() - y * phi.cos();
+    left_x_right_x_left(xb, yb, phi).map(|[a, b, c]| [c, b, a])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Goals in the start frame, for a unit turning radius
+    fn goals() -> impl Iterator<Item = (f32, f32, f32)> {
+        (-4..=4).flat_map(|i| {
+            (-4..=4).flat_map(move |j| {
+                (-3..=3).map(move |k| (i as f32 * 0.7, j as f32 * 0.6, k as f32 * 0.9))
+            })
+        })
+    }
+
+    fn end_pose(segments: &[(Steer, f32)]) -> (Vec2, f32) {
+        let path = ReedsSheppPath {
+            segments: segments.to_vec(),
+            length: 0.0,
+        };
+        let last = path.sample(Vec2::ZERO, 0.0, 1.0, 0.05).pop().unwrap();
+        (last.position, last.yaw)
+    }
+
+    fn assert_reaches(segments: &[(Steer, f32)], (x, y, phi): (f32, f32, f32), name: &str) {
+        let (position, yaw) = end_pose(segments);
+        assert!(
+            position.distance(Vec2::new(x, y)) < 1e-3 && normalize_angle(yaw - phi).abs() < 1e-3,
+            "{name} to ({x}, {y}, {phi}) ends at {position} {yaw}: {segments:?}"
+        );
+    }
+
+    #[test]
+    fn every_family_ends_at_the_goal() {
+        let families: [(&str, Family); 4] = [
+            ("LSL", left_straight_left),
+            ("LSR", left_straight_right),
+            ("L|R|L", left_x_right_x_left),
+            ("L|RL", left_x_right_left),
+        ];
+        for (name, family) in families {
+            let mut found = 0;
+            for goal in goals() {
+                if let Some(word) = family(goal.0, goal.1, goal.2) {
+                    assert_reaches(&word, goal, name);
+                    found += 1;
+                }
+            }
+            assert!(found > 0, "{name} never applies");
+        }
+    }
+
+    #[test]
+    fn every_candidate_ends_at_the_goal() {
+        for goal in goals() {
+            for segments in candidate_paths(goal.0, goal.1, goal.2) {
+                assert_reaches(&segments, goal, "candidate");
+            }
+        }
+    }
+
+    #[test]
+    fn backwards_family_differs_from_forwards() {
+        let differs = goals().any(|(x, y, phi)| {
+            match (left_x_right_x_left(x, y, phi), left_x_right_left(x, y, phi)) {
+                (Some(forwards), Some(backwards)) => forwards
+                    .iter()
+                    .zip(&backwards)
+                    .any(|(a, b)| (a.1 - b.1).abs() > 1e-3),
+                (None, Some(_)) => true,
+                _ => false,
+            }
+        });
+        assert!(differs);
+    }
+}
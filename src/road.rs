@@ -0,0 +1,146 @@
+use bevy::color::palettes::css::WHITE;
+use bevy::prelude::*;
+
+use crate::path::Polyline;
+use crate::utils::normalize_angle;
+
+pub struct RoadPlugin;
+
+impl Plugin for RoadPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RoadNetwork>()
+            .add_systems(Startup, setup_default_road)
+            .add_systems(Update, draw_lane_markings);
+    }
+}
+
+pub const LANE_WIDTH: f32 = 3.5;
+// Distance beyond a lane boundary a pose still counts as in that lane, about half a car
+// width
+const OFF_ROAD_MARGIN: f32 = 1.0; // m
+
+pub struct Lane {
+    pub centerline: Polyline,
+    pub width: f32,
+}
+
+/// Reference line of a road, along which road coordinates are measured.
+pub struct RoadReference {
+    pub id: String,
+    pub reference: Polyline,
+}
+
+/// Drivable lanes of the world, queried by assistance systems.
+#[derive(Resource, Default)]
+pub struct RoadNetwork {
+    pub lanes: Vec<Lane>,
+    pub roads: Vec<RoadReference>,
+}
+
+/// Position along (s) and across (t, positive to the left) a road reference line.
+#[derive(Copy, Clone, Debug)]
+pub struct RoadCoordinates {
+    pub road: usize,
+    pub s: f32,
+    pub t: f32,
+}
+
+/// Where a pose lies relative to a lane.
+#[derive(Copy, Clone, Debug)]
+pub struct LanePosition {
+    pub lane: usize,
+    pub s: f32,
+    pub offset: f32,        // from the lane centre, positive to the left
+    pub heading_error: f32, // pose yaw minus lane heading
+    pub width: f32,
+}
+
+impl RoadNetwork {
+    /// Lane containing `position`, or the nearest lane if it is just outside it. None
+    /// once it is off-road, more than a margin beyond the boundary of every lane.
+    pub fn localize(&self, position: Vec2, yaw: f32) -> Option<LanePosition> {
+        self.lanes
+            .iter()
+            .enumerate()
+            .filter_map(|(i, lane)| {
+                let p = lane.centerline.project(position)?;
+                Some(LanePosition {
+                    lane: i,
+                    s: p.s,
+                    offset: p.lateral,
+                    heading_error: normalize_angle(yaw - p.heading),
+                    width: lane.width,
+                })
+            })
+            .min_by(|a, b| a.offset.abs().total_cmp(&b.offset.abs()))
+            .filter(|lane| lane.offset.abs() <= lane.width / 2.0 + OFF_ROAD_MARGIN)
+    }
+
+    /// Coordinates on the road whose reference line is nearest to `position`.
+    pub fn road_coordinates(&self, position: Vec2) -> Option<RoadCoordinates> {
+        self.roads
+            .iter()
+            .enumerate()
+            .filter_map(|(i, road)| {
+                let p = road.reference.project(position)?;
+                Some(RoadCoordinates {
+                    road: i,
+                    s: p.s,
+                    t: p.lateral,
+                })
+            })
+            .min_by(|a, b| a.t.abs().total_cmp(&b.t.abs()))
+    }
+}
+
+// Two lane straight road through the origin along the x axis
+fn setup_default_road(mut road: ResMut<RoadNetwork>) {
+    let half_length = 100.0;
+    road.roads = vec![RoadReference {
+        id: "default".to_string(),
+        reference: Polyline::new(
+            vec![
+                Vec2::new(-half_length, -LANE_WIDTH / 2.0),
+                Vec2::new(half_length, -LANE_WIDTH / 2.0),
+            ],
+            false,
+        ),
+    }];
+    road.lanes = [0.0, LANE_WIDTH]
+        .into_iter()
+        .map(|y| Lane {
+            centerline: Polyline::new(
+                vec![Vec2::new(-half_length, y), Vec2::new(half_length, y)],
+                false,
+            ),
+            width: LANE_WIDTH,
+        })
+        .collect();
+}
+
+fn draw_lane_markings(mut gizmos: Gizmos, road: Res<RoadNetwork>) {
+    const Z: f32 = 0.02; // just above the ground plane
+    for lane in &road.lanes {
+        let points = lane.centerline.points();
+        for side in [-0.5, 0.5] {
+            let mut boundary: Vec<Vec3> = points
+                .iter()
+                .enumerate()
+                .map(|(i, p)| (*p + lane_normal(points, i) * side * lane.width).extend(Z))
+                .collect();
+            if lane.centerline.is_closed()
+                && let Some(first) = boundary.first().copied()
+            {
+                boundary.push(first);
+            }
+            gizmos.linestrip(boundary, WHITE);
+        }
+    }
+}
+
+// Left normal at a polyline vertex, averaged over the adjacent segments
+fn lane_normal(points: &[Vec2], i: usize) -> Vec2 {
+    let prev = points[i.saturating_sub(1)];
+    let next = points[(i + 1).min(points.len() - 1)];
+    (next - prev).normalize_or_zero().perp()
+}
@@ -0,0 +1,38 @@
+use bevy::prelude::*;
+
+pub struct RunLogPlugin;
+
+impl Plugin for RunLogPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RunLog>();
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct RunLogEntry {
+    pub time: f32, // s since startup
+    pub source: &'static str,
+    pub message: String,
+}
+
+/// Timestamped events of the current run, kept for scoring and review.
+#[derive(Resource, Default)]
+pub struct RunLog {
+    entries: Vec<RunLogEntry>,
+}
+
+impl RunLog {
+    pub fn record(&mut self, time: f32, source: &'static str, message: impl Into<String>) {
+        let message = message.into();
+        info!("[{:.2}s] {}: {}", time, source, message);
+        self.entries.push(RunLogEntry {
+            time,
+            source,
+            message,
+        });
+    }
+
+    pub fn entries(&self) -> &[RunLogEntry] {
+        &self.entries
+    }
+}
@@ -0,0 +1,549 @@
+use avian3d::prelude::*;
+use bevy::asset::io::Reader;
+use bevy::asset::{AssetLoader, LoadContext};
+use bevy::color::palettes::tailwind::{GRAY_100, GRAY_600};
+use bevy::prelude::*;
+use serde::Deserialize;
+use std::f32::consts::FRAC_PI_2;
+
+use crate::car_dynamics::{Ego, EgoState};
+use crate::faults::{FaultSpec, Faults};
+use crate::lap_timer::{CheckpointSpec, spawn_checkpoints};
+use crate::opendrive::OpenDrive;
+use crate::parking::{ParkingBaySpec, spawn_parking_bay};
+use crate::road::RoadNetwork;
+use crate::run_log::RunLog;
+use crate::semantic::SemanticClass;
+use crate::sensor::SensorRig;
+use crate::storyboard::{ActorSpec, StoryEvent, Storyboard, Trigger, spawn_actor};
+use crate::traffic::{TrafficConfig, TrafficSpec, spawn_traffic_vehicle};
+use crate::traffic_control::{
+    RuleViolations, StopSignSpec, TrafficLightSpec, spawn_stop_sign, spawn_traffic_light,
+};
+use crate::utils::launch_argument;
+
+pub struct ScenarioPlugin;
+
+impl Plugin for ScenarioPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<Scenario>()
+            .init_asset::<VehicleParams>()
+            .register_asset_loader(ScenarioLoader)
+            .register_asset_loader(VehicleParamsLoader)
+            .init_resource::<ActiveScenario>()
+            .add_systems(Startup, select_scenario)
+            .add_systems(Update, (apply_scenario, check_termination).chain());
+    }
+}
+
+/// World description loaded from a `.scenario.ron` file.
+///
+/// Pick one with `--scenario <path>` on the command line or `?scenario=<path>` in
+/// the page URL; paths are relative to the `assets` folder.
+#[derive(Asset, TypePath, Deserialize, Clone)]
+pub struct Scenario {
+    pub name: String,
+    /// Path of a `.vehicle.ron` file, or none to keep the default car.
+    #[serde(default)]
+    pub vehicle: Option<String>,
+    /// Path of an OpenDRIVE `.xodr` file replacing the default road.
+    #[serde(default)]
+    pub road_network: Option<String>,
+    #[serde(default)]
+    pub ego: EgoStart,
+    #[serde(default)]
+    pub ground: Ground,
+    #[serde(default)]
+    pub obstacles: Vec<ObstacleSpec>,
+    #[serde(default)]
+    pub actors: Vec<ActorSpec>,
+    #[serde(default)]
+    pub traffic: TrafficSpec,
+    #[serde(default)]
+    pub traffic_lights: Vec<TrafficLightSpec>,
+    #[serde(default)]
+    pub stop_signs: Vec<StopSignSpec>,
+    /// Gates of a timed lap, starting with the start/finish line.
+    #[serde(default)]
+    pub checkpoints: Vec<CheckpointSpec>,
+    /// Slot for automated parking, between two parked cars.
+    #[serde(default)]
+    pub parking_bay: Option<ParkingBaySpec>,
+    #[serde(default)]
+    pub sensors: SensorRig,
+    #[serde(default)]
+    pub story: Vec<StoryEvent>,
+    #[serde(default)]
+    pub termination: Vec<Termination>,
+    /// Ends the run like `termination`, evaluated by the storyboard.
+    #[serde(default)]
+    pub stop_trigger: Option<Trigger>,
+    /// Sensor and actuator faults injected during the run.
+    #[serde(default)]
+    pub faults: Vec<FaultSpec>,
+    #[serde(skip)]
+    vehicle_params: Option<Handle<VehicleParams>>,
+    #[serde(skip)]
+    road_handle: Option<Handle<OpenDrive>>,
+}
+
+#[derive(Deserialize, Clone, Copy, Default)]
+pub struct EgoStart {
+    pub x: f32,
+    pub y: f32,
+    pub yaw: f32,   // rad
+    pub speed: f32, // m/s
+}
+
+#[derive(Deserialize, Clone, Copy)]
+pub struct Ground {
+    pub size: (f32, f32), // half-extent along x and y from the origin, m
+}
+
+impl Default for Ground {
+    fn default() -> Self {
+        Self {
+            size: (200.0, 200.0),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Copy)]
+pub struct ObstacleSpec {
+    pub position: (f32, f32, f32),
+    pub size: (f32, f32, f32),
+    #[serde(default)]
+    pub yaw: f32,
+    #[serde(default = "default_obstacle_color")]
+    pub color: (f32, f32, f32),
+    #[serde(default)]
+    pub motion: ObstacleMotion,
+}
+
+fn default_obstacle_color() -> (f32, f32, f32) {
+    (1.0, 0.0, 0.0)
+}
+
+#[derive(Deserialize, Clone, Copy, Default)]
+pub enum ObstacleMotion {
+    /// Fixed in place.
+    #[default]
+    Static,
+    /// Free rigid body that can be pushed around.
+    Dynamic { density: f32 },
+    /// Kinematic body moving at a constant velocity in m/s.
+    Moving { vx: f32, vy: f32 },
+}
+
+/// Ends the run when any condition holds.
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub enum Termination {
+    Timeout(f32),  // s
+    Distance(f32), // m travelled
+    Collision,
+    Goal { x: f32, y: f32, radius: f32 },
+}
+
+/// Vehicle parameters referenced by scenarios.
+#[derive(Asset, TypePath, Deserialize, Clone)]
+pub struct VehicleParams {
+    pub model: String,             // glTF file, first scene is used
+    pub collider: (f32, f32, f32), // m, width, height and length
+}
+
+impl Scenario {
+    /// Loads the referenced files as dependencies, so the scenario only counts as
+    /// loaded together with them.
+    pub fn load_dependencies(&mut self, load_context: &mut LoadContext) {
+        self.vehicle_params = self.vehicle.clone().map(|path| load_context.load(path));
+        self.road_handle = self
+            .road_network
+            .clone()
+            .map(|path| load_context.load(path));
+    }
+}
+
+/// The hard-coded world the sim used to start with.
+impl Default for Scenario {
+    fn default() -> Self {
+        Self {
+            name: "Default".to_string(),
+            vehicle: None,
+            road_network: None,
+            ego: EgoStart::default(),
+            ground: Ground::default(),
+            obstacles: vec![ObstacleSpec {
+                position: (10.0, 0.0, 1.0),
+                size: (5.0, 2.0, 1.0),
+                yaw: 0.0,
+                color: default_obstacle_color(),
+                motion: ObstacleMotion::Dynamic { density: 100.0 },
+            }],
+            actors: Vec::new(),
+            traffic: TrafficSpec::default(),
+            traffic_lights: Vec::new(),
+            stop_signs: Vec::new(),
+            checkpoints: Vec::new(),
+            // Perpendicular bay below the road
+            parking_bay: Some(ParkingBaySpec {
+                x: 20.0,
+                y: -12.0,
+                yaw: FRAC_PI_2,
+            }),
+            sensors: SensorRig::default(),
+            story: Vec::new(),
+            termination: Vec::new(),
+            stop_trigger: None,
+            faults: Vec::new(),
+            vehicle_params: None,
+            road_handle: None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum RonAssetError {
+    Io(std::io::Error),
+    Ron(ron::error::SpannedError),
+}
+
+impl std::fmt::Display for RonAssetError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "could not read file: {e}"),
+            Self::Ron(e) => write!(f, "could not parse RON: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for RonAssetError {}
+
+async fn read_ron<T: for<'de> Deserialize<'de>>(
+    reader: &mut dyn Reader,
+) -> Result<T, RonAssetError> {
+    let mut bytes = Vec::new();
+    reader
+        .read_to_end(&mut bytes)
+        .await
+        .map_err(RonAssetError::Io)?;
+    ron::de::from_bytes(&bytes).map_err(RonAssetError::Ron)
+}
+
+struct ScenarioLoader;
+
+impl AssetLoader for ScenarioLoader {
+    type Asset = Scenario;
+    type Settings = ();
+    type Error = RonAssetError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        load_context: &mut LoadContext<'_>,
+    ) -> Result<Scenario, RonAssetError> {
+        let mut scenario: Scenario = read_ron(reader).await?;
+        scenario.load_dependencies(load_context);
+        Ok(scenario)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["scenario.ron"]
+    }
+}
+
+struct VehicleParamsLoader;
+
+impl AssetLoader for VehicleParamsLoader {
+    type Asset = VehicleParams;
+    type Settings = ();
+    type Error = RonAssetError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        _load_context: &mut LoadContext<'_>,
+    ) -> Result<VehicleParams, RonAssetError> {
+        read_ron(reader).await
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["vehicle.ron"]
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct ActiveScenario {
+    pub name: String,
+    pub outcome: Option<String>,
+    handle: Handle<Scenario>,
+    applied: bool,
+    start_time: f32,
+    termination: Vec<Termination>,
+}
+
+impl ActiveScenario {
+    /// Ends the run, freezing it by pausing virtual time.
+    pub fn finish(&mut self, outcome: String, run_log: &mut RunLog, time: &mut Time<Virtual>) {
+        run_log.record(time.elapsed_secs(), "Scenario", outcome.clone());
+        self.outcome = Some(outcome);
+        time.pause();
+    }
+}
+
+/// Entities spawned from the scenario description.
+#[derive(Component)]
+pub struct ScenarioEntity;
+
+#[derive(Component)]
+#[require(SemanticClass::Obstacle)]
+pub struct Obstacle;
+
+/// Ground and road surfaces, ignored by collision checks.
+#[derive(Component)]
+#[require(SemanticClass::Ground)]
+pub struct GroundPlane;
+
+/// Colliders vehicles pass through: trigger volumes and drivable surfaces.
+pub type Passable = Or<(With<Sensor>, With<GroundPlane>)>;
+
+fn select_scenario(
+    asset_server: Res<AssetServer>,
+    mut scenarios: ResMut<Assets<Scenario>>,
+    mut active: ResMut<ActiveScenario>,
+) {
+    active.handle = match launch_argument("scenario") {
+        Some(path) => {
+            info!("Loading scenario {}", path);
+            asset_server.load(path)
+        }
+        None => scenarios.add(Scenario::default()),
+    };
+}
+
+#[allow(clippy::too_many_arguments)]
+fn apply_scenario(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut scenarios: ResMut<Assets<Scenario>>,
+    vehicles: Res<Assets<VehicleParams>>,
+    road_networks: Res<Assets<OpenDrive>>,
+    mut road: ResMut<RoadNetwork>,
+    mut traffic: ResMut<TrafficConfig>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    car: Single<(Entity, &mut EgoState, &mut Transform), With<Ego>>,
+    mut active: ResMut<ActiveScenario>,
+    mut run_log: ResMut<RunLog>,
+    time: Res<Time>,
+) {
+    if active.applied {
+        return;
+    }
+    if asset_server
+        .recursive_dependency_load_state(&active.handle)
+        .is_failed()
+    {
+        error!("Scenario failed to load, falling back to the default world");
+        active.handle = scenarios.add(Scenario::default());
+        return;
+    }
+    let Some(scenario) = scenarios.get(&active.handle) else {
+        return;
+    };
+    let vehicle = match &scenario.vehicle_params {
+        Some(handle) => match vehicles.get(handle) {
+            Some(vehicle) => Some(vehicle),
+            None => return,
+        },
+        None => None,
+    };
+    let road_network = match &scenario.road_handle {
+        Some(handle) => match road_networks.get(handle) {
+            Some(road_network) => Some(road_network),
+            None => return,
+        },
+        None => None,
+    };
+
+    let (entity, mut state, mut transform) = car.into_inner();
+    let ego = scenario.ego;
+    *state = EgoState {
+        x: ego.x,
+        y: ego.y,
+        yaw: ego.yaw,
+        v: ego.speed,
+        s: 0.0,
+    };
+    transform.translation.x = ego.x;
+    transform.translation.y = ego.y;
+    // Same model orientation as `init::spawn_ego`, turned to the start heading
+    transform.rotation =
+        Quat::from_rotation_z(ego.yaw + FRAC_PI_2) * Quat::from_rotation_x(FRAC_PI_2);
+
+    scenario.sensors.attach(&mut commands.entity(entity));
+
+    if let Some(vehicle) = vehicle {
+        let (width, height, length) = vehicle.collider;
+        commands.entity(entity).insert((
+            SceneRoot(asset_server.load(format!("{}#Scene0", vehicle.model))),
+            Collider::cuboid(width, height, length),
+        ));
+    }
+
+    let (half_width, half_length) = scenario.ground.size;
+    commands.spawn((
+        ScenarioEntity,
+        GroundPlane,
+        RigidBody::Static,
+        ColliderConstructor::TrimeshFromMesh,
+        Mesh3d(meshes.add(Plane3d::new(Vec3::Z, Vec2::new(half_width, half_length)))),
+        MeshMaterial3d(materials.add(StandardMaterial::from_color(GRAY_100))),
+        Transform::default(),
+    ));
+
+    if let Some(road_network) = road_network {
+        road.lanes = road_network.lanes();
+        road.roads = road_network.reference_lines();
+        let material = materials.add(StandardMaterial::from_color(GRAY_600));
+        for odr_road in &road_network.roads {
+            commands.spawn((
+                ScenarioEntity,
+                GroundPlane,
+                SemanticClass::Road,
+                RigidBody::Static,
+                ColliderConstructor::TrimeshFromMesh,
+                Mesh3d(meshes.add(odr_road.surface_mesh())),
+                MeshMaterial3d(material.clone()),
+                // Just above the ground plane
+                Transform::from_xyz(0.0, 0.0, 0.01),
+            ));
+        }
+    }
+
+    for obstacle in &scenario.obstacles {
+        let (x, y, z) = obstacle.position;
+        let (sx, sy, sz) = obstacle.size;
+        let (r, g, b) = obstacle.color;
+        let mut entity = commands.spawn((
+            ScenarioEntity,
+            Obstacle,
+            Collider::cuboid(sx, sy, sz),
+            Mesh3d(meshes.add(Cuboid::new(sx, sy, sz))),
+            MeshMaterial3d(materials.add(StandardMaterial::from_color(Color::srgb(r, g, b)))),
+            Transform::from_xyz(x, y, z).with_rotation(Quat::from_rotation_z(obstacle.yaw)),
+        ));
+        match obstacle.motion {
+            ObstacleMotion::Static => entity.insert(RigidBody::Static),
+            ObstacleMotion::Dynamic { density } => {
+                entity.insert((RigidBody::Dynamic, ColliderDensity(density)))
+            }
+            ObstacleMotion::Moving { vx, vy } => {
+                entity.insert((RigidBody::Kinematic, LinearVelocity(Vec3::new(vx, vy, 0.0))))
+            }
+        };
+    }
+
+    for actor in &scenario.actors {
+        spawn_actor(&mut commands, &mut meshes, &mut materials, actor);
+    }
+    traffic.density = scenario.traffic.density;
+    for vehicle in &scenario.traffic.vehicles {
+        if spawn_traffic_vehicle(&mut commands, &asset_server, &road, vehicle).is_none() {
+            warn!("Traffic vehicle on a lane missing from the road network, skipped");
+        }
+    }
+    for light in &scenario.traffic_lights {
+        spawn_traffic_light(
+            &mut commands,
+            &mut meshes,
+            &mut materials,
+            light,
+            time.elapsed_secs(),
+        );
+    }
+    for sign in &scenario.stop_signs {
+        spawn_stop_sign(&mut commands, &mut meshes, &mut materials, sign);
+    }
+    if !scenario.checkpoints.is_empty() {
+        let gates = spawn_checkpoints(&mut commands, scenario.name.clone(), &scenario.checkpoints);
+        for gate in gates {
+            commands.entity(gate).insert(ScenarioEntity);
+        }
+    }
+    if let Some(bay) = &scenario.parking_bay {
+        for entity in spawn_parking_bay(&mut commands, &mut meshes, &mut materials, bay) {
+            commands.entity(entity).insert(ScenarioEntity);
+        }
+    }
+    commands.insert_resource(Storyboard::new(
+        scenario.story.clone(),
+        scenario.stop_trigger.clone(),
+        time.elapsed_secs(),
+    ));
+    commands.insert_resource(Faults::new(scenario.faults.clone(), time.elapsed_secs()));
+    commands.insert_resource(RuleViolations::default());
+
+    active.name = scenario.name.clone();
+    active.termination = scenario.termination.clone();
+    active.start_time = time.elapsed_secs();
+    active.outcome = None;
+    active.applied = true;
+    run_log.record(
+        time.elapsed_secs(),
+        "Scenario",
+        format!("Started {}", scenario.name),
+    );
+}
+
+fn check_termination(
+    car: Single<(Entity, &EgoState, &Transform, &Collider), With<Ego>>,
+    passable: Query<Entity, Passable>,
+    spatial_query: SpatialQuery,
+    mut active: ResMut<ActiveScenario>,
+    mut run_log: ResMut<RunLog>,
+    mut time: ResMut<Time<Virtual>>,
+) {
+    if !active.applied || active.outcome.is_some() {
+        return;
+    }
+    let (entity, state, transform, collider) = *car;
+    let elapsed = time.elapsed_secs() - active.start_time;
+
+    let outcome = active
+        .termination
+        .iter()
+        .find_map(|condition| match *condition {
+            Termination::Timeout(limit) if elapsed >= limit => {
+                Some(format!("Timed out after {limit:.0} s"))
+            }
+            Termination::Distance(limit) if state.s >= limit => {
+                Some(format!("Travelled {limit:.0} m"))
+            }
+            Termination::Collision => {
+                let filter = SpatialQueryFilter::from_excluded_entities(
+                    passable.iter().chain(std::iter::once(entity)),
+                );
+                spatial_query
+                    .shape_intersections(
+                        collider,
+                        transform.translation,
+                        transform.rotation,
+                        &filter,
+                    )
+                    .first()
+                    .map(|hit| format!("Collided with {hit}"))
+            }
+            Termination::Goal { x, y, radius }
+                if Vec2::new(state.x, state.y).distance(Vec2::new(x, y)) <= radius =>
+            {
+                Some("Reached goal".to_string())
+            }
+            _ => None,
+        });
+
+    if let Some(outcome) = outcome {
+        active.finish(outcome, &mut run_log, &mut time);
+    }
+}
@@ -0,0 +1,83 @@
+use bevy::prelude::*;
+
+use crate::storyboard::ActorKind;
+
+/// Class of an entity in segmentation labels and ground truth, stored in label images
+/// as its `u8` value.
+#[derive(Component, Clone, Copy, PartialEq, Eq, Default, Debug)]
+#[repr(u8)]
+pub enum SemanticClass {
+    /// Anything without a class, and the sky.
+    #[default]
+    Unlabeled = 0,
+    Ground = 1,
+    Road = 2,
+    Vehicle = 3,
+    Pedestrian = 4,
+    Cyclist = 5,
+    Obstacle = 6,
+}
+
+impl SemanticClass {
+    pub const ALL: [SemanticClass; 7] = [
+        SemanticClass::Unlabeled,
+        SemanticClass::Ground,
+        SemanticClass::Road,
+        SemanticClass::Vehicle,
+        SemanticClass::Pedestrian,
+        SemanticClass::Cyclist,
+        SemanticClass::Obstacle,
+    ];
+
+    pub fn id(self) -> u8 {
+        self as u8
+    }
+
+    pub fn from_id(id: u8) -> Self {
+        Self::ALL.get(id as usize).copied().unwrap_or_default()
+    }
+
+    /// Lower case name, as written in recorded ground truth.
+    pub fn name(self) -> &'static str {
+        match self {
+            SemanticClass::Unlabeled => "unlabeled",
+            SemanticClass::Ground => "ground",
+            SemanticClass::Road => "road",
+            SemanticClass::Vehicle => "vehicle",
+            SemanticClass::Pedestrian => "pedestrian",
+            SemanticClass::Cyclist => "cyclist",
+            SemanticClass::Obstacle => "obstacle",
+        }
+    }
+
+    /// Colour of the class in segmentation previews.
+    pub fn color(self) -> [u8; 3] {
+        match self {
+            SemanticClass::Unlabeled => [0, 0, 0],
+            SemanticClass::Ground => [152, 251, 152],
+            SemanticClass::Road => [128, 64, 128],
+            SemanticClass::Vehicle => [0, 0, 142],
+            SemanticClass::Pedestrian => [220, 20, 60],
+            SemanticClass::Cyclist => [119, 11, 32],
+            SemanticClass::Obstacle => [250, 170, 30],
+        }
+    }
+
+    /// Class of `entity`, or of its nearest classified ancestor.
+    pub fn of(entity: Entity, classes: &Query<&SemanticClass>, parents: &Query<&ChildOf>) -> Self {
+        std::iter::once(entity)
+            .chain(parents.iter_ancestors(entity))
+            .find_map(|entity| classes.get(entity).ok().copied())
+            .unwrap_or_default()
+    }
+}
+
+impl From<ActorKind> for SemanticClass {
+    fn from(kind: ActorKind) -> Self {
+        match kind {
+            ActorKind::Vehicle => SemanticClass::Vehicle,
+            ActorKind::Pedestrian => SemanticClass::Pedestrian,
+            ActorKind::Cyclist => SemanticClass::Cyclist,
+        }
+    }
+}
@@ -0,0 +1,96 @@
+use bevy::ecs::system::EntityCommands;
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use crate::camera_sensor::CameraSensor;
+use crate::car_dynamics::EgoState;
+use crate::gnss::Gnss;
+use crate::imu::Imu;
+use crate::lidar::Lidar;
+use crate::occupancy::OccupancyGrid;
+use crate::radar::Radar;
+use crate::ultrasonic::Ultrasonic;
+use crate::vehicle_bus::VehicleBus;
+
+/// Sensor pose in the vehicle frame: x forward, y left and z up from the vehicle centre
+/// on the ground, with roll, pitch and yaw in radians.
+#[derive(Deserialize, Clone, Copy, Default, Debug)]
+pub struct Mount {
+    pub position: (f32, f32, f32), // m
+    #[serde(default)]
+    pub rotation: (f32, f32, f32), // rad
+}
+
+impl Mount {
+    pub fn new(x: f32, y: f32, z: f32) -> Self {
+        Self {
+            position: (x, y, z),
+            rotation: (0.0, 0.0, 0.0),
+        }
+    }
+
+    /// Sensor to world transform on a vehicle at `state`.
+    pub fn world_pose(&self, state: &EgoState) -> Transform {
+        let (x, y, z) = self.position;
+        let (roll, pitch, yaw) = self.rotation;
+        let vehicle = Transform::from_xyz(state.x, state.y, 0.0)
+            .with_rotation(Quat::from_rotation_z(state.yaw));
+        let mount = Transform::from_xyz(x, y, z).with_rotation(Quat::from_euler(
+            EulerRot::ZYX,
+            yaw,
+            pitch,
+            roll,
+        ));
+        vehicle * mount
+    }
+}
+
+/// Sensors fitted to the ego by a scenario's `sensors`.
+#[derive(Deserialize, Clone, Default)]
+pub struct SensorRig {
+    #[serde(default)]
+    pub lidar: Option<Lidar>,
+    #[serde(default)]
+    pub radar: Option<Radar>,
+    #[serde(default)]
+    pub camera: Option<CameraSensor>,
+    #[serde(default)]
+    pub imu: Option<Imu>,
+    #[serde(default)]
+    pub gnss: Option<Gnss>,
+    #[serde(default)]
+    pub bus: Option<VehicleBus>,
+    #[serde(default)]
+    pub ultrasonic: Option<Ultrasonic>,
+    #[serde(default)]
+    pub occupancy: Option<OccupancyGrid>,
+}
+
+impl SensorRig {
+    pub fn attach(&self, car: &mut EntityCommands) {
+        if let Some(lidar) = &self.lidar {
+            car.insert(lidar.clone());
+        }
+        if let Some(radar) = &self.radar {
+            car.insert(radar.clone());
+        }
+        if let Some(camera) = &self.camera {
+            car.insert(camera.clone());
+        }
+        if let Some(imu) = &self.imu {
+            car.insert(imu.clone());
+        }
+        if let Some(gnss) = &self.gnss {
+            car.insert(gnss.clone());
+        }
+        if let Some(bus) = &self.bus {
+            car.insert(bus.clone());
+        }
+        if let Some(ultrasonic) = &self.ultrasonic {
+            car.insert(ultrasonic.clone());
+        }
+        if let Some(occupancy) = &self.occupancy {
+            car.insert(occupancy.clone());
+        }
+    }
+}
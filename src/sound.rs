@@ -1,5 +1,8 @@
-use crate::car_dynamics::{EgoControl, EgoState};
+use crate::car_dynamics::{Ego, EgoControl, EgoState};
+use crate::ultrasonic::Ultrasonic;
+use bevy::audio::Pitch;
 use bevy::prelude::*;
+use std::time::Duration;
 
 pub struct SoundPlugin;
 
@@ -9,7 +12,7 @@ impl Plugin for SoundPlugin {
             .add_systems(Startup, load_sound_asset)
             .add_systems(OnEnter(SoundState::THROTTLE), trigger_throttle_sound)
             .add_systems(OnEnter(SoundState::BRAKE), trigger_brake_sound)
-            .add_systems(Update, change_sound_state);
+            .add_systems(Update, (change_sound_state, beep_parking_sensors));
     }
 }
 
@@ -17,6 +20,7 @@ impl Plugin for SoundPlugin {
 struct SoundTrack {
     brake: Handle<AudioSource>,
     throttle: Handle<AudioSource>,
+    beep: Handle<Pitch>,
 }
 
 #[derive(States, Clone, Debug, Hash, Default, PartialEq, Eq)]
@@ -29,16 +33,30 @@ enum SoundState {
 
 const BRAKE_SOUND_SPEED: f32 = 5.0;
 
-fn load_sound_asset(mut commands: Commands, asset_server: Res<AssetServer>) {
+// Park distance beeps, from one a second at the edge of the range to a continuous tone
+const BEEP_FREQUENCY: f32 = 1800.0; // Hz
+const BEEP_LENGTH: f32 = 0.08; // s
+const BEEP_MAX_INTERVAL: f32 = 1.0; // s
+const BEEP_CONTINUOUS_DISTANCE: f32 = 0.3; // m
+
+fn load_sound_asset(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut pitches: ResMut<Assets<Pitch>>,
+) {
     commands.insert_resource(SoundTrack {
         brake: asset_server.load("brake.mp3"),
         throttle: asset_server.load("throttle.mp3"),
+        beep: pitches.add(Pitch::new(
+            BEEP_FREQUENCY,
+            Duration::from_secs_f32(BEEP_LENGTH),
+        )),
     })
 }
 
 fn change_sound_state(
-    ego_control: Single<&EgoControl>,
-    ego_state: Single<&EgoState>,
+    ego_control: Single<&EgoControl, With<Ego>>,
+    ego_state: Single<&EgoState, With<Ego>>,
     mut next_sound_state: ResMut<NextState<SoundState>>,
 ) {
     if ego_control.throttle > 0.5 {
@@ -76,3 +94,27 @@ fn trigger_brake_sound(
         PlaybackSettings::DESPAWN,
     ));
 }
+
+fn beep_parking_sensors(
+    mut commands: Commands,
+    ultrasonic: Single<&Ultrasonic, With<Ego>>,
+    sound_track: Res<SoundTrack>,
+    time: Res<Time>,
+    mut next_beep: Local<f32>,
+) {
+    let now = time.elapsed_secs();
+    let Some(distance) = ultrasonic.nearest() else {
+        return;
+    };
+    if !ultrasonic.enabled || !ultrasonic.beep || now < *next_beep {
+        return;
+    }
+    let fraction = ((distance - BEEP_CONTINUOUS_DISTANCE)
+        / (ultrasonic.range - BEEP_CONTINUOUS_DISTANCE))
+        .clamp(0.0, 1.0);
+    *next_beep = now + BEEP_LENGTH + fraction * (BEEP_MAX_INTERVAL - BEEP_LENGTH);
+    commands.spawn((
+        AudioPlayer(sound_track.beep.clone()),
+        PlaybackSettings::DESPAWN,
+    ));
+}
@@ -0,0 +1,553 @@
+use avian3d::prelude::*;
+use bevy::prelude::*;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::f32::consts::{FRAC_PI_2, PI};
+
+use crate::car_dynamics::{Ego, EgoState};
+use crate::run_log::RunLog;
+use crate::scenario::{ActiveScenario, ScenarioEntity};
+use crate::semantic::SemanticClass;
+
+pub struct StoryboardPlugin;
+
+impl Plugin for StoryboardPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Storyboard>()
+            .add_systems(Update, (run_storyboard, drive_scripted_actors).chain());
+    }
+}
+
+/// Name the storyboard uses for the ego car.
+pub const EGO_NAME: &str = "Ego";
+
+const WAYPOINT_RADIUS: f32 = 0.3; // m, counts as reached
+
+/// Road user moved along a script rather than by physics or a driver.
+#[derive(Deserialize, Clone)]
+pub struct ActorSpec {
+    pub name: String,
+    pub x: f32,
+    pub y: f32,
+    #[serde(default)]
+    pub yaw: f32, // rad
+    #[serde(default)]
+    pub speed: f32, // m/s
+    #[serde(default)]
+    pub kind: ActorKind,
+    /// Length, width and height in m, or the default size of `kind`.
+    #[serde(default)]
+    pub size: Option<(f32, f32, f32)>,
+    #[serde(default = "default_actor_color")]
+    pub color: (f32, f32, f32),
+    /// Points the actor heads for in turn, keeping its heading after the last.
+    #[serde(default)]
+    pub waypoints: Vec<(f32, f32)>,
+}
+
+/// What kind of road user an actor is, which sets its shape.
+#[derive(Component, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ActorKind {
+    /// Box collider.
+    #[default]
+    Vehicle,
+    /// Upright capsule collider.
+    Pedestrian,
+    /// Capsule collider lying along the direction of travel.
+    Cyclist,
+}
+
+impl ActorKind {
+    pub fn default_size(self) -> (f32, f32, f32) {
+        match self {
+            ActorKind::Vehicle => (4.5, 1.8, 1.5),
+            ActorKind::Pedestrian => (0.5, 0.5, 1.8),
+            ActorKind::Cyclist => (1.8, 0.6, 1.7),
+        }
+    }
+}
+
+fn default_actor_color() -> (f32, f32, f32) {
+    (0.2, 0.4, 1.0)
+}
+
+/// Actions run on `actors` once `trigger` fires.
+#[derive(Deserialize, Clone)]
+pub struct StoryEvent {
+    pub name: String,
+    pub actors: Vec<String>,
+    pub actions: Vec<StoryAction>,
+    pub trigger: Trigger,
+    /// Arms `trigger` once it has fired, as the start trigger of an OpenSCENARIO act
+    /// does for the events in it.
+    #[serde(default)]
+    pub after: Option<Trigger>,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub enum StoryAction {
+    Speed { target: f32, dynamics: Dynamics },      // m/s
+    LaneChange { offset: f32, dynamics: Dynamics }, // m, positive to the left
+    Teleport { x: f32, y: f32, yaw: f32 },
+}
+
+/// How an action approaches its target, as in OpenSCENARIO `TransitionDynamics`.
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub struct Dynamics {
+    pub shape: Shape,
+    pub dimension: Dimension,
+    pub value: f32,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Shape {
+    Step,
+    Linear,
+    Sinusoidal,
+    Cubic,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub enum Dimension {
+    Time,     // s
+    Distance, // m
+    Rate,     // unit per s
+}
+
+impl Shape {
+    fn profile(self, u: f32) -> f32 {
+        let u = u.clamp(0.0, 1.0);
+        match self {
+            Shape::Step => 1.0,
+            Shape::Linear => u,
+            Shape::Sinusoidal => (1.0 - (PI * u).cos()) / 2.0,
+            Shape::Cubic => u * u * (3.0 - 2.0 * u),
+        }
+    }
+}
+
+/// Fires when every condition of any one group holds.
+#[derive(Deserialize, Clone, Default)]
+pub struct Trigger {
+    pub groups: Vec<Vec<Condition>>,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct Condition {
+    pub kind: ConditionKind,
+    #[serde(default)]
+    pub delay: f32, // s the condition must hold before it counts
+    #[serde(skip)]
+    holding_since: Option<f32>,
+}
+
+impl Condition {
+    pub fn new(kind: ConditionKind, delay: f32) -> Self {
+        Self {
+            kind,
+            delay,
+            holding_since: None,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub enum ConditionKind {
+    SimulationTime {
+        value: f32,
+        rule: Rule,
+    },
+    /// Distance between the reference points of two entities.
+    RelativeDistance {
+        entities: Triggering,
+        other: String,
+        value: f32,
+        rule: Rule,
+    },
+    Distance {
+        entities: Triggering,
+        x: f32,
+        y: f32,
+        value: f32,
+        rule: Rule,
+    },
+    TraveledDistance {
+        entities: Triggering,
+        value: f32,
+    },
+    Speed {
+        entities: Triggering,
+        value: f32,
+        rule: Rule,
+    },
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct Triggering {
+    pub names: Vec<String>,
+    #[serde(default)]
+    pub all: bool, // every entity must satisfy the condition, otherwise any
+}
+
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub enum Rule {
+    GreaterThan,
+    LessThan,
+    EqualTo,
+    GreaterOrEqual,
+    LessOrEqual,
+}
+
+impl Rule {
+    fn check(self, value: f32, threshold: f32) -> bool {
+        match self {
+            Rule::GreaterThan => value > threshold,
+            Rule::LessThan => value < threshold,
+            Rule::EqualTo => (value - threshold).abs() < 1e-2,
+            Rule::GreaterOrEqual => value >= threshold,
+            Rule::LessOrEqual => value <= threshold,
+        }
+    }
+}
+
+impl Triggering {
+    fn check(&self, world: &HashMap<String, EgoState>, test: impl Fn(&EgoState) -> bool) -> bool {
+        let mut states = self.names.iter().filter_map(|name| world.get(name));
+        if self.all {
+            states.all(test)
+        } else {
+            states.any(test)
+        }
+    }
+}
+
+impl ConditionKind {
+    fn holds(&self, time: f32, world: &HashMap<String, EgoState>) -> bool {
+        let position = |state: &EgoState| Vec2::new(state.x, state.y);
+        match self {
+            ConditionKind::SimulationTime { value, rule } => rule.check(time, *value),
+            ConditionKind::RelativeDistance {
+                entities,
+                other,
+                value,
+                rule,
+            } => world.get(other).is_some_and(|other| {
+                entities.check(world, |state| {
+                    rule.check(position(state).distance(position(other)), *value)
+                })
+            }),
+            ConditionKind::Distance {
+                entities,
+                x,
+                y,
+                value,
+                rule,
+            } => entities.check(world, |state| {
+                rule.check(position(state).distance(Vec2::new(*x, *y)), *value)
+            }),
+            ConditionKind::TraveledDistance { entities, value } => {
+                entities.check(world, |state| state.s >= *value)
+            }
+            ConditionKind::Speed {
+                entities,
+                value,
+                rule,
+            } => entities.check(world, |state| rule.check(state.v, *value)),
+        }
+    }
+}
+
+impl Trigger {
+    /// Whether the trigger fires at `time` in s of story time, given the named entities.
+    pub fn update(&mut self, time: f32, world: &HashMap<String, EgoState>) -> bool {
+        let mut fired = false;
+        for group in &mut self.groups {
+            let mut group_holds = !group.is_empty();
+            for condition in group.iter_mut() {
+                if condition.kind.holds(time, world) {
+                    let since = *condition.holding_since.get_or_insert(time);
+                    group_holds &= time - since >= condition.delay;
+                } else {
+                    condition.holding_since = None;
+                    group_holds = false;
+                }
+            }
+            fired |= group_holds;
+        }
+        fired
+    }
+}
+
+/// Storyboard of the running scenario.
+#[derive(Resource, Default)]
+pub struct Storyboard {
+    events: Vec<StoryEvent>,
+    stop_trigger: Option<Trigger>,
+    fired: Vec<bool>,
+    start_time: f32,
+}
+
+impl Storyboard {
+    pub fn new(events: Vec<StoryEvent>, stop_trigger: Option<Trigger>, start_time: f32) -> Self {
+        Self {
+            fired: vec![false; events.len()],
+            events,
+            stop_trigger,
+            start_time,
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct ScriptedActor {
+    pub name: String,
+    speed: Option<Transition>,
+    lateral: Option<Transition>,
+    lateral_applied: f32, // m of the lane change moved so far
+    waypoints: Vec<Vec2>,
+    next_waypoint: usize,
+}
+
+#[derive(Copy, Clone)]
+struct Transition {
+    from: f32,
+    to: f32,
+    elapsed: f32,
+    duration: f32,
+    shape: Shape,
+}
+
+impl Transition {
+    // `speed` turns a distance dimension into a duration
+    fn new(from: f32, to: f32, dynamics: Dynamics, speed: f32) -> Self {
+        let change = (to - from).abs();
+        let duration = match dynamics.dimension {
+            _ if dynamics.shape == Shape::Step => 0.0,
+            Dimension::Time => dynamics.value,
+            Dimension::Rate => change / dynamics.value.max(1e-3),
+            Dimension::Distance => dynamics.value / speed.max(1.0),
+        };
+        Self {
+            from,
+            to,
+            elapsed: 0.0,
+            duration,
+            shape: dynamics.shape,
+        }
+    }
+
+    fn value(&self) -> f32 {
+        let u = if self.duration > 0.0 {
+            self.elapsed / self.duration
+        } else {
+            1.0
+        };
+        self.from + (self.to - self.from) * self.shape.profile(u)
+    }
+
+    fn is_done(&self) -> bool {
+        self.elapsed >= self.duration
+    }
+}
+
+/// Spawns a scripted actor, moved kinematically like the ego car.
+pub fn spawn_actor(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+    actor: &ActorSpec,
+) {
+    let (length, width, height) = actor.size.unwrap_or(actor.kind.default_size());
+    let (r, g, b) = actor.color;
+    // Capsules are as thick as the actor is wide
+    let radius = width / 2.0;
+    let (collider, mesh) = match actor.kind {
+        ActorKind::Vehicle => (
+            Collider::cuboid(length, width, height),
+            Mesh::from(Cuboid::new(length, width, height)),
+        ),
+        ActorKind::Pedestrian => {
+            let half_length = (height / 2.0 - radius).max(0.0);
+            (
+                Collider::capsule_endpoints(
+                    radius,
+                    Vec3::NEG_Z * half_length,
+                    Vec3::Z * half_length,
+                ),
+                Mesh::from(Capsule3d::new(radius, 2.0 * half_length))
+                    .rotated_by(Quat::from_rotation_x(FRAC_PI_2)),
+            )
+        }
+        ActorKind::Cyclist => {
+            let half_length = (length / 2.0 - radius).max(0.0);
+            (
+                Collider::capsule_endpoints(
+                    radius,
+                    Vec3::NEG_X * half_length,
+                    Vec3::X * half_length,
+                ),
+                Mesh::from(Capsule3d::new(radius, 2.0 * half_length))
+                    .rotated_by(Quat::from_rotation_z(FRAC_PI_2)),
+            )
+        }
+    };
+    commands.spawn((
+        ScenarioEntity,
+        ScriptedActor {
+            name: actor.name.clone(),
+            speed: None,
+            lateral: None,
+            lateral_applied: 0.0,
+            waypoints: actor
+                .waypoints
+                .iter()
+                .map(|&(x, y)| Vec2::new(x, y))
+                .collect(),
+            next_waypoint: 0,
+        },
+        actor.kind,
+        SemanticClass::from(actor.kind),
+        EgoState {
+            x: actor.x,
+            y: actor.y,
+            yaw: actor.yaw,
+            v: actor.speed,
+            s: 0.0,
+        },
+        RigidBody::Kinematic,
+        collider,
+        Mesh3d(meshes.add(mesh)),
+        MeshMaterial3d(materials.add(StandardMaterial::from_color(Color::srgb(r, g, b)))),
+        Transform::from_xyz(actor.x, actor.y, height / 2.0)
+            .with_rotation(Quat::from_rotation_z(actor.yaw)),
+    ));
+}
+
+/// States of the entities that trigger conditions name, by name.
+pub fn entity_states<'a>(
+    ego: &EgoState,
+    actors: impl Iterator<Item = (&'a ScriptedActor, &'a EgoState)>,
+) -> HashMap<String, EgoState> {
+    let mut world: HashMap<String, EgoState> = actors
+        .map(|(actor, state)| (actor.name.clone(), *state))
+        .collect();
+    world.insert(EGO_NAME.to_string(), *ego);
+    world
+}
+
+fn run_storyboard(
+    mut storyboard: ResMut<Storyboard>,
+    ego: Single<&EgoState, (With<Ego>, Without<ScriptedActor>)>,
+    mut actors: Query<(&mut ScriptedActor, &mut EgoState), Without<Ego>>,
+    mut active: ResMut<ActiveScenario>,
+    mut run_log: ResMut<RunLog>,
+    mut time: ResMut<Time<Virtual>>,
+) {
+    if storyboard.events.is_empty() && storyboard.stop_trigger.is_none() {
+        return;
+    }
+    let now = time.elapsed_secs();
+    let story_time = now - storyboard.start_time;
+    let world = entity_states(&ego, actors.iter());
+
+    let storyboard = &mut *storyboard;
+    for (event, fired) in storyboard.events.iter_mut().zip(&mut storyboard.fired) {
+        if *fired {
+            continue;
+        }
+        if let Some(after) = &mut event.after {
+            if !after.update(story_time, &world) {
+                continue;
+            }
+            event.after = None;
+        }
+        if !event.trigger.update(story_time, &world) {
+            continue;
+        }
+        *fired = true;
+        run_log.record(now, "Storyboard", format!("Event {}", event.name));
+        for (mut actor, mut state) in &mut actors {
+            if !event.actors.contains(&actor.name) {
+                continue;
+            }
+            for action in &event.actions {
+                match *action {
+                    StoryAction::Speed { target, dynamics } => {
+                        actor.speed = Some(Transition::new(state.v, target, dynamics, state.v));
+                    }
+                    StoryAction::LaneChange { offset, dynamics } => {
+                        actor.lateral = Some(Transition::new(0.0, offset, dynamics, state.v));
+                        actor.lateral_applied = 0.0;
+                    }
+                    StoryAction::Teleport { x, y, yaw } => {
+                        state.x = x;
+                        state.y = y;
+                        state.yaw = yaw;
+                    }
+                }
+            }
+        }
+        if event.actors.iter().any(|name| name == EGO_NAME) {
+            warn!("Storyboard actions on the ego car are not supported");
+        }
+    }
+
+    if let Some(stop) = &mut storyboard.stop_trigger
+        && stop.update(story_time, &world)
+    {
+        storyboard.stop_trigger = None;
+        active.finish(
+            "Storyboard stop trigger".to_string(),
+            &mut run_log,
+            &mut time,
+        );
+    }
+}
+
+// Actors move along their heading, turned towards their waypoints; lane changes
+// shift them sideways
+fn drive_scripted_actors(
+    mut actors: Query<(&mut ScriptedActor, &mut EgoState, &mut Transform), Without<Ego>>,
+    time: Res<Time>,
+) {
+    let dt = time.delta_secs();
+    for (mut actor, mut state, mut transform) in &mut actors {
+        if let Some(speed) = &mut actor.speed {
+            speed.elapsed += dt;
+            state.v = speed.value();
+            if speed.is_done() {
+                actor.speed = None;
+            }
+        }
+        while let Some(&waypoint) = actor.waypoints.get(actor.next_waypoint) {
+            let to_waypoint = waypoint - Vec2::new(state.x, state.y);
+            if to_waypoint.length() > (state.v * dt).max(WAYPOINT_RADIUS) {
+                state.yaw = to_waypoint.to_angle();
+                break;
+            }
+            actor.next_waypoint += 1;
+        }
+        let heading = Vec2::from_angle(state.yaw);
+        let mut step = heading * state.v * dt;
+        if let Some(mut lateral) = actor.lateral {
+            lateral.elapsed += dt;
+            let offset = lateral.value();
+            step += heading.perp() * (offset - actor.lateral_applied);
+            actor.lateral_applied = offset;
+            actor.lateral = (!lateral.is_done()).then_some(lateral);
+        }
+        state.x += step.x;
+        state.y += step.y;
+        state.s += step.length();
+
+        transform.translation.x = state.x;
+        transform.translation.y = state.y;
+        // Body turns into the direction of travel during lane changes
+        let yaw = if state.v > 0.0 {
+            step.to_angle()
+        } else {
+            state.yaw
+        };
+        transform.rotation = Quat::from_rotation_z(yaw);
+    }
+}
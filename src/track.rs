@@ -0,0 +1,326 @@
+use avian3d::prelude::*;
+use bevy::asset::RenderAssetUsages;
+use bevy::color::palettes::css::GRAY;
+use bevy::mesh::{Indices, PrimitiveTopology};
+use bevy::prelude::*;
+use std::f32::consts::{FRAC_PI_2, TAU};
+
+use crate::car_dynamics::{Car, Ego, EgoState, RIDE_HEIGHT, bicycle_kinematic_model};
+use crate::lap_timer::{CheckpointSpec, spawn_checkpoints};
+use crate::parking::ParkingBay;
+use crate::path::Polyline;
+use crate::path_follower::PathFollower;
+use crate::road::{Lane, RoadNetwork, RoadReference};
+use crate::run_log::RunLog;
+use crate::scenario::GroundPlane;
+use crate::semantic::SemanticClass;
+use crate::utils::{SplitMix64, normalize_angle};
+
+pub struct TrackPlugin;
+
+impl Plugin for TrackPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RaceTrack>()
+            .add_systems(Update, regenerate_track)
+            .add_systems(Update, follow_surface.after(bicycle_kinematic_model));
+    }
+}
+
+// Control points on a jittered circle around the origin
+const MIN_CONTROL_POINTS: u64 = 6;
+const MAX_CONTROL_POINTS: u64 = 10;
+const ANGLE_JITTER: f32 = 0.2; // fraction of the spacing
+const MIN_RADIUS: f32 = 60.0; // m
+const MAX_RADIUS: f32 = 90.0; // m
+const SAMPLES_PER_SEGMENT: usize = 16;
+const MIN_TURN_RADIUS: f32 = 15.0; // m
+const MAX_ATTEMPTS: usize = 20;
+
+const MIN_WIDTH: f32 = 8.0; // m
+const MAX_WIDTH: f32 = 12.0; // m
+const MAX_BANK: f32 = 0.1; // rad
+// Turns this tight or tighter get the full bank angle
+const BANK_RADIUS: f32 = 30.0; // m
+const SECTORS: usize = 3;
+// Start line ahead of the start position, for a flying lap
+const START_LINE_OFFSET: f32 = 10.0; // m
+
+/// Closed-loop track generated from a seed.
+pub struct Track {
+    pub seed: u64,
+    pub centerline: Polyline,
+    pub width: f32,
+    pub max_bank: f32,
+    banks: Vec<f32>, // per centreline point, positive in left turns
+}
+
+impl Track {
+    pub fn generate(seed: u64) -> Self {
+        let mut rng = SplitMix64::new(seed);
+        let width = rng.range(MIN_WIDTH, MAX_WIDTH);
+        let max_bank = rng.range(0.0, MAX_BANK);
+        // Redraw loops with turns too tight to drive, the last draw is kept regardless
+        let mut points = Vec::new();
+        let mut curvatures = Vec::new();
+        for _ in 0..MAX_ATTEMPTS {
+            points = spline_loop(&mut rng);
+            curvatures = curvatures_of(&points);
+            if curvatures.iter().all(|k| k.abs() * MIN_TURN_RADIUS <= 1.0) {
+                break;
+            }
+        }
+        let banks = curvatures
+            .iter()
+            .map(|k| max_bank * (k * BANK_RADIUS).clamp(-1.0, 1.0))
+            .collect();
+
+        Self {
+            seed,
+            centerline: Polyline::new(points, true),
+            width,
+            max_bank,
+            banks,
+        }
+    }
+
+    /// Start pose on the centreline: position and heading.
+    pub fn start(&self) -> (Vec2, f32) {
+        let points = self.centerline.points();
+        (points[0], (points[1] - points[0]).to_angle())
+    }
+
+    /// Lap gates splitting the track into equal sectors, the start line first.
+    pub fn checkpoints(&self) -> Vec<CheckpointSpec> {
+        let length = self.centerline.length();
+        (0..SECTORS)
+            .map(|i| {
+                let s = START_LINE_OFFSET + length * i as f32 / SECTORS as f32;
+                let point = self.centerline.sample(s);
+                CheckpointSpec {
+                    x: point.x,
+                    y: point.y,
+                    yaw: self.centerline.heading_at(s),
+                    width: self.width,
+                }
+            })
+            .collect()
+    }
+
+    /// Track surface, banked about its inner edge.
+    pub fn mesh(&self) -> Mesh {
+        let points = self.centerline.points();
+        let n = points.len();
+        let mut positions: Vec<[f32; 3]> = Vec::with_capacity(2 * (n + 1));
+        let mut uvs: Vec<[f32; 2]> = Vec::with_capacity(2 * (n + 1));
+        // The first point is repeated at the end to close the loop
+        let mut s = 0.0;
+        for i in 0..=n {
+            let p = points[i % n];
+            if i > 0 {
+                s += points[i - 1].distance(p);
+            }
+            let normal = (points[(i + 1) % n] - points[(i + n - 1) % n])
+                .normalize_or_zero()
+                .perp();
+            let rise = self.width * self.banks[i % n].abs().sin();
+            let (right_z, left_z) = if self.banks[i % n] > 0.0 {
+                (rise, 0.0)
+            } else {
+                (0.0, rise)
+            };
+            for (side, z, u) in [(-0.5, right_z, 0.0), (0.5, left_z, 1.0)] {
+                positions.push((p + normal * side * self.width).extend(z).to_array());
+                uvs.push([u, s]);
+            }
+        }
+        let mut indices: Vec<u32> = Vec::with_capacity(6 * n);
+        for k in 0..n as u32 {
+            let (r0, l0, r1, l1) = (2 * k, 2 * k + 1, 2 * k + 2, 2 * k + 3);
+            indices.extend([r0, r1, l1, r0, l1, l0]);
+        }
+        Mesh::new(
+            PrimitiveTopology::TriangleList,
+            RenderAssetUsages::default(),
+        )
+        .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, positions)
+        .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, uvs)
+        .with_inserted_indices(Indices::U32(indices))
+        .with_computed_smooth_normals()
+    }
+}
+
+// Closed Catmull-Rom spline through jittered control points, counter-clockwise
+fn spline_loop(rng: &mut SplitMix64) -> Vec<Vec2> {
+    let count = MIN_CONTROL_POINTS + rng.next_u64() % (MAX_CONTROL_POINTS - MIN_CONTROL_POINTS + 1);
+    let count = count as usize;
+    let spacing = TAU / count as f32;
+    let controls: Vec<Vec2> = (0..count)
+        .map(|i| {
+            let angle = (i as f32 + rng.range(-ANGLE_JITTER, ANGLE_JITTER)) * spacing;
+            Vec2::from_angle(angle) * rng.range(MIN_RADIUS, MAX_RADIUS)
+        })
+        .collect();
+
+    let mut points = Vec::with_capacity(count * SAMPLES_PER_SEGMENT);
+    for i in 0..count {
+        let p = [
+            controls[(i + count - 1) % count],
+            controls[i],
+            controls[(i + 1) % count],
+            controls[(i + 2) % count],
+        ];
+        for k in 0..SAMPLES_PER_SEGMENT {
+            points.push(catmull_rom(p, k as f32 / SAMPLES_PER_SEGMENT as f32));
+        }
+    }
+    points
+}
+
+// Signed curvature at each point of a closed polyline, positive in left turns
+fn curvatures_of(points: &[Vec2]) -> Vec<f32> {
+    let n = points.len();
+    (0..n)
+        .map(|i| {
+            let prev = points[(i + n - 1) % n];
+            let next = points[(i + 1) % n];
+            let turn =
+                normalize_angle((next - points[i]).to_angle() - (points[i] - prev).to_angle());
+            turn / (0.5 * prev.distance(next)).max(f32::EPSILON)
+        })
+        .collect()
+}
+
+fn catmull_rom([p0, p1, p2, p3]: [Vec2; 4], t: f32) -> Vec2 {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    0.5 * (2.0 * p1
+        + (p2 - p0) * t
+        + (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * t2
+        + (3.0 * p1 - p0 - 3.0 * p2 + p3) * t3)
+}
+
+/// The generated track, if any.
+#[derive(Resource, Default)]
+pub struct RaceTrack {
+    pub track: Option<Track>,
+}
+
+#[derive(Component)]
+struct TrackSurface;
+
+#[allow(clippy::too_many_arguments)]
+fn regenerate_track(
+    mut commands: Commands,
+    key: Res<ButtonInput<KeyCode>>,
+    mut race_track: ResMut<RaceTrack>,
+    surfaces: Query<Entity, With<TrackSurface>>,
+    parking_bays: Query<Entity, With<ParkingBay>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    car: Single<(&mut EgoState, &mut Transform, Option<&mut PathFollower>), With<Ego>>,
+    mut road: ResMut<RoadNetwork>,
+    mut run_log: ResMut<RunLog>,
+    time: Res<Time>,
+) {
+    if !key.just_pressed(KeyCode::KeyG) {
+        return;
+    }
+    let seed = race_track.track.as_ref().map_or(1, |track| track.seed + 1);
+    let track = Track::generate(seed);
+
+    // The track replaces the road, and with it any parking bay beside it
+    for entity in surfaces.iter().chain(&parking_bays) {
+        commands.entity(entity).despawn();
+    }
+    commands.spawn((
+        TrackSurface,
+        GroundPlane,
+        SemanticClass::Road,
+        RigidBody::Static,
+        ColliderConstructor::TrimeshFromMesh,
+        Mesh3d(meshes.add(track.mesh())),
+        MeshMaterial3d(materials.add(StandardMaterial::from_color(GRAY))),
+        // Just above the ground plane
+        Transform::from_xyz(0.0, 0.0, 0.01),
+    ));
+    for gate in spawn_checkpoints(
+        &mut commands,
+        format!("Track seed {seed}"),
+        &track.checkpoints(),
+    ) {
+        commands.entity(gate).insert(TrackSurface);
+    }
+
+    // Start on the line, at rest
+    let (mut state, mut transform, follower) = car.into_inner();
+    let (start, yaw) = track.start();
+    *state = EgoState {
+        x: start.x,
+        y: start.y,
+        yaw,
+        v: 0.0,
+        s: 0.0,
+    };
+    transform.translation.x = start.x;
+    transform.translation.y = start.y;
+    transform.rotation = Quat::from_rotation_z(yaw + FRAC_PI_2) * Quat::from_rotation_x(FRAC_PI_2);
+
+    // The centreline is the reference path and the single lane of the road network
+    if let Some(mut follower) = follower {
+        follower.path = track.centerline.clone();
+    }
+    road.lanes = vec![Lane {
+        centerline: track.centerline.clone(),
+        width: track.width,
+    }];
+    road.roads = vec![RoadReference {
+        id: format!("track {seed}"),
+        reference: track.centerline.clone(),
+    }];
+
+    run_log.record(
+        time.elapsed_secs(),
+        "Track",
+        format!(
+            "Generated seed {} ({:.0} m, {:.1} m wide)",
+            seed,
+            track.centerline.length(),
+            track.width
+        ),
+    );
+    race_track.track = Some(track);
+}
+
+// Cars ride on the highest surface below them, taking its height and tilt so that they
+// lean into banked turns. Without one they stay level at ground height.
+fn follow_surface(
+    spatial_query: SpatialQuery,
+    mut cars: Query<(Entity, &EgoState, &mut Transform), With<Car>>,
+    surfaces: Query<(), With<GroundPlane>>,
+) {
+    const CAST_HEIGHT: f32 = 10.0; // m above the ground plane
+    for (entity, state, mut transform) in &mut cars {
+        let origin = Vec3::new(state.x, state.y, CAST_HEIGHT);
+        let hit = spatial_query.cast_ray_predicate(
+            origin,
+            Dir3::NEG_Z,
+            2.0 * CAST_HEIGHT,
+            true,
+            &SpatialQueryFilter::from_excluded_entities([entity]),
+            &|entity| surfaces.contains(entity),
+        );
+        let (ground, normal) = match hit {
+            Some(hit) => (
+                origin - Vec3::Z * hit.distance,
+                // Trimesh normals face whichever side was hit
+                hit.normal * hit.normal.z.signum(),
+            ),
+            None => (Vec3::new(state.x, state.y, 0.0), Vec3::Z),
+        };
+        transform.translation = ground + normal * RIDE_HEIGHT;
+        // Same model orientation as `init::spawn_car`, tilted onto the surface
+        transform.rotation = Quat::from_rotation_arc(Vec3::Z, normal)
+            * Quat::from_rotation_z(state.yaw + FRAC_PI_2)
+            * Quat::from_rotation_x(FRAC_PI_2);
+    }
+}
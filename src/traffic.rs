@@ -0,0 +1,487 @@
+use avian3d::prelude::*;
+use bevy::prelude::*;
+use serde::Deserialize;
+use std::f32::consts::FRAC_PI_2;
+
+use crate::car_dynamics::{
+    ControlSystems, EgoControl, EgoState, MAX_STEERING_ANGLE, STEER_RATIO, pedals_for_acceleration,
+};
+use crate::init::spawn_car;
+use crate::path::Polyline;
+use crate::path_follower::pure_pursuit;
+use crate::road::{LANE_WIDTH, RoadNetwork};
+use crate::scenario::Obstacle;
+use crate::utils::{SplitMix64, normalize_angle};
+
+pub struct TrafficPlugin;
+
+impl Plugin for TrafficPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TrafficConfig>()
+            // After the scenario has placed the ego and its own traffic
+            .add_systems(PostUpdate, respawn_density_traffic)
+            .add_systems(Update, drive_traffic.in_set(ControlSystems::Autopilot));
+    }
+}
+
+const VEHICLE_LENGTH: f32 = 5.0; // m, bumper to bumper spacing of centres at zero gap
+const LOOKAHEAD_RANGE: f32 = 150.0; // m, for leaders and followers
+const MIN_LOOKAHEAD: f32 = 5.0; // m, steering
+const LOOKAHEAD_GAIN: f32 = 0.5; // s, steering
+const LANE_CHANGE_COOLDOWN: f32 = 5.0; // s
+const MAX_LANE_HEADING_ERROR: f32 = 0.5; // rad, for a lane to count as adjacent
+const SPAWN_CLEARANCE: f32 = 20.0; // m, kept free around other vehicles
+const DESIRED_SPEED_SPREAD: f32 = 0.2; // fraction of the configured desired speed
+
+/// Intelligent Driver Model parameters.
+#[derive(Copy, Clone, Debug)]
+pub struct Idm {
+    pub time_headway: f32,             // s
+    pub min_gap: f32,                  // m
+    pub max_acceleration: f32,         // m/s^2
+    pub comfortable_deceleration: f32, // m/s^2
+    pub exponent: i32,
+}
+
+impl Default for Idm {
+    fn default() -> Self {
+        Self {
+            time_headway: 1.5,
+            min_gap: 2.0,
+            max_acceleration: 1.5,
+            comfortable_deceleration: 2.0,
+            exponent: 4,
+        }
+    }
+}
+
+impl Idm {
+    /// Acceleration at speed `v` towards `desired_speed`, behind a leader at
+    /// (`gap`, `leader_speed`) if there is one.
+    pub fn acceleration(&self, v: f32, desired_speed: f32, leader: Option<(f32, f32)>) -> f32 {
+        let free = 1.0 - (v / desired_speed.max(0.1)).powi(self.exponent);
+        let interaction = leader.map_or(0.0, |(gap, leader_speed)| {
+            let braking = v * (v - leader_speed)
+                / (2.0 * (self.max_acceleration * self.comfortable_deceleration).sqrt());
+            let desired_gap = self.min_gap + (v * self.time_headway + braking).max(0.0);
+            (desired_gap / gap.max(0.1)).powi(2)
+        });
+        self.max_acceleration * (free - interaction)
+    }
+}
+
+/// MOBIL lane change model parameters.
+#[derive(Copy, Clone, Debug)]
+pub struct Mobil {
+    pub politeness: f32,
+    pub threshold: f32,         // m/s^2, advantage needed to change lanes
+    pub safe_deceleration: f32, // m/s^2, most the new follower may have to brake
+}
+
+impl Default for Mobil {
+    fn default() -> Self {
+        Self {
+            politeness: 0.3,
+            threshold: 0.2,
+            safe_deceleration: 4.0,
+        }
+    }
+}
+
+/// Settings shared by all traffic vehicles.
+#[derive(Resource)]
+pub struct TrafficConfig {
+    pub density: f32,       // vehicles per km of lane
+    pub desired_speed: f32, // m/s, mean of the density traffic
+    pub lane_changes: bool,
+    pub idm: Idm,
+    pub mobil: Mobil,
+    spawned_density: f32,
+}
+
+impl Default for TrafficConfig {
+    fn default() -> Self {
+        Self {
+            density: 0.0,
+            desired_speed: 12.0,
+            lane_changes: true,
+            idm: Idm::default(),
+            mobil: Mobil::default(),
+            spawned_density: 0.0,
+        }
+    }
+}
+
+/// Traffic part of a scenario.
+#[derive(Deserialize, Clone, Default)]
+pub struct TrafficSpec {
+    /// Vehicles per km of lane, spread over every lane of the road network.
+    #[serde(default)]
+    pub density: f32,
+    #[serde(default)]
+    pub vehicles: Vec<TrafficVehicle>,
+}
+
+/// Traffic vehicle placed by a scenario.
+#[derive(Deserialize, Clone)]
+pub struct TrafficVehicle {
+    pub route: Route,
+    #[serde(default)]
+    pub s: f32, // m along the route
+    #[serde(default)]
+    pub speed: f32, // m/s
+    pub desired_speed: f32, // m/s
+    #[serde(default)]
+    pub lane_changes: bool,
+}
+
+#[derive(Deserialize, Clone)]
+pub enum Route {
+    /// Lane of the road network, by index.
+    Lane(usize),
+    /// Closed loop through the points.
+    Waypoints(Vec<(f32, f32)>),
+}
+
+/// Vehicle driven along `route` by IDM, changing lanes with MOBIL.
+#[derive(Component)]
+pub struct TrafficAgent {
+    pub route: Polyline,
+    pub lane: Option<usize>, // road network lane the route follows
+    pub desired_speed: f32,
+    pub lane_changes: bool,
+    cooldown: f32,
+}
+
+/// Traffic spawned from [`TrafficConfig::density`], replaced when it changes.
+#[derive(Component)]
+struct DensityTraffic;
+
+/// Spawns a scenario traffic vehicle, or nothing if its lane does not exist.
+pub fn spawn_traffic_vehicle(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    road: &RoadNetwork,
+    vehicle: &TrafficVehicle,
+) -> Option<Entity> {
+    let (route, lane) = match &vehicle.route {
+        Route::Lane(lane) => (road.lanes.get(*lane)?.centerline.clone(), Some(*lane)),
+        Route::Waypoints(points) => (
+            Polyline::new(points.iter().map(|&(x, y)| Vec2::new(x, y)).collect(), true),
+            None,
+        ),
+    };
+    let agent = TrafficAgent {
+        route,
+        lane,
+        desired_speed: vehicle.desired_speed,
+        lane_changes: vehicle.lane_changes,
+        cooldown: 0.0,
+    };
+    Some(spawn_agent(
+        commands,
+        asset_server,
+        agent,
+        vehicle.s,
+        vehicle.speed,
+    ))
+}
+
+fn spawn_agent(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    agent: TrafficAgent,
+    s: f32,
+    speed: f32,
+) -> Entity {
+    let position = agent.route.sample(s);
+    let state = EgoState {
+        x: position.x,
+        y: position.y,
+        yaw: agent.route.heading_at(s),
+        v: speed,
+        s: 0.0,
+    };
+    let entity = spawn_car(commands, asset_server, state);
+    commands.entity(entity).insert(agent);
+    entity
+}
+
+fn respawn_density_traffic(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut config: ResMut<TrafficConfig>,
+    road: Res<RoadNetwork>,
+    spawned: Query<Entity, With<DensityTraffic>>,
+    users: Query<&EgoState, Without<DensityTraffic>>,
+) {
+    if config.density == config.spawned_density && !road.is_changed() {
+        return;
+    }
+    config.spawned_density = config.density;
+    for entity in &spawned {
+        commands.entity(entity).despawn();
+    }
+
+    // Evenly spaced with a random phase per lane, reproducible for a given density
+    let mut rng = SplitMix64::new(config.density.to_bits() as u64);
+    for (i, lane) in road.lanes.iter().enumerate() {
+        let length = lane.centerline.length();
+        let count = (length / 1000.0 * config.density) as usize;
+        if count == 0 {
+            continue;
+        }
+        let spacing = length / count as f32;
+        let phase = rng.range(0.0, spacing);
+        for k in 0..count {
+            let s = phase + k as f32 * spacing;
+            let position = lane.centerline.sample(s);
+            let desired_speed = config.desired_speed
+                * rng.range(1.0 - DESIRED_SPEED_SPREAD, 1.0 + DESIRED_SPEED_SPREAD);
+            if users
+                .iter()
+                .any(|u| Vec2::new(u.x, u.y).distance(position) < SPAWN_CLEARANCE)
+            {
+                continue;
+            }
+            let agent = TrafficAgent {
+                route: lane.centerline.clone(),
+                lane: Some(i),
+                desired_speed,
+                lane_changes: true,
+                cooldown: 0.0,
+            };
+            let entity = spawn_agent(&mut commands, &asset_server, agent, s, desired_speed);
+            commands.entity(entity).insert(DensityTraffic);
+        }
+    }
+}
+
+// Anything a traffic vehicle has to keep its distance from
+struct RoadUser {
+    entity: Entity,
+    position: Vec2,
+    velocity: Vec2,
+    desired_speed: f32,
+}
+
+// Another road user as seen along a route
+#[derive(Copy, Clone)]
+struct Neighbour {
+    distance: f32, // m between centres along the route, negative behind
+    speed: f32,    // m/s along the route
+    desired_speed: f32,
+}
+
+impl Neighbour {
+    fn gap(&self) -> f32 {
+        self.distance.abs() - VEHICLE_LENGTH
+    }
+}
+
+// Nearest road users ahead of and behind `s` within half a lane of `route`
+fn neighbours(
+    route: &Polyline,
+    s: f32,
+    own: Entity,
+    users: &[RoadUser],
+) -> (Option<Neighbour>, Option<Neighbour>) {
+    let length = route.length();
+    let mut ahead: Option<Neighbour> = None;
+    let mut behind: Option<Neighbour> = None;
+    for user in users.iter().filter(|u| u.entity != own) {
+        let Some(p) = route.project(user.position) else {
+            continue;
+        };
+        if p.lateral.abs() > LANE_WIDTH / 2.0 {
+            continue;
+        }
+        let mut distance = p.s - s;
+        if route.is_closed() {
+            distance = (distance + length / 2.0).rem_euclid(length) - length / 2.0;
+        }
+        if distance.abs() > LOOKAHEAD_RANGE {
+            continue;
+        }
+        let neighbour = Neighbour {
+            distance,
+            speed: user.velocity.dot(Vec2::from_angle(p.heading)),
+            desired_speed: user.desired_speed,
+        };
+        if distance > 0.0 {
+            if ahead.is_none_or(|a| distance < a.distance) {
+                ahead = Some(neighbour);
+            }
+        } else if behind.is_none_or(|b| distance > b.distance) {
+            behind = Some(neighbour);
+        }
+    }
+    (ahead, behind)
+}
+
+// MOBIL incentive for moving from the lane with `current` neighbours (ahead,
+// behind) to the one with `target` neighbours, or none if the move is unsafe
+fn lane_change_gain(
+    config: &TrafficConfig,
+    v: f32,
+    desired_speed: f32,
+    current: (Option<Neighbour>, Option<Neighbour>),
+    target: (Option<Neighbour>, Option<Neighbour>),
+) -> Option<f32> {
+    let (idm, mobil) = (&config.idm, &config.mobil);
+    let (ahead, behind) = current;
+    let (new_ahead, new_behind) = target;
+    if new_ahead.is_some_and(|a| a.gap() < idm.min_gap)
+        || new_behind.is_some_and(|b| b.gap() < idm.min_gap)
+    {
+        return None;
+    }
+
+    // Acceleration of an optional follower behind an optional (gap, speed) leader
+    let following = |follower: Option<Neighbour>, leader: Option<(f32, f32)>| {
+        follower.map_or(0.0, |f| idm.acceleration(f.speed, f.desired_speed, leader))
+    };
+    let behind_us = |follower: Option<Neighbour>| follower.map(|f| (f.gap(), v));
+    let between = |follower: Option<Neighbour>, leader: Option<Neighbour>| {
+        let (follower, leader) = (follower?, leader?);
+        Some((
+            leader.distance - follower.distance - VEHICLE_LENGTH,
+            leader.speed,
+        ))
+    };
+
+    let new_follower_after = following(new_behind, behind_us(new_behind));
+    if new_follower_after < -mobil.safe_deceleration {
+        return None;
+    }
+    let new_follower_now = following(new_behind, between(new_behind, new_ahead));
+    let old_follower_now = following(behind, behind_us(behind));
+    let old_follower_after = following(behind, between(behind, ahead));
+    let own_now = idm.acceleration(v, desired_speed, ahead.map(|a| (a.gap(), a.speed)));
+    let own_after = idm.acceleration(v, desired_speed, new_ahead.map(|a| (a.gap(), a.speed)));
+    Some(
+        own_after - own_now
+            + mobil.politeness
+                * (new_follower_after - new_follower_now + old_follower_after - old_follower_now),
+    )
+}
+
+fn drive_traffic(
+    mut agents: Query<(
+        Entity,
+        &mut TrafficAgent,
+        &mut EgoState,
+        &mut EgoControl,
+        &mut Transform,
+    )>,
+    others: Query<(Entity, &EgoState), Without<TrafficAgent>>,
+    obstacles: Query<(Entity, &Position, Option<&LinearVelocity>), With<Obstacle>>,
+    road: Res<RoadNetwork>,
+    config: Res<TrafficConfig>,
+    time: Res<Time>,
+) {
+    let mut users: Vec<RoadUser> = agents
+        .iter()
+        .map(|(entity, agent, state, ..)| (entity, state, agent.desired_speed))
+        .chain(
+            others
+                .iter()
+                .map(|(entity, state)| (entity, state, config.desired_speed)),
+        )
+        .map(|(entity, state, desired_speed)| RoadUser {
+            entity,
+            position: Vec2::new(state.x, state.y),
+            velocity: Vec2::from_angle(state.yaw) * state.v,
+            desired_speed,
+        })
+        .collect();
+    users.extend(
+        obstacles
+            .iter()
+            .map(|(entity, position, velocity)| RoadUser {
+                entity,
+                position: position.truncate(),
+                velocity: velocity.map_or(Vec2::ZERO, |v| v.truncate()),
+                desired_speed: 0.0,
+            }),
+    );
+
+    let idm = &config.idm;
+    for (entity, mut agent, mut state, mut control, mut transform) in &mut agents {
+        let position = Vec2::new(state.x, state.y);
+        let Some(projection) = agent.route.project(position) else {
+            continue;
+        };
+
+        // Open routes start over once driven to the end
+        if !agent.route.is_closed() && projection.s >= agent.route.length() - 1.0 {
+            let start = agent.route.sample(0.0);
+            state.x = start.x;
+            state.y = start.y;
+            state.yaw = agent.route.heading_at(0.0);
+            transform.translation.x = start.x;
+            transform.translation.y = start.y;
+            transform.rotation =
+                Quat::from_rotation_z(state.yaw + FRAC_PI_2) * Quat::from_rotation_x(FRAC_PI_2);
+            continue;
+        }
+
+        let s = projection.s;
+        let (ahead, behind) = neighbours(&agent.route, s, entity, &users);
+        let leader = ahead.map(|a| (a.gap(), a.speed));
+        let acceleration = idm.acceleration(state.v, agent.desired_speed, leader);
+
+        // MOBIL: move to the adjacent lane that pays off most
+        agent.cooldown -= time.delta_secs();
+        if config.lane_changes
+            && agent.lane_changes
+            && agent.cooldown <= 0.0
+            && let Some(current) = agent.lane
+        {
+            let best = road
+                .lanes
+                .iter()
+                .enumerate()
+                .filter(|&(i, _)| i != current)
+                .filter_map(|(i, lane)| {
+                    let p = lane.centerline.project(position)?;
+                    let adjacent = (p.lateral.abs() - lane.width).abs() < lane.width / 4.0
+                        && normalize_angle(state.yaw - p.heading).abs() < MAX_LANE_HEADING_ERROR;
+                    if !adjacent {
+                        return None;
+                    }
+                    let target = neighbours(&lane.centerline, p.s, entity, &users);
+                    let gain = lane_change_gain(
+                        &config,
+                        state.v,
+                        agent.desired_speed,
+                        (ahead, behind),
+                        target,
+                    )?;
+                    (gain > config.mobil.threshold).then_some((i, gain))
+                })
+                .max_by(|a, b| a.1.total_cmp(&b.1));
+            if let Some((lane, _)) = best {
+                agent.route = road.lanes[lane].centerline.clone();
+                agent.lane = Some(lane);
+                agent.cooldown = LANE_CHANGE_COOLDOWN;
+            }
+        }
+
+        let (throttle, brake) = pedals_for_acceleration(acceleration, state.v);
+        // Route may have just switched to another lane
+        let s = agent.route.project(position).map_or(s, |p| p.s);
+        let target = agent
+            .route
+            .sample(s + MIN_LOOKAHEAD + LOOKAHEAD_GAIN * state.v.max(0.0));
+        let front_wheel_angle = pure_pursuit(position, state.yaw, target)
+            .clamp(-MAX_STEERING_ANGLE, MAX_STEERING_ANGLE);
+        *control = EgoControl {
+            throttle,
+            brake,
+            front_wheel_angle,
+            steer_wheel_angle: front_wheel_angle * STEER_RATIO,
+            ..default()
+        };
+    }
+}
@@ -0,0 +1,368 @@
+use bevy::color::palettes::css::{DARK_GRAY, GREEN, ORANGE, RED, WHITE};
+use bevy::prelude::*;
+use serde::Deserialize;
+use std::f32::consts::FRAC_PI_2;
+
+use crate::car_dynamics::{CAR_LENGTH, Ego, EgoState};
+use crate::run_log::RunLog;
+use crate::scenario::ScenarioEntity;
+
+pub struct TrafficControlPlugin;
+
+impl Plugin for TrafficControlPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RuleViolations>()
+            .add_message::<RuleViolation>()
+            .add_systems(
+                Update,
+                (
+                    update_traffic_lights,
+                    draw_stop_lines,
+                    (check_stop_lines, log_rule_violations).chain(),
+                ),
+            );
+    }
+}
+
+// Below this speed the car counts as stopped
+const STOP_SPEED: f32 = 0.3; // m/s
+// A stop sign is obeyed by stopping this close before the line
+const STOP_ZONE: f32 = 5.0; // m
+// Stopping this far past the line is an overshoot rather than running it
+const MAX_OVERSHOOT: f32 = 4.0; // m
+// Larger jumps between frames are teleports, not crossings
+const MAX_STEP: f32 = 5.0; // m
+// Approaches heading further off the controlled direction are ignored
+const MAX_HEADING_ERROR: f32 = 1.0; // rad
+const POLE_HEIGHT: f32 = 3.0; // m
+const POLE_OFFSET: f32 = 0.5; // m, beyond the right end of the line
+
+/// Fixed-time signal cycle: green, amber, then red.
+#[derive(Deserialize, Clone, Copy)]
+pub struct PhaseTiming {
+    pub green: f32, // s
+    pub amber: f32, // s
+    pub red: f32,   // s
+    /// Time into the cycle at the start of the scenario.
+    #[serde(default)]
+    pub offset: f32, // s
+}
+
+impl Default for PhaseTiming {
+    fn default() -> Self {
+        Self {
+            green: 20.0,
+            amber: 3.0,
+            red: 20.0,
+            offset: 0.0,
+        }
+    }
+}
+
+impl PhaseTiming {
+    pub fn phase_at(&self, elapsed: f32) -> LightPhase {
+        let cycle = self.green + self.amber + self.red;
+        let t = (elapsed + self.offset).rem_euclid(cycle.max(f32::EPSILON));
+        if t < self.green {
+            LightPhase::Green
+        } else if t < self.green + self.amber {
+            LightPhase::Amber
+        } else {
+            LightPhase::Red
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LightPhase {
+    Green,
+    Amber,
+    Red,
+}
+
+impl LightPhase {
+    fn color(self) -> Srgba {
+        match self {
+            LightPhase::Green => GREEN,
+            LightPhase::Amber => ORANGE,
+            LightPhase::Red => RED,
+        }
+    }
+}
+
+/// Signal at a stop line, placed in a scenario's `traffic_lights`.
+#[derive(Deserialize, Clone, Copy)]
+pub struct TrafficLightSpec {
+    /// Centre of the stop line.
+    pub x: f32,
+    pub y: f32,
+    /// Direction of the traffic the light controls.
+    pub yaw: f32,
+    /// Length of the stop line, across the controlled lanes.
+    #[serde(default = "default_line_width")]
+    pub width: f32,
+    #[serde(default)]
+    pub timing: PhaseTiming,
+}
+
+/// Stop sign at a stop line, placed in a scenario's `stop_signs`.
+#[derive(Deserialize, Clone, Copy)]
+pub struct StopSignSpec {
+    pub x: f32,
+    pub y: f32,
+    pub yaw: f32,
+    #[serde(default = "default_line_width")]
+    pub width: f32,
+}
+
+fn default_line_width() -> f32 {
+    3.5
+}
+
+/// Line traffic in direction `yaw` has to stop at, with the ego's progress across it.
+#[derive(Component)]
+pub struct StopLine {
+    pub position: Vec2,
+    pub yaw: f32,
+    pub width: f32,
+    previous: Option<f32>, // signed distance of the ego front, positive past the line
+    stopped: bool,         // stopped before the line since last crossing it
+    pending: Option<ViolationKind>,
+}
+
+impl StopLine {
+    fn new(x: f32, y: f32, yaw: f32, width: f32) -> Self {
+        Self {
+            position: Vec2::new(x, y),
+            yaw,
+            width,
+            previous: None,
+            stopped: false,
+            pending: None,
+        }
+    }
+
+    // Ends of the line, right then left
+    fn ends(&self) -> (Vec2, Vec2) {
+        let half = Vec2::from_angle(self.yaw).perp() * self.width / 2.0;
+        (self.position - half, self.position + half)
+    }
+
+    fn pole_position(&self) -> Vec2 {
+        let (right, _) = self.ends();
+        right - Vec2::from_angle(self.yaw).perp() * POLE_OFFSET
+    }
+}
+
+#[derive(Component)]
+pub struct TrafficLight {
+    pub timing: PhaseTiming,
+    pub phase: LightPhase,
+    start: f32, // s since startup
+    lamp: Handle<StandardMaterial>,
+}
+
+#[derive(Component)]
+pub struct StopSign;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ViolationKind {
+    RanRedLight,
+    StoppedPastLine,
+    FailedToStop,
+}
+
+impl ViolationKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ViolationKind::RanRedLight => "Ran red light",
+            ViolationKind::StoppedPastLine => "Stopped past the stop line",
+            ViolationKind::FailedToStop => "Failed to stop at stop sign",
+        }
+    }
+}
+
+#[derive(Message, Copy, Clone, Debug)]
+pub struct RuleViolation {
+    pub kind: ViolationKind,
+    pub position: Vec2,
+    pub speed: f32,
+}
+
+/// Traffic rule violations of the ego during the run.
+#[derive(Resource, Default)]
+pub struct RuleViolations {
+    pub entries: Vec<(f32, RuleViolation)>, // s since startup
+}
+
+pub fn spawn_traffic_light(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+    spec: &TrafficLightSpec,
+    start: f32,
+) {
+    let line = StopLine::new(spec.x, spec.y, spec.yaw, spec.width);
+    let phase = spec.timing.phase_at(0.0);
+    let lamp = materials.add(lamp_material(phase));
+    let pole = line.pole_position();
+    commands
+        .spawn((
+            ScenarioEntity,
+            TrafficLight {
+                timing: spec.timing,
+                phase,
+                start,
+                lamp: lamp.clone(),
+            },
+            Mesh3d(meshes.add(Cylinder::new(0.08, POLE_HEIGHT))),
+            MeshMaterial3d(materials.add(StandardMaterial::from_color(DARK_GRAY))),
+            // Cylinders stand along y, turn them upright
+            Transform::from_xyz(pole.x, pole.y, POLE_HEIGHT / 2.0)
+                .with_rotation(Quat::from_rotation_x(FRAC_PI_2)),
+            line,
+        ))
+        .with_child((
+            Mesh3d(meshes.add(Sphere::new(0.2))),
+            MeshMaterial3d(lamp),
+            Transform::from_xyz(0.0, POLE_HEIGHT / 2.0, 0.0),
+        ));
+}
+
+pub fn spawn_stop_sign(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+    spec: &StopSignSpec,
+) {
+    let line = StopLine::new(spec.x, spec.y, spec.yaw, spec.width);
+    let pole = line.pole_position();
+    commands
+        .spawn((
+            ScenarioEntity,
+            StopSign,
+            Mesh3d(meshes.add(Cylinder::new(0.05, POLE_HEIGHT - 0.5))),
+            MeshMaterial3d(materials.add(StandardMaterial::from_color(DARK_GRAY))),
+            Transform::from_xyz(pole.x, pole.y, (POLE_HEIGHT - 0.5) / 2.0)
+                .with_rotation(Quat::from_rotation_x(FRAC_PI_2)),
+            line,
+        ))
+        .with_child((
+            // Octagon facing the oncoming traffic
+            Mesh3d(meshes.add(Cylinder::new(0.4, 0.03).mesh().resolution(8))),
+            MeshMaterial3d(materials.add(StandardMaterial::from_color(RED))),
+            // Undoes the pole's rotation, then turns the axis to the travel direction
+            Transform::from_xyz(0.0, (POLE_HEIGHT - 0.5) / 2.0 + 0.3, 0.0).with_rotation(
+                Quat::from_rotation_x(-FRAC_PI_2) * Quat::from_rotation_z(spec.yaw - FRAC_PI_2),
+            ),
+        ));
+}
+
+fn lamp_material(phase: LightPhase) -> StandardMaterial {
+    StandardMaterial {
+        base_color: phase.color().into(),
+        emissive: LinearRgba::from(phase.color()) * 5.0,
+        ..default()
+    }
+}
+
+fn update_traffic_lights(
+    mut lights: Query<&mut TrafficLight>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    time: Res<Time>,
+) {
+    for mut light in &mut lights {
+        let phase = light.timing.phase_at(time.elapsed_secs() - light.start);
+        if phase != light.phase {
+            light.phase = phase;
+            if let Some(material) = materials.get_mut(&light.lamp) {
+                *material = lamp_material(phase);
+            }
+        }
+    }
+}
+
+fn draw_stop_lines(mut gizmos: Gizmos, lines: Query<&StopLine>) {
+    const Z: f32 = 0.03; // above the lane markings
+    for line in &lines {
+        let (right, left) = line.ends();
+        gizmos.line(right.extend(Z), left.extend(Z), WHITE);
+    }
+}
+
+fn check_stop_lines(
+    car: Single<&EgoState, With<Ego>>,
+    mut lines: Query<(&mut StopLine, Option<&TrafficLight>)>,
+    mut violations: MessageWriter<RuleViolation>,
+) {
+    let heading = Vec2::from_angle(car.yaw);
+    let front = Vec2::new(car.x, car.y) + heading * CAR_LENGTH / 2.0;
+    for (mut line, light) in &mut lines {
+        let direction = Vec2::from_angle(line.yaw);
+        let relative = front - line.position;
+        let along = relative.dot(direction);
+        let approaching = relative.dot(direction.perp()).abs() <= line.width / 2.0
+            && heading.angle_to(direction).abs() < MAX_HEADING_ERROR;
+        let violation = |kind| RuleViolation {
+            kind,
+            position: front,
+            speed: car.v,
+        };
+
+        // An overshoot ends by stopping close past the line or driving on
+        if let Some(kind) = line.pending {
+            if approaching && along < MAX_OVERSHOOT && car.v.abs() < STOP_SPEED {
+                violations.write(violation(ViolationKind::StoppedPastLine));
+                line.pending = None;
+            } else if !approaching || along >= MAX_OVERSHOOT {
+                violations.write(violation(kind));
+                line.pending = None;
+            }
+        }
+
+        if !approaching {
+            line.previous = None;
+            line.stopped = false;
+            continue;
+        }
+        if (-STOP_ZONE..=0.0).contains(&along) && car.v.abs() < STOP_SPEED {
+            line.stopped = true;
+        }
+        let crossed = line
+            .previous
+            .is_some_and(|previous| previous < 0.0 && along >= 0.0 && along - previous < MAX_STEP);
+        if crossed {
+            let red = light.is_some_and(|light| light.phase == LightPhase::Red);
+            let unstopped = light.is_none() && !line.stopped;
+            if red {
+                line.pending = Some(ViolationKind::RanRedLight);
+            } else if unstopped {
+                line.pending = Some(ViolationKind::FailedToStop);
+            }
+            line.stopped = false;
+        }
+        line.previous = Some(along);
+    }
+}
+
+fn log_rule_violations(
+    mut violations: MessageReader<RuleViolation>,
+    mut log: ResMut<RuleViolations>,
+    mut run_log: ResMut<RunLog>,
+    time: Res<Time>,
+) {
+    for violation in violations.read() {
+        run_log.record(
+            time.elapsed_secs(),
+            "Traffic Rules",
+            format!(
+                "{} at ({:.1}, {:.1}), {:.1} m/s",
+                violation.kind.name(),
+                violation.position.x,
+                violation.position.y,
+                violation.speed
+            ),
+        );
+        log.entries.push((time.elapsed_secs(), *violation));
+    }
+}
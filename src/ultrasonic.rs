@@ -0,0 +1,297 @@
+use avian3d::prelude::*;
+use bevy::color::palettes::css::*;
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use crate::car_dynamics::{CAR_LENGTH, Car, EgoState};
+use crate::faults::{FaultTarget, Faults};
+use crate::semantic::SemanticClass;
+use crate::sensor::Mount;
+use crate::utils::SplitMix64;
+
+pub struct UltrasonicPlugin;
+
+impl Plugin for UltrasonicPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_message::<UltrasonicScan>()
+            .add_systems(Update, (scan_ultrasonics, draw_ultrasonic_echoes));
+        #[cfg(not(target_arch = "wasm32"))]
+        app.add_systems(Startup, recording::setup_recorder)
+            .add_systems(Update, recording::record_scans.after(scan_ultrasonics));
+    }
+}
+
+// Rays sampling each sensor's beam, across and up its field of view
+const BEAM_RAYS: (u32, u32) = (9, 3);
+
+/// Distance band colours, nearest first, shared by the gizmos and the panel.
+pub const PROXIMITY_BANDS: [(f32, Srgba); 3] = [(0.4, RED), (0.9, ORANGE), (1.5, YELLOW)];
+
+/// Park distance control: short-range transducers around the bumpers, each reporting the
+/// distance to the nearest echo in its beam.
+///
+/// The beam is sampled with a fan of ray casts. Returns from the ground and road are
+/// ignored, as the real sensors' thresholds do.
+#[derive(Component, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct Ultrasonic {
+    pub enabled: bool,
+    /// Transducers, facing out along their x axis.
+    pub sensors: Vec<Mount>,
+    pub range: f32,          // m
+    pub min_range: f32,      // m, nearer echoes read as this
+    pub horizontal_fov: f32, // deg
+    pub vertical_fov: f32,   // deg
+    pub rate: f32,           // Hz
+    pub noise: f32,          // m, standard deviation
+    /// Beep faster as obstacles approach.
+    pub beep: bool,
+    pub seed: u64,
+    #[serde(skip)]
+    next_scan: f32, // s since startup
+    #[serde(skip)]
+    rng: Option<SplitMix64>,
+    /// Last distance of each sensor, `None` with nothing in range.
+    #[serde(skip)]
+    pub distances: Vec<Option<f32>>,
+}
+
+impl Default for Ultrasonic {
+    fn default() -> Self {
+        // Four across each bumper, the outer ones angled to the corners
+        let bumper = |x: f32, facing: f32| {
+            [(0.8, 35.0), (0.3, 0.0), (-0.3, 0.0), (-0.8, -35.0)].map(|(y, splay): (f32, f32)| {
+                let mut mount = Mount::new(x, y, 0.5);
+                mount.rotation.2 = (facing + splay * x.signum()).to_radians();
+                mount
+            })
+        };
+        Self {
+            enabled: true,
+            sensors: [
+                bumper(CAR_LENGTH / 2.0, 0.0),
+                bumper(-CAR_LENGTH / 2.0, 180.0),
+            ]
+            .concat(),
+            range: 2.5,
+            min_range: 0.2,
+            horizontal_fov: 70.0,
+            vertical_fov: 30.0,
+            rate: 15.0,
+            noise: 0.01,
+            beep: true,
+            seed: 0,
+            next_scan: 0.0,
+            rng: None,
+            distances: Vec::new(),
+        }
+    }
+}
+
+impl Ultrasonic {
+    /// Park distance sensors fitted to the car but switched off.
+    pub fn disabled() -> Self {
+        Self {
+            enabled: false,
+            ..default()
+        }
+    }
+
+    /// Nearest distance over all sensors.
+    pub fn nearest(&self) -> Option<f32> {
+        self.distances.iter().flatten().copied().reduce(f32::min)
+    }
+
+    // Unit ray directions in the sensor frame
+    fn beam(&self) -> impl Iterator<Item = Vec3> + '_ {
+        let (across, up) = BEAM_RAYS;
+        let spread =
+            |i: u32, n: u32, fov: f32| (fov * (i as f32 / (n - 1) as f32 - 0.5)).to_radians();
+        (0..across).flat_map(move |i| {
+            let azimuth = spread(i, across, self.horizontal_fov);
+            (0..up).map(move |j| {
+                let elevation = spread(j, up, self.vertical_fov);
+                Vec3::new(
+                    elevation.cos() * azimuth.cos(),
+                    elevation.cos() * azimuth.sin(),
+                    elevation.sin(),
+                )
+            })
+        })
+    }
+}
+
+/// Colour of an echo at `distance`, green beyond the nearest bands.
+pub fn proximity_color(distance: f32) -> Srgba {
+    PROXIMITY_BANDS
+        .iter()
+        .find(|(limit, _)| distance < *limit)
+        .map_or(LIME, |(_, color)| *color)
+}
+
+/// Distances of all sensors, in the order of `Ultrasonic::sensors`.
+#[derive(Message, Clone, Debug)]
+pub struct UltrasonicScan {
+    #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+    pub time: f32, // s since startup
+    pub distances: Vec<Option<f32>>,
+}
+
+#[allow(clippy::too_many_arguments)]
+fn scan_ultrasonics(
+    mut ultrasonics: Query<(Entity, &mut Ultrasonic, &EgoState), With<Car>>,
+    sensors: Query<(), With<Sensor>>,
+    classes: Query<&SemanticClass>,
+    parents: Query<&ChildOf>,
+    spatial_query: SpatialQuery,
+    mut scans: MessageWriter<UltrasonicScan>,
+    mut faults: ResMut<Faults>,
+    time: Res<Time>,
+) {
+    let now = time.elapsed_secs();
+    for (car, mut ultrasonic, state) in &mut ultrasonics {
+        if !ultrasonic.enabled || now < ultrasonic.next_scan {
+            continue;
+        }
+        ultrasonic.next_scan = now + 1.0 / ultrasonic.rate.max(0.1);
+        let seed = ultrasonic.seed;
+        let mut rng = ultrasonic.rng.take().unwrap_or(SplitMix64::new(seed));
+
+        let filter = SpatialQueryFilter::from_excluded_entities([car]);
+        let echoes = |entity| {
+            !sensors.contains(entity)
+                && !matches!(
+                    SemanticClass::of(entity, &classes, &parents),
+                    SemanticClass::Ground | SemanticClass::Road
+                )
+        };
+        let distances: Vec<_> = ultrasonic
+            .sensors
+            .iter()
+            .map(|mount| {
+                let pose = mount.world_pose(state);
+                ultrasonic
+                    .beam()
+                    .filter_map(|direction| {
+                        let direction = Dir3::new(pose.rotation * direction).ok()?;
+                        spatial_query.cast_ray_predicate(
+                            pose.translation,
+                            direction,
+                            ultrasonic.range,
+                            true,
+                            &filter,
+                            &echoes,
+                        )
+                    })
+                    .map(|hit| hit.distance)
+                    .reduce(f32::min)
+                    .map(|distance| {
+                        (distance + rng.gaussian() * ultrasonic.noise)
+                            .clamp(ultrasonic.min_range, ultrasonic.range)
+                    })
+            })
+            .collect();
+
+        ultrasonic.rng = Some(rng);
+        let scan = UltrasonicScan {
+            time: now,
+            distances,
+        };
+        let Some(scan) = faults.apply(FaultTarget::Ultrasonic, scan, now, |scan, error| {
+            for distance in scan.distances.iter_mut().flatten() {
+                *distance = (*distance + error()).max(0.0);
+            }
+        }) else {
+            continue;
+        };
+        ultrasonic.distances = scan.distances.clone();
+        scans.write(scan);
+    }
+}
+
+fn draw_ultrasonic_echoes(mut gizmos: Gizmos, ultrasonics: Query<(&Ultrasonic, &EgoState)>) {
+    const SEGMENTS: u32 = 8;
+    for (ultrasonic, state) in &ultrasonics {
+        if !ultrasonic.enabled {
+            continue;
+        }
+        let half_fov = ultrasonic.horizontal_fov.to_radians() / 2.0;
+        for (mount, distance) in ultrasonic.sensors.iter().zip(&ultrasonic.distances) {
+            let Some(distance) = *distance else {
+                continue;
+            };
+            // Arc across the beam at the echo distance
+            let pose = mount.world_pose(state);
+            let arc = (0..=SEGMENTS).map(|i| {
+                let angle = -half_fov + 2.0 * half_fov * i as f32 / SEGMENTS as f32;
+                pose.transform_point((Vec2::from_angle(angle) * distance).extend(0.0))
+            });
+            gizmos.linestrip(arc, proximity_color(distance));
+        }
+    }
+}
+
+/// Distances saved as CSV, one column per sensor and empty with nothing in range, with
+/// `--record-ultrasonic <file>`.
+#[cfg(not(target_arch = "wasm32"))]
+mod recording {
+    use bevy::prelude::*;
+    use std::fs::File;
+    use std::io::{BufWriter, Write};
+
+    use super::UltrasonicScan;
+    use crate::utils::launch_argument;
+
+    #[derive(Resource)]
+    pub struct UltrasonicRecorder {
+        file: BufWriter<File>,
+        header: bool,
+    }
+
+    pub fn setup_recorder(mut commands: Commands) {
+        let Some(path) = launch_argument("record-ultrasonic") else {
+            return;
+        };
+        match File::create(&path) {
+            Ok(file) => {
+                info!("Recording ultrasonic distances to {}", path);
+                commands.insert_resource(UltrasonicRecorder {
+                    file: BufWriter::new(file),
+                    header: false,
+                });
+            }
+            Err(e) => error!("Cannot record ultrasonic distances to {}: {}", path, e),
+        }
+    }
+
+    pub fn record_scans(
+        mut scans: MessageReader<UltrasonicScan>,
+        recorder: Option<ResMut<UltrasonicRecorder>>,
+    ) {
+        let Some(mut recorder) = recorder else {
+            scans.clear();
+            return;
+        };
+        for scan in scans.read() {
+            // The header follows the number of sensors in the first scan
+            if !recorder.header {
+                recorder.header = true;
+                let names: Vec<String> =
+                    (0..scan.distances.len()).map(|i| format!("d{i}")).collect();
+                if let Err(e) = writeln!(recorder.file, "time,{}", names.join(",")) {
+                    error!("Failed to record ultrasonic distances: {}", e);
+                    return;
+                }
+            }
+            let columns: Vec<String> = scan
+                .distances
+                .iter()
+                .map(|d| d.map_or(String::new(), |d| format!("{:.3}", d)))
+                .collect();
+            if let Err(e) = writeln!(recorder.file, "{:.3},{}", scan.time, columns.join(",")) {
+                error!("Failed to record ultrasonic distances: {}", e);
+                return;
+            }
+        }
+    }
+}
@@ -19,4 +19,65 @@ pub fn wrap(x: f32, low: f32, high: f32) -> f32 {
     let inv_range = 1.0 / range;
     let num_wraps = ((x - low) * inv_range).floor();
     return x - range * num_wraps;
-}
\ No newline at end of file
+}
+
+/// Small seedable random number generator (SplitMix64), for reproducible worlds.
+#[derive(Clone, Debug)]
+pub struct SplitMix64(u64);
+
+impl SplitMix64 {
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in [0, 1)
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    /// Uniform in [low, high)
+    pub fn range(&mut self, low: f32, high: f32) -> f32 {
+        low + (high - low) * self.next_f32()
+    }
+
+    /// Standard normal, by the Box-Muller transform
+    pub fn gaussian(&mut self) -> f32 {
+        let u1 = 1.0 - self.next_f32(); // (0, 1], keeps the log finite
+        let u2 = self.next_f32();
+        (-2.0 * u1.ln()).sqrt() * (2.0 * PI * u2).cos()
+    }
+}
+
+/// Value of `--<name> <value>` (or `--<name>=<value>`) on the command line, or of the
+/// `?<name>=<value>` page URL parameter on the web.
+pub fn launch_argument(name: &str) -> Option<String> {
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        let flag = format!("--{name}");
+        let args: Vec<String> = std::env::args().collect();
+        args.iter().enumerate().find_map(|(i, arg)| {
+            if *arg == flag {
+                args.get(i + 1).cloned()
+            } else {
+                arg.strip_prefix(&flag)?
+                    .strip_prefix('=')
+                    .map(str::to_string)
+            }
+        })
+    }
+    #[cfg(target_arch = "wasm32")]
+    {
+        let search = web_sys::window()?.location().search().ok()?;
+        web_sys::UrlSearchParams::new_with_str(&search)
+            .ok()?
+            .get(name)
+    }
+}
@@ -0,0 +1,378 @@
+use bevy::prelude::*;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::f32::consts::PI;
+
+use crate::car_dynamics::{
+    Car, EgoControl, EgoState, Gear, TRACK_WIDTH, WHEEL_RADIUS, WHEELBASE, bicycle_kinematic_model,
+};
+use crate::dbc::Dbc;
+use crate::faults::{FaultTarget, Faults};
+
+pub struct VehicleBusPlugin;
+
+impl Plugin for VehicleBusPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_message::<SignalFrame>()
+            .add_message::<CanFrame>()
+            .add_systems(Update, sample_signals.after(bicycle_kinematic_model));
+        #[cfg(not(target_arch = "wasm32"))]
+        app.add_systems(Startup, recording::setup_recorders)
+            .add_systems(Update, recording::record_frames.after(sample_signals));
+    }
+}
+
+// Resolutions of the production sensors
+const WHEEL_SPEED_RESOLUTION: f32 = 1.0 / 32.0; // km/h
+const STEERING_ANGLE_RESOLUTION: f32 = 0.1; // deg
+const STEERING_SPEED_RESOLUTION: f32 = 1.0; // deg/s
+const PEDAL_RESOLUTION: f32 = 0.4; // %
+const PULSE_COUNTER_MODULO: u64 = 1 << 16;
+
+/// Wheel speed sensors in the order of their signals.
+const WHEELS: [&str; 4] = ["FL", "FR", "RL", "RR"];
+
+/// Signals sampled together at one rate, like the sensors behind one CAN message.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SignalGroup {
+    /// `WheelSpeed{FL,FR,RL,RR}` in km/h and the wrapping tooth counters
+    /// `WheelPulses{FL,FR,RL,RR}`.
+    Wheels,
+    /// `SteeringWheelAngle` in degrees, positive to the left, and `SteeringWheelSpeed`
+    /// in deg/s.
+    Steering,
+    /// `ThrottlePosition` and `BrakePosition` in %, and `Gear` as 0 P, 1 R, 2 N, 3 D.
+    Pedals,
+}
+
+/// Chassis signals as the vehicle's ECUs see them: wheel speed sensors, the steering
+/// angle sensor and pedal positions, each group sampled at its own rate and quantised
+/// to the sensors' resolution.
+///
+/// With a `dbc` file, each sample is also encoded into the CAN messages carrying its
+/// signals.
+#[derive(Component, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct VehicleBus {
+    pub enabled: bool,
+    pub wheel_rate: f32,            // Hz
+    pub steering_rate: f32,         // Hz
+    pub pedal_rate: f32,            // Hz
+    pub pulses_per_revolution: u32, // teeth on the wheel speed sensor rings
+    /// Asset path of the DBC file describing the CAN messages.
+    pub dbc: Option<String>,
+    #[serde(skip)]
+    next_sample: [f32; 3], // s since startup, per group
+    #[serde(skip)]
+    distances: [f64; 4], // m rolled by each wheel
+    #[serde(skip)]
+    previous_steering: Option<(f32, f32)>, // s since startup and deg
+    #[serde(skip)]
+    dbc_handle: Option<(String, Handle<Dbc>)>,
+    /// Latest value of every signal.
+    #[serde(skip)]
+    pub values: HashMap<String, f64>,
+}
+
+impl Default for VehicleBus {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            wheel_rate: 50.0,
+            steering_rate: 100.0,
+            pedal_rate: 50.0,
+            pulses_per_revolution: 48,
+            dbc: Some("can/vehicle.dbc".to_string()),
+            next_sample: [0.0; 3],
+            distances: [0.0; 4],
+            previous_steering: None,
+            dbc_handle: None,
+            values: HashMap::new(),
+        }
+    }
+}
+
+impl VehicleBus {
+    /// Vehicle bus fitted to the car but switched off.
+    pub fn disabled() -> Self {
+        Self {
+            enabled: false,
+            ..default()
+        }
+    }
+
+    pub fn value(&self, name: &str) -> Option<f64> {
+        self.values.get(name).copied()
+    }
+}
+
+/// One sample of a signal group.
+#[derive(Message, Clone, Debug)]
+pub struct SignalFrame {
+    #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+    pub time: f32, // s since startup
+    #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+    pub group: SignalGroup,
+    pub signals: Vec<(String, f64)>,
+}
+
+/// One CAN frame encoded with the DBC file.
+#[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+#[derive(Message, Clone, Debug)]
+pub struct CanFrame {
+    pub time: f32, // s since startup
+    pub id: u32,
+    pub extended: bool,
+    pub data: Vec<u8>,
+}
+
+fn quantize(value: f32, resolution: f32) -> f64 {
+    ((value / resolution).round() * resolution) as f64
+}
+
+/// Speed of each wheel's centre in m/s, from the rear axle speed and the yaw rate of
+/// the bicycle model.
+fn wheel_speeds(state: &EgoState, control: &EgoControl) -> [f32; 4] {
+    let yaw_rate = state.v * control.front_wheel_angle.tan() / WHEELBASE;
+    let (inner, outer) = (
+        state.v - yaw_rate * TRACK_WIDTH / 2.0,
+        state.v + yaw_rate * TRACK_WIDTH / 2.0,
+    );
+    let lateral = yaw_rate * WHEELBASE;
+    [
+        inner.hypot(lateral),
+        outer.hypot(lateral),
+        inner.abs(),
+        outer.abs(),
+    ]
+}
+
+fn sample_signals(
+    mut buses: Query<(&mut VehicleBus, &EgoState, &EgoControl), With<Car>>,
+    mut signal_frames: MessageWriter<SignalFrame>,
+    mut can_frames: MessageWriter<CanFrame>,
+    asset_server: Res<AssetServer>,
+    databases: Res<Assets<Dbc>>,
+    mut faults: ResMut<Faults>,
+    time: Res<Time>,
+) {
+    let now = time.elapsed_secs();
+    let dt = time.delta_secs();
+    for (mut bus, state, control) in &mut buses {
+        if !bus.enabled {
+            bus.previous_steering = None;
+            continue;
+        }
+        let speeds = wheel_speeds(state, control);
+        for (distance, speed) in bus.distances.iter_mut().zip(speeds) {
+            *distance += (speed * dt) as f64;
+        }
+        match (&bus.dbc, &bus.dbc_handle) {
+            (Some(path), Some((loaded, _))) if path == loaded => {}
+            (Some(path), _) => bus.dbc_handle = Some((path.clone(), asset_server.load(path))),
+            (None, _) => bus.dbc_handle = None,
+        }
+
+        let groups = [
+            (SignalGroup::Wheels, bus.wheel_rate),
+            (SignalGroup::Steering, bus.steering_rate),
+            (SignalGroup::Pedals, bus.pedal_rate),
+        ];
+        for (i, (group, rate)) in groups.into_iter().enumerate() {
+            if now < bus.next_sample[i] {
+                continue;
+            }
+            bus.next_sample[i] = now + 1.0 / rate.max(0.1);
+            let signals = match group {
+                SignalGroup::Wheels => {
+                    let circumference = 2.0 * PI as f64 * WHEEL_RADIUS as f64;
+                    let pulses = bus.distances.map(|distance| {
+                        let teeth =
+                            (distance / circumference * bus.pulses_per_revolution as f64) as u64;
+                        (teeth % PULSE_COUNTER_MODULO) as f64
+                    });
+                    let speeds = speeds.map(|v| quantize(v * 3.6, WHEEL_SPEED_RESOLUTION));
+                    let speeds = WHEELS
+                        .iter()
+                        .zip(speeds)
+                        .map(|(wheel, speed)| (format!("WheelSpeed{wheel}"), speed));
+                    let pulses = WHEELS
+                        .iter()
+                        .zip(pulses)
+                        .map(|(wheel, count)| (format!("WheelPulses{wheel}"), count));
+                    speeds.chain(pulses).collect()
+                }
+                SignalGroup::Steering => {
+                    let angle = control.steer_wheel_angle.to_degrees();
+                    let speed = match bus.previous_steering {
+                        Some((then, previous)) if now > then => (angle - previous) / (now - then),
+                        _ => 0.0,
+                    };
+                    bus.previous_steering = Some((now, angle));
+                    vec![
+                        (
+                            "SteeringWheelAngle".to_string(),
+                            quantize(angle, STEERING_ANGLE_RESOLUTION),
+                        ),
+                        (
+                            "SteeringWheelSpeed".to_string(),
+                            quantize(speed, STEERING_SPEED_RESOLUTION),
+                        ),
+                    ]
+                }
+                SignalGroup::Pedals => {
+                    let gear = match control.gear {
+                        Gear::Reverse => 1.0,
+                        Gear::Drive => 3.0,
+                    };
+                    vec![
+                        (
+                            "ThrottlePosition".to_string(),
+                            quantize(control.throttle * 100.0, PEDAL_RESOLUTION),
+                        ),
+                        (
+                            "BrakePosition".to_string(),
+                            quantize(control.brake * 100.0, PEDAL_RESOLUTION),
+                        ),
+                        ("Gear".to_string(), gear),
+                    ]
+                }
+            };
+            let frame = SignalFrame {
+                time: now,
+                group,
+                signals,
+            };
+            // Counters and the gear only ever read whole values
+            let corrupt = |frame: &mut SignalFrame, error: &mut dyn FnMut() -> f32| {
+                for (name, value) in &mut frame.signals {
+                    if name != "Gear" && !name.starts_with("WheelPulses") {
+                        *value += error() as f64;
+                    }
+                }
+            };
+            let Some(frame) =
+                faults.apply_stream(FaultTarget::VehicleBus, i as u32, frame, now, corrupt)
+            else {
+                continue;
+            };
+            for (name, value) in &frame.signals {
+                bus.values.insert(name.clone(), *value);
+            }
+
+            // Every message carrying one of the group's signals is sent with it
+            let database = bus
+                .dbc_handle
+                .as_ref()
+                .and_then(|(_, handle)| databases.get(handle));
+            for message in database.iter().flat_map(|dbc| &dbc.messages) {
+                if !message
+                    .signals
+                    .iter()
+                    .any(|signal| frame.signals.iter().any(|(name, _)| *name == signal.name))
+                {
+                    continue;
+                }
+                can_frames.write(CanFrame {
+                    time: now,
+                    id: message.id,
+                    extended: message.extended,
+                    data: message.encode(|name| bus.value(name)),
+                });
+            }
+            signal_frames.write(frame);
+        }
+    }
+}
+
+/// Signals saved as CSV with `--record-signals <file>`, and CAN frames as a candump log
+/// with `--record-can <file>`.
+#[cfg(not(target_arch = "wasm32"))]
+mod recording {
+    use bevy::prelude::*;
+    use std::fs::File;
+    use std::io::{BufWriter, Write};
+
+    use super::{CanFrame, SignalFrame};
+    use crate::utils::launch_argument;
+
+    #[derive(Resource)]
+    pub struct SignalRecorder(BufWriter<File>);
+
+    #[derive(Resource)]
+    pub struct CanRecorder(BufWriter<File>);
+
+    fn create(flag: &str, what: &str, header: Option<&str>) -> Option<BufWriter<File>> {
+        let path = launch_argument(flag)?;
+        let mut file = match File::create(&path) {
+            Ok(file) => BufWriter::new(file),
+            Err(e) => {
+                error!("Cannot record {} to {}: {}", what, path, e);
+                return None;
+            }
+        };
+        if let Some(header) = header
+            && let Err(e) = writeln!(file, "{}", header)
+        {
+            error!("Failed to write {}: {}", path, e);
+            return None;
+        }
+        info!("Recording {} to {}", what, path);
+        Some(file)
+    }
+
+    pub fn setup_recorders(mut commands: Commands) {
+        if let Some(file) = create(
+            "record-signals",
+            "vehicle signals",
+            Some("time,group,signal,value"),
+        ) {
+            commands.insert_resource(SignalRecorder(file));
+        }
+        if let Some(file) = create("record-can", "CAN frames", None) {
+            commands.insert_resource(CanRecorder(file));
+        }
+    }
+
+    pub fn record_frames(
+        mut signal_frames: MessageReader<SignalFrame>,
+        mut can_frames: MessageReader<CanFrame>,
+        signal_recorder: Option<ResMut<SignalRecorder>>,
+        can_recorder: Option<ResMut<CanRecorder>>,
+    ) {
+        if let Some(mut recorder) = signal_recorder {
+            for frame in signal_frames.read() {
+                for (name, value) in &frame.signals {
+                    let row = writeln!(
+                        recorder.0,
+                        "{:.4},{:?},{},{}",
+                        frame.time, frame.group, name, value
+                    );
+                    if let Err(e) = row {
+                        error!("Failed to record vehicle signals: {}", e);
+                        return;
+                    }
+                }
+            }
+        } else {
+            signal_frames.clear();
+        }
+        let Some(mut recorder) = can_recorder else {
+            can_frames.clear();
+            return;
+        };
+        // candump -l format, as replayed by canplayer
+        for frame in can_frames.read() {
+            let id = if frame.extended {
+                format!("{:08X}", frame.id)
+            } else {
+                format!("{:03X}", frame.id)
+            };
+            let data: String = frame.data.iter().map(|b| format!("{:02X}", b)).collect();
+            if let Err(e) = writeln!(recorder.0, "({:.6}) can0 {}#{}", frame.time, id, data) {
+                error!("Failed to record CAN frames: {}", e);
+                return;
+            }
+        }
+    }
+}